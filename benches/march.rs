@@ -0,0 +1,127 @@
+// Compare the scalar and SIMD builds with
+//   cargo bench --bench march -- --save-baseline scalar
+//   cargo bench --bench march --features simd -- --baseline scalar
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+
+use sdf_rs::march::{cast_field, cast_packet, cast_ray, cast_scene_packet, MarchSettings, Stepping};
+use sdf_rs::matrix::Mat4;
+use sdf_rs::node::{Cuboid, Union};
+use sdf_rs::transform::Transform;
+use sdf_rs::point::{Point3, Vector3};
+use sdf_rs::real::Float;
+use sdf_rs::vector::Vec4;
+use sdf_rs::Objects;
+
+const WIDTH: i32 = 64;
+const HEIGHT: i32 = 36;
+
+// View rays for a low resolution render of the demo scene
+fn rays(eye: &Point3) -> Vec<Vector3> {
+    let camera: Mat4 = Mat4::look(&(*eye).into(), &Vec4::position(0.0, 0.0, 0.0));
+    let mut rays = Vec::new();
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            let d = Vector3::new(
+                (2 * x - WIDTH) as Float / HEIGHT as Float,
+                (2 * y - HEIGHT) as Float / HEIGHT as Float,
+                2.5,
+            );
+            rays.push(&camera * &d.normalized());
+        }
+    }
+    rays
+}
+
+fn march(c: &mut Criterion) {
+    let eye = Point3::new(5.0, 5.0, -10.0);
+    let rays = rays(&eye);
+    let settings = MarchSettings::default();
+
+    let mut group = c.benchmark_group("cast_ray");
+    group.throughput(Throughput::Elements(rays.len() as u64));
+    group.bench_function("scene", |b| {
+        b.iter(|| {
+            for d in &rays {
+                black_box(cast_ray(black_box(&eye), d, &settings));
+            }
+        })
+    });
+    group.bench_function("packet4", |b| b.iter(|| packets::<4>(&eye, &rays, &settings)));
+    group.bench_function("packet8", |b| b.iter(|| packets::<8>(&eye, &rays, &settings)));
+    group.bench_function("packet16", |b| b.iter(|| packets::<16>(&eye, &rays, &settings)));
+    for stepping in [Stepping::Sphere, Stepping::OverRelaxed(1.2), Stepping::OverRelaxed(1.6), Stepping::Scaled(0.5)] {
+        let settings = MarchSettings { stepping, ..settings };
+        group.bench_function(format!("{:?}", stepping), |b| {
+            b.iter(|| {
+                for d in &rays {
+                    black_box(cast_ray(black_box(&eye), d, &settings));
+                }
+            })
+        });
+    }
+    group.finish();
+
+    // The part of the scene packets speed up, without the ground's per-ray
+    // terrain march
+    let mut group = c.benchmark_group("objects");
+    group.throughput(Throughput::Elements(rays.len() as u64));
+    group.bench_function("scalar", |b| {
+        b.iter(|| {
+            for d in &rays {
+                black_box(cast_field(&Objects, black_box(&eye), d, &settings));
+            }
+        })
+    });
+    group.bench_function("packet4", |b| b.iter(|| object_packets::<4>(&eye, &rays, &settings)));
+    group.bench_function("packet8", |b| b.iter(|| object_packets::<8>(&eye, &rays, &settings)));
+    group.bench_function("packet16", |b| b.iter(|| object_packets::<16>(&eye, &rays, &settings)));
+    group.finish();
+
+    // Boxes turned by matrices, so every sample goes through a Mat4 * Vec4
+    let turned = |angle: Float, x: Float| {
+        let m: Mat4 = &Mat4::translation(&Vec4::direction(x, 0.0, 0.0)) * &Mat4::rotation(&Vec4::direction(1.0, 1.0, 0.0).normalized(), angle);
+        Transform::new(Cuboid { dimensions: Vector3::new(0.8, 0.8, 0.8) }, &m).unwrap()
+    };
+    let boxes = Union(Union(turned(0.3, -3.0), turned(0.9, -1.0)), Union(turned(1.5, 1.0), turned(2.1, 3.0)));
+    c.bench_function("cast_field/transformed", |b| {
+        b.iter(|| {
+            for d in &rays {
+                black_box(cast_field(&boxes, black_box(&eye), d, &settings));
+            }
+        })
+    });
+}
+
+// The ray count is a multiple of every packet size, so no padding is needed
+fn packets<const N: usize>(eye: &Point3, rays: &[Vector3], settings: &MarchSettings) {
+    let origins = [*eye; N];
+    for chunk in rays.chunks_exact(N) {
+        let chunk: &[Vector3; N] = chunk.try_into().unwrap();
+        black_box(cast_scene_packet(black_box(&origins), chunk, settings));
+    }
+}
+
+// The same, for the objects alone
+fn object_packets<const N: usize>(eye: &Point3, rays: &[Vector3], settings: &MarchSettings) {
+    let origins = [*eye; N];
+    for chunk in rays.chunks_exact(N) {
+        let chunk: &[Vector3; N] = chunk.try_into().unwrap();
+        black_box(cast_packet(&Objects, black_box(&origins), chunk, settings));
+    }
+}
+
+fn maths(c: &mut Criterion) {
+    let eye = Vec4::position(5.0, 5.0, -10.0);
+    let m: Mat4 = Mat4::look(&eye, &Vec4::position(0.0, 0.0, 0.0));
+    let n: Mat4 = Mat4::translation(&Vec4::direction(1.0, 2.0, 3.0));
+    let v = Vec4::direction(0.3, -0.2, 0.9);
+
+    c.bench_function("mat4 * vec4", |b| b.iter(|| black_box(&m) * black_box(&v)));
+    c.bench_function("mat4 * mat4", |b| b.iter(|| black_box(&m) * black_box(&n)));
+    c.bench_function("vec4 + vec4", |b| b.iter(|| black_box(v) + black_box(eye)));
+    c.bench_function("vec4 dot", |b| b.iter(|| black_box(v).dot_product(black_box(&eye))));
+}
+
+criterion_group!(benches, march, maths);
+criterion_main!(benches);
@@ -0,0 +1,22 @@
+// Whole frames of the demo scene. Compare precisions with
+//   cargo bench --bench render -- --save-baseline f64
+//   cargo bench --bench render --features f32 -- --baseline f64
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+
+use sdf_rs::options::Options;
+use sdf_rs::render;
+
+fn frames(c: &mut Criterion) {
+    let options = Options { width: 480, height: 270, ..Default::default() };
+    let packets = Options { width: 480, height: 270, packet: Some(8), ..Default::default() };
+
+    let mut group = c.benchmark_group("render");
+    group.sample_size(10);
+    group.bench_function("480x270", |b| b.iter(|| render(black_box(&options))));
+    group.bench_function("480x270 packet8", |b| b.iter(|| render(black_box(&packets))));
+    group.finish();
+}
+
+criterion_group!(benches, frames);
+criterion_main!(benches);
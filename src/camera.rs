@@ -0,0 +1,91 @@
+use crate::matrix::Mat4;
+use crate::point::{Point3, Vector3};
+use crate::quat::Quat;
+use crate::real::Float;
+
+// A camera circling a target at a fixed distance, for turntables and smooth
+// camera paths. The eye is the starting offset from the target, rotated.
+#[derive(Debug, Copy, Clone)]
+pub struct Orbit
+{
+    pub target: Point3,
+    pub offset: Vector3,
+    pub rotation: Quat,
+}
+
+impl Orbit
+{
+    pub fn new(eye: &Point3, target: &Point3) -> Self {
+        Orbit {
+            target: *target,
+            offset: *eye - *target,
+            rotation: Quat::identity(),
+        }
+    }
+
+    pub fn eye(&self) -> Point3 {
+        self.target + self.rotation.rotate(&self.offset)
+    }
+
+    pub fn camera(&self) -> Mat4 {
+        Mat4::look(&self.eye().into(), &self.target.into())
+    }
+
+    // Turned by `angle` radians about the vertical through the target
+    pub fn turn(&self, angle: Float) -> Orbit {
+        Orbit {
+            rotation: Quat::from_axis_angle(&Vector3::new(0.0, 1.0, 0.0), angle) * self.rotation,
+            ..*self
+        }
+    }
+
+    // Part way round to another orbit of the same target and offset, at a
+    // constant angular speed
+    pub fn towards(&self, other: &Orbit, t: Float) -> Orbit {
+        Orbit {
+            rotation: self.rotation.slerp(&other.rotation, t),
+            ..*self
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QUARTER: Float = std::f64::consts::FRAC_PI_2 as Float;
+
+    fn near(a: Point3, b: Point3) -> bool {
+        (a - b).mag() < 1e-5
+    }
+
+    #[test]
+    fn turntable() {
+        let orbit = Orbit::new(&Point3::new(0.0, 2.0, -10.0), &Point3::new(0.0, 2.0, 0.0));
+        assert_eq!(Point3::new(0.0, 2.0, -10.0), orbit.eye());
+        assert!(near(Point3::new(-10.0, 2.0, 0.0), orbit.turn(QUARTER).eye()));
+        assert!(near(Point3::new(0.0, 2.0, 10.0), orbit.turn(QUARTER).turn(QUARTER).eye()));
+
+        // The camera keeps looking at the target
+        let forward = &orbit.turn(0.7).camera() * &Vector3::new(0.0, 0.0, 1.0);
+        let to_target = (orbit.target - orbit.turn(0.7).eye()).normalized();
+        assert!((forward - to_target).mag() < 1e-5);
+    }
+
+    #[test]
+    fn paths() {
+        let orbit = Orbit::new(&Point3::new(3.0, 0.0, 0.0), &Point3::origin());
+        // Short of a half turn, where the shorter way round is ambiguous
+        let end = orbit.turn(QUARTER * 1.5);
+
+        assert!(near(orbit.eye(), orbit.towards(&end, 0.0).eye()));
+        assert!(near(end.eye(), orbit.towards(&end, 1.0).eye()));
+        assert!(near(orbit.turn(QUARTER * 0.75).eye(), orbit.towards(&end, 0.5).eye()));
+
+        // Stays on the sphere all the way round
+        for i in 0..10 {
+            let eye = orbit.towards(&end, i as Float / 10.0).eye();
+            assert!(Float::abs(eye.to_vector().mag() - 3.0) < 1e-5);
+        }
+    }
+}
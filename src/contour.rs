@@ -0,0 +1,671 @@
+// Dual meshing: one vertex per cell the surface passes through, rather than
+// one per crossed edge as in marching cubes, joined into a quad across every
+// crossed edge. With the vertex at the average of where the surface crosses
+// the cell's edges that's naive surface nets, which is smooth everywhere.
+// Dual contouring also takes the field's gradient at each crossing, the
+// tangent plane there, and puts the vertex where it best fits all the planes
+// (solving a quadratic error function, or QEF), which lands it on the edge or
+// corner where the planes meet, so sharp features stay sharp.
+//
+// Cells live in an octree over the grid. Branches far enough from the surface
+// for the distance to rule it out are never split, so empty space costs next
+// to nothing, and where a group of eight cells' planes are fitted well enough
+// by one vertex they're merged, so flat areas take few triangles. Following
+// Ju et al., "Dual Contouring of Hermite Data" (2002), a merge also has to
+// leave the sign of the field unchanged at the points it drops, and the
+// merged cell has to hold a single piece of surface.
+
+use crate::mesh::{corner_offset, edge_corners, loops, other_axes, Grid, Mesh};
+use crate::normal::{calc_normal, NormalMethod};
+use crate::point::{Point3, Vector3};
+use crate::real::Float;
+use crate::DistanceField;
+
+// Regula falsi steps refining where the surface crosses a cell edge
+const CROSSING_STEPS: u32 = 8;
+// Directions the planes barely constrain, with less than this fraction of
+// the strongest, are left at the average of the crossings
+const SINGULAR: Float = 0.1;
+
+// The planes through each crossing, summed up as AᵀA, Aᵀb and bᵀb so cells can
+// be merged by adding them
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Qef
+{
+    ata: [[Float; 3]; 3],
+    atb: Vector3,
+    btb: Float,
+    sum: Vector3,
+    count: u32,
+}
+
+impl Default for Qef
+{
+    fn default() -> Self {
+        Qef { ata: [[0.0; 3]; 3], atb: Vector3::zero(), btb: 0.0, sum: Vector3::zero(), count: 0 }
+    }
+}
+
+impl Qef
+{
+    // The plane through `p` with unit `normal`
+    pub fn add(&mut self, p: &Point3, normal: &Vector3) {
+        let n = [normal.x, normal.y, normal.z];
+        for (i, row) in self.ata.iter_mut().enumerate() {
+            for (j, a) in row.iter_mut().enumerate() {
+                *a += n[i] * n[j];
+            }
+        }
+        let b = normal.dot_product(&p.to_vector());
+        self.atb += normal.scale(b);
+        self.btb += b * b;
+        self.sum += p.to_vector();
+        self.count += 1;
+    }
+
+    pub fn merge(&mut self, other: &Qef) {
+        for i in 0..3 {
+            for j in 0..3 {
+                self.ata[i][j] += other.ata[i][j];
+            }
+        }
+        self.atb += other.atb;
+        self.btb += other.btb;
+        self.sum += other.sum;
+        self.count += other.count;
+    }
+
+    // Average of the points the planes went through
+    pub fn mass_point(&self) -> Point3 {
+        let m = self.sum.scale(1.0 / self.count.max(1) as Float);
+        Point3::new(m.x, m.y, m.z)
+    }
+
+    fn ata_times(&self, v: &Vector3) -> Vector3 {
+        let row = |r: &[Float; 3]| r[0] * v.x + r[1] * v.y + r[2] * v.z;
+        Vector3::new(row(&self.ata[0]), row(&self.ata[1]), row(&self.ata[2]))
+    }
+
+    // Sum of squared distances from `p` to the planes
+    pub fn error(&self, p: &Point3) -> Float {
+        let x = p.to_vector();
+        (x.dot_product(&self.ata_times(&x)) - 2.0 * x.dot_product(&self.atb) + self.btb).max(0.0)
+    }
+
+    // The point closest to all the planes, measured from the mass point so
+    // that directions they leave free stay there
+    pub fn solve(&self) -> Point3 {
+        let mass = self.mass_point();
+        let residual = self.atb - self.ata_times(&mass.to_vector());
+        let (values, vectors) = eigen(self.ata);
+        let largest = values.iter().cloned().fold(0.0, Float::max);
+
+        let mut offset = Vector3::zero();
+        for (k, &value) in values.iter().enumerate() {
+            if value > SINGULAR * largest && value > 0.0 {
+                let v = Vector3::new(vectors[0][k], vectors[1][k], vectors[2][k]);
+                offset += v.scale(v.dot_product(&residual) / value);
+            }
+        }
+        mass + offset
+    }
+}
+
+// Eigenvalues of a symmetric matrix, and the eigenvectors as the columns of
+// the other, by Jacobi rotations
+fn eigen(mut a: [[Float; 3]; 3]) -> ([Float; 3], [[Float; 3]; 3]) {
+    let mut v = [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]];
+    for _ in 0..32 {
+        let off = a[0][1] * a[0][1] + a[0][2] * a[0][2] + a[1][2] * a[1][2];
+        let scale = a[0][0] * a[0][0] + a[1][1] * a[1][1] + a[2][2] * a[2][2];
+        if off <= Float::EPSILON * Float::EPSILON * scale {
+            break;
+        }
+        for (p, q) in [(0, 1), (0, 2), (1, 2)] {
+            if a[p][q] == 0.0 {
+                continue;
+            }
+            let theta = (a[q][q] - a[p][p]) / (2.0 * a[p][q]);
+            let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+            let c = 1.0 / (t * t + 1.0).sqrt();
+            let s = t * c;
+            for row in a.iter_mut() {
+                let (kp, kq) = (row[p], row[q]);
+                row[p] = c * kp - s * kq;
+                row[q] = s * kp + c * kq;
+            }
+            let (row_p, row_q) = (a[p], a[q]);
+            for (k, (pk, qk)) in row_p.iter().zip(&row_q).enumerate() {
+                a[p][k] = c * pk - s * qk;
+                a[q][k] = s * pk + c * qk;
+            }
+            for row in v.iter_mut() {
+                let (kp, kq) = (row[p], row[q]);
+                row[p] = c * kp - s * kq;
+                row[q] = s * kp + c * kq;
+            }
+        }
+    }
+    ([a[0][0], a[1][1], a[2][2]], v)
+}
+
+#[derive(Debug, Clone)]
+struct Leaf
+{
+    // Cubes of the grid to a side, as a power of two
+    level: u32,
+    // Corners inside the surface, numbered as in the mesh module
+    inside: u8,
+    qef: Qef,
+    vertex: Point3,
+    index: u32,
+}
+
+#[derive(Debug, Clone)]
+enum Node
+{
+    // No surface anywhere in the cell, which is all inside or all outside
+    Empty(bool),
+    Leaf(Leaf),
+    Branch(Box<[Node; 8]>),
+}
+
+impl Node
+{
+    // Only for the corners of cells that aren't split
+    fn corner_inside(&self, corner: usize) -> bool {
+        match self {
+            Node::Empty(inside) => *inside,
+            Node::Leaf(leaf) => leaf.inside & (1 << corner) != 0,
+            Node::Branch(_) => unreachable!(),
+        }
+    }
+
+    // A node's child, or for cells that aren't split the cell itself
+    fn child(&self, i: usize) -> &Node {
+        match self {
+            Node::Branch(children) => &children[i],
+            node => node,
+        }
+    }
+
+    fn is_branch(&self) -> bool {
+        matches!(self, Node::Branch(_))
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Placement
+{
+    MassPoint,
+    Qef,
+}
+
+struct Octree<'a, F>
+{
+    field: &'a F,
+    grid: &'a Grid,
+    cells: [usize; 3],
+    placement: Placement,
+    // RMS distance to the planes below which cells merge, if they ever do
+    tolerance: Option<Float>,
+}
+
+impl<'a, F: DistanceField> Octree<'a, F>
+{
+    fn point(&self, index: [usize; 3]) -> Point3 {
+        self.grid.point(index)
+    }
+
+    // Where the surface crosses between a and b, which are on opposite sides
+    fn crossing(&self, mut a: Point3, mut da: Float, mut b: Point3, mut db: Float) -> Point3 {
+        for _ in 0..CROSSING_STEPS {
+            let p = a + (b - a).scale(da / (da - db));
+            let d = self.field.distance(&p);
+            if d == 0.0 {
+                return p;
+            }
+            if (d < 0.0) == (da < 0.0) {
+                (a, da) = (p, d);
+            } else {
+                (b, db) = (p, d);
+            }
+        }
+        a + (b - a).scale(da / (da - db))
+    }
+
+    // The vertex for a cell between `min` and `min + size`
+    fn place(&self, qef: &Qef, min: &Point3, size: Float) -> Point3 {
+        match self.placement {
+            Placement::MassPoint => qef.mass_point(),
+            Placement::Qef => {
+                // Planes that are nearly parallel can meet far away; then the
+                // cell's own average will do
+                let p = qef.solve();
+                let margin = size * 1e-3;
+                let within = |v: Float, lo: Float| v >= lo - margin && v <= lo + size + margin;
+                if within(p.x, min.x) && within(p.y, min.y) && within(p.z, min.z) {
+                    p
+                } else {
+                    qef.mass_point()
+                }
+            }
+        }
+    }
+
+    // The cell `1 << level` grid cubes to a side from grid point `origin`
+    fn build(&self, origin: [usize; 3], level: u32) -> Node {
+        if (0..3).any(|i| origin[i] >= self.cells[i]) {
+            // Outside the grid's box
+            return Node::Empty(false);
+        }
+        let size = (1 << level) as Float * self.grid.cell_size();
+        let min = self.point(origin);
+        let centre = min + Vector3::splat(size / 2.0);
+        let d = self.field.distance(&centre);
+        if d.abs() > size * (3.0 as Float).sqrt() / 2.0 * self.field.lipschitz() {
+            return Node::Empty(d < 0.0);
+        }
+
+        if level == 0 {
+            return self.leaf(origin, &min, size);
+        }
+
+        let half = 1 << (level - 1);
+        let children: [Node; 8] = std::array::from_fn(|c| {
+            let offset = corner_offset(c);
+            self.build([0, 1, 2].map(|i| origin[i] + offset[i] * half), level - 1)
+        });
+        if let Node::Empty(inside) = children[0] {
+            if children.iter().all(|n| matches!(n, Node::Empty(i) if *i == inside)) {
+                return Node::Empty(inside);
+            }
+        }
+        // Cells reaching past the box stay split, so the surface stays open
+        // where it's cut off
+        let within = (0..3).all(|i| origin[i] + (1 << level) <= self.cells[i]);
+        match self.tolerance {
+            Some(tolerance) if within && !children.iter().any(Node::is_branch) => {
+                match self.merge(&children, level, &min, size, tolerance) {
+                    Some(leaf) => Node::Leaf(leaf),
+                    None => Node::Branch(Box::new(children)),
+                }
+            }
+            _ => Node::Branch(Box::new(children)),
+        }
+    }
+
+    // A cell of the grid, with the planes where the surface crosses its edges
+    fn leaf(&self, origin: [usize; 3], min: &Point3, size: Float) -> Node {
+        let corner = |c: usize| {
+            let offset = corner_offset(c);
+            self.point([0, 1, 2].map(|i| origin[i] + offset[i]))
+        };
+        let distances: [Float; 8] = std::array::from_fn(|c| self.field.distance(&corner(c)));
+        let inside = (0..8).filter(|&c| distances[c] < 0.0).fold(0u8, |m, c| m | (1 << c));
+        if inside == 0 || inside == 255 {
+            return Node::Empty(inside == 255);
+        }
+
+        let mut qef = Qef::default();
+        for edge in 0..12 {
+            let (a, b) = edge_corners(edge);
+            if (inside >> a & 1) != (inside >> b & 1) {
+                let p = self.crossing(corner(a), distances[a], corner(b), distances[b]);
+                qef.add(&p, &calc_normal(self.field, &p, 1.0, NormalMethod::AutoDiff));
+            }
+        }
+        let vertex = self.place(&qef, min, size);
+        Node::Leaf(Leaf { level: 0, inside, qef, vertex, index: 0 })
+    }
+
+    // One cell in place of eight that aren't split, if that keeps the shape
+    fn merge(&self, children: &[Node; 8], level: u32, min: &Point3, size: Float, tolerance: Float) -> Option<Leaf> {
+        // Signs on the 3 x 3 x 3 lattice of the children's corners
+        let sign = |lattice: [usize; 3]| {
+            let child = lattice.map(|v| (v == 2) as usize);
+            let corner = lattice.map(|v| (v != 0) as usize);
+            children[child[0] | child[1] << 1 | child[2] << 2].corner_inside(corner[0] | corner[1] << 1 | corner[2] << 2)
+        };
+        let inside = (0..8).filter(|&c| sign(corner_offset(c).map(|v| 2 * v))).fold(0u8, |m, c| m | (1 << c));
+
+        // Every edge midpoint, face centre and the centre has to match a
+        // corner of the edge, face or cell it's in, so no surface is lost
+        for x in 0..3 {
+            for y in 0..3 {
+                for z in 0..3 {
+                    let lattice = [x, y, z];
+                    if lattice.iter().all(|&v| v != 1) {
+                        continue;
+                    }
+                    let matches = (0..8).any(|c| {
+                        let corner = corner_offset(c).map(|v| 2 * v);
+                        (0..3).all(|i| lattice[i] == 1 || lattice[i] == corner[i]) && sign(corner) == sign(lattice)
+                    });
+                    if !matches {
+                        return None;
+                    }
+                }
+            }
+        }
+        if loops(inside).len() != 1 {
+            return None;
+        }
+
+        let mut qef = Qef::default();
+        for child in children {
+            if let Node::Leaf(leaf) = child {
+                qef.merge(&leaf.qef);
+            }
+        }
+        let vertex = self.place(&qef, min, size);
+        if (qef.error(&vertex) / qef.count as Float).sqrt() > tolerance {
+            return None;
+        }
+        Some(Leaf { level, inside, qef, vertex, index: 0 })
+    }
+
+    fn mesh(&self) -> Mesh {
+        let largest = self.cells.iter().cloned().max().unwrap_or(1);
+        let levels = largest.next_power_of_two().trailing_zeros();
+        let mut root = self.build([0, 0, 0], levels);
+
+        let mut mesh = Mesh::new();
+        self.number(&mut root, &mut mesh);
+        cell(&root, &mut mesh);
+        mesh
+    }
+
+    // Give every leaf its vertex in the mesh
+    fn number(&self, node: &mut Node, mesh: &mut Mesh) {
+        match node {
+            Node::Branch(children) => children.iter_mut().for_each(|child| self.number(child, mesh)),
+            Node::Leaf(leaf) => {
+                leaf.index = mesh.vertices.len() as u32;
+                mesh.vertices.push(leaf.vertex);
+                mesh.normals.push(calc_normal(self.field, &leaf.vertex, 1.0, NormalMethod::AutoDiff));
+            }
+            Node::Empty(_) => (),
+        }
+    }
+}
+
+// The rest follows Ju et al.: every edge where cells meet is visited once, by
+// walking the tree's cells, the faces between them and the edges between
+// those, down to the smallest cells on each side.
+
+fn cell(node: &Node, mesh: &mut Mesh) {
+    if let Node::Branch(children) = node {
+        for child in children.iter() {
+            cell(child, mesh);
+        }
+        for axis in 0..3 {
+            let (b, c) = other_axes(axis);
+            for p in 0..4 {
+                let low = (p & 1) << b | (p >> 1 & 1) << c;
+                face([&children[low], &children[low | 1 << axis]], axis, mesh);
+            }
+            for q in 0..2 {
+                let around = std::array::from_fn(|s| &children[q << axis | (s & 1) << b | (s >> 1 & 1) << c]);
+                edge(around, axis, mesh);
+            }
+        }
+    }
+}
+
+// Cells either side of a face across `axis`, the lower first
+fn face(nodes: [&Node; 2], axis: usize, mesh: &mut Mesh) {
+    if !nodes.iter().any(|n| n.is_branch()) {
+        return;
+    }
+    let (b, c) = other_axes(axis);
+    for p in 0..4 {
+        let bits = (p & 1) << b | (p >> 1 & 1) << c;
+        face([nodes[0].child(bits | 1 << axis), nodes[1].child(bits)], axis, mesh);
+    }
+    // The two edges across the middle of the face
+    for (along, across) in [(b, c), (c, b)] {
+        let (e1, _) = other_axes(along);
+        for q in 0..2 {
+            let around = std::array::from_fn(|s| {
+                let side = |other: usize| if other == e1 { s & 1 } else { s >> 1 & 1 };
+                let node = nodes[side(axis)];
+                node.child((1 - side(axis)) << axis | side(across) << across | q << along)
+            });
+            edge(around, along, mesh);
+        }
+    }
+}
+
+// The four cells round an edge along `axis`, slot s being on the high side
+// of the edge along the first other axis if bit 0 is set, and the second if
+// bit 1 is
+fn edge(nodes: [&Node; 4], axis: usize, mesh: &mut Mesh) {
+    if !nodes.iter().any(|n| n.is_branch()) {
+        quad(nodes, axis, mesh);
+        return;
+    }
+    let (b, c) = other_axes(axis);
+    for q in 0..2 {
+        let around = std::array::from_fn(|s| nodes[s].child(q << axis | (1 - (s & 1)) << b | (1 - (s >> 1 & 1)) << c));
+        edge(around, axis, mesh);
+    }
+}
+
+// Join the vertices round an edge, if the surface crosses it
+fn quad(nodes: [&Node; 4], axis: usize, mesh: &mut Mesh) {
+    let mut leaves = vec![];
+    for node in nodes {
+        match node {
+            Node::Leaf(leaf) => leaves.push(leaf),
+            _ => return,
+        }
+    }
+    // The edge as seen by the smallest cell, which is all of it
+    let (slot, smallest) = leaves.iter().enumerate().min_by_key(|(_, leaf)| leaf.level).unwrap();
+    let (low, high) = edge_corners(4 * axis + 3 - slot);
+    let low_inside = smallest.inside & (1 << low) != 0;
+    if low_inside == (smallest.inside & (1 << high) != 0) {
+        return;
+    }
+
+    // Counter-clockwise looking down the axis, which is out if the low end
+    // is inside
+    let mut ring = [0, 1, 3, 2].map(|s| leaves[s].index);
+    if !low_inside {
+        ring.reverse();
+    }
+    // Split along the shorter diagonal, which follows a crease if there is one
+    let at = |i: usize| mesh.vertices[ring[i] as usize];
+    let triangles = if (at(0) - at(2)).mag() <= (at(1) - at(3)).mag() {
+        [[ring[0], ring[1], ring[2]], [ring[0], ring[2], ring[3]]]
+    } else {
+        [[ring[0], ring[1], ring[3]], [ring[1], ring[2], ring[3]]]
+    };
+    for t in triangles {
+        // Two of the cells can be one larger one
+        if t[0] != t[1] && t[1] != t[2] && t[2] != t[0] {
+            mesh.triangles.push(t);
+        }
+    }
+}
+
+// Dual contouring of `field` inside `grid`. Cells merge where one vertex fits
+// their planes to within an RMS distance of `tolerance`: zero only merges
+// where the fit is exact, as on flat faces, and below zero they never do.
+pub fn dual_contour<F: DistanceField>(field: &F, grid: &Grid, tolerance: Float) -> Mesh {
+    let cells = grid.cells();
+    Octree { field, grid, cells, placement: Placement::Qef, tolerance: Some(tolerance) }.mesh()
+}
+
+// Naive surface nets: a vertex in every cell of the grid the surface passes
+// through, at the average of its crossings
+pub fn surface_nets<F: DistanceField>(field: &F, grid: &Grid) -> Mesh {
+    let cells = grid.cells();
+    Octree { field, grid, cells, placement: Placement::MassPoint, tolerance: None }.mesh()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mesh::marching_cubes;
+    use crate::node::Sphere;
+    use crate::real::Real;
+
+    const PI: Float = std::f64::consts::PI as Float;
+
+    fn near(a: Float, b: Float, tolerance: Float) -> bool {
+        Float::abs(a - b) < tolerance
+    }
+
+    // A box with truly sharp edges, unlike `Cuboid`
+    struct Block(Vector3);
+
+    impl DistanceField for Block
+    {
+        fn distance<T: Real>(&self, p: &Point3<T>) -> T {
+            let q = p.to_vector().abs() - self.0.lift();
+            q.x.max(q.y).max(q.z)
+        }
+    }
+
+    fn block() -> Block {
+        Block(Vector3::new(0.63, 0.41, 0.77))
+    }
+
+    fn corners(size: &Vector3) -> Vec<Point3> {
+        (0..8).map(|c| {
+            let [x, y, z] = corner_offset(c).map(|v| v as Float * 2.0 - 1.0);
+            Point3::new(x * size.x, y * size.y, z * size.z)
+        }).collect()
+    }
+
+    fn nearest(mesh: &Mesh, p: &Point3) -> Float {
+        mesh.vertices.iter().map(|v| (*v - *p).mag()).fold(Float::INFINITY, Float::min)
+    }
+
+    fn grid(resolution: u32) -> Grid {
+        Grid::new(&Point3::new(-1.0, -1.0, -1.0), &Point3::new(1.0, 1.0, 1.0), resolution)
+    }
+
+    #[test]
+    fn qef_corner() {
+        // Three planes meeting at a corner, sampled away from it
+        let mut qef = Qef::default();
+        qef.add(&Point3::new(1.0, 0.3, 0.2), &Vector3::new(1.0, 0.0, 0.0));
+        qef.add(&Point3::new(0.6, 2.0, 0.1), &Vector3::new(0.0, 1.0, 0.0));
+        qef.add(&Point3::new(0.5, 0.4, 3.0), &Vector3::new(0.0, 0.0, 1.0));
+        let p = qef.solve();
+        assert!((p - Point3::new(1.0, 2.0, 3.0)).mag() < 1e-4, "{:?}", p);
+        assert!(qef.error(&p) < 1e-6);
+        assert!(near(1.0, qef.error(&Point3::new(0.0, 2.0, 3.0)), 1e-4));
+    }
+
+    #[test]
+    fn qef_free_directions() {
+        // Two planes leave a line, and the solution stays level with the
+        // mass point along it
+        let mut qef = Qef::default();
+        let n = Vector3::new(1.0, 1.0, 0.0).normalized();
+        qef.add(&Point3::new(1.0, 0.0, 0.5), &n);
+        qef.add(&Point3::new(0.0, 1.0, 1.5), &Vector3::new(0.0, 0.0, 1.0));
+        let p = qef.solve();
+        assert!(near(1.0, p.x + p.y, 1e-4) && near(1.5, p.z, 1e-4), "{:?}", p);
+        assert!(near(0.5, p.x - p.y + 0.5, 1e-4), "{:?}", p);
+
+        // And with its planes merged in, another cell's corner
+        let mut other = Qef::default();
+        other.add(&Point3::new(2.0, 0.0, 0.0), &Vector3::new(1.0, 0.0, 0.0));
+        other.merge(&qef);
+        let p = other.solve();
+        assert!((p - Point3::new(2.0, -1.0, 1.5)).mag() < 1e-3, "{:?}", p);
+    }
+
+    #[test]
+    fn eigen_decomposes() {
+        let m = [[4.0, 1.0, 0.5], [1.0, 3.0, 0.2], [0.5, 0.2, 1.0]];
+        let (values, v) = eigen(m);
+        for k in 0..3 {
+            for i in 0..3 {
+                let mv: Float = (0..3).map(|j| m[i][j] * v[j][k]).sum();
+                assert!(near(mv, values[k] * v[i][k], 1e-4));
+            }
+        }
+        assert!(near(8.0, values.iter().sum(), 1e-4));
+    }
+
+    #[test]
+    fn keeps_sharp_corners() {
+        let size = block().0;
+        let contoured = dual_contour(&block(), &grid(16), 0.0);
+        let cubes = marching_cubes(&block(), &grid(16));
+        assert!(contoured.is_closed());
+        assert!(contoured.volume() > 0.0);
+        for corner in corners(&size) {
+            assert!(nearest(&contoured, &corner) < 1e-3, "{:?}", corner);
+            // Where marching cubes cuts them off
+            assert!(nearest(&cubes, &corner) > 0.02);
+        }
+        let exact = 8.0 * size.x * size.y * size.z;
+        assert!(near(exact, contoured.volume(), 1e-3), "{}", contoured.volume());
+        assert!(!near(exact, cubes.volume(), 1e-3));
+    }
+
+    #[test]
+    fn merges_flat_faces() {
+        let fine = dual_contour(&block(), &grid(32), -1.0);
+        let merged = dual_contour(&block(), &grid(32), 1e-4);
+        assert!(merged.is_closed());
+        assert!(merged.triangles.len() * 4 < fine.triangles.len(), "{} {}", merged.triangles.len(), fine.triangles.len());
+        for corner in corners(&block().0) {
+            assert!(nearest(&merged, &corner) < 1e-3, "{:?}", corner);
+        }
+        assert!(near(fine.volume(), merged.volume(), 1e-3));
+    }
+
+    #[test]
+    fn curved_surfaces() {
+        let ball = Sphere { radius: 0.8 };
+        for mesh in [dual_contour(&ball, &grid(24), 1e-3), surface_nets(&ball, &grid(24))] {
+            assert!(mesh.is_closed());
+            let edges = mesh.triangles.len() * 3 / 2;
+            assert_eq!(2, mesh.vertices.len() as i64 - edges as i64 + mesh.triangles.len() as i64);
+            assert!(near(4.0 / 3.0 * PI * 0.512, mesh.volume(), 0.05), "{}", mesh.volume());
+            for (p, n) in mesh.vertices.iter().zip(&mesh.normals) {
+                assert!(near(0.8, p.to_vector().mag(), 0.02), "{:?}", p);
+                assert!(near(1.0, n.dot_product(&p.to_vector().normalized()), 1e-3));
+            }
+        }
+    }
+
+    #[test]
+    fn surface_nets_round_corners() {
+        let nets = surface_nets(&block(), &grid(16));
+        assert!(nets.is_closed());
+        assert!(corners(&block().0).iter().all(|c| nearest(&nets, c) > 0.02));
+    }
+
+    #[test]
+    fn faces_out() {
+        // The block is convex round the origin, so out is away from it. (On
+        // the edges, vertex normals are one face's or the other's.)
+        let mesh = dual_contour(&block(), &grid(12), 1e-3);
+        for t in &mesh.triangles {
+            let [a, b, c] = t.map(|i| mesh.vertices[i as usize]);
+            let facing = (b - a).cross_product(&(c - a));
+            assert!(facing.dot_product(&(a.to_vector() + b.to_vector() + c.to_vector())) > 0.0);
+        }
+    }
+
+    #[test]
+    fn skips_empty_space() {
+        // A small ball in a big box, and a grid point on every unit
+        let grid = Grid::new(&Point3::new(-100.0, -100.0, -100.0), &Point3::new(100.0, 100.0, 100.0), 200);
+        let mesh = surface_nets(&Sphere { radius: 2.0 }, &grid);
+        assert!(mesh.is_closed());
+        assert!(mesh.vertices.iter().all(|p| near(2.0, p.to_vector().mag(), 0.3)));
+        // Cut by the sides of the box, it stays open
+        let grid = Grid::new(&Point3::new(0.0, -1.0, -1.0), &Point3::new(1.0, 1.0, 1.0), 16);
+        assert!(!dual_contour(&Sphere { radius: 0.8 }, &grid, 1e-3).is_closed());
+    }
+}
@@ -0,0 +1,182 @@
+use anyhow::{bail, Result};
+use image::Rgb;
+
+use crate::real::Float;
+use crate::point::{Point3, Vector3};
+use crate::march::{cast_ray, HitStatus};
+use crate::normal::calc_normal;
+use crate::options::Options;
+use crate::{sdf, Scene};
+
+// A plane through the scene, `normal . p = offset` with a unit normal
+#[derive(Debug, Clone, Copy)]
+pub struct SlicePlane
+{
+    pub normal: Vector3,
+    pub offset: Float,
+}
+
+impl Default for SlicePlane
+{
+    // The y = 0 plane
+    fn default() -> Self {
+        SlicePlane::new(&Vector3::new(0.0, 1.0, 0.0), 0.0)
+    }
+}
+
+impl SlicePlane
+{
+    pub fn new(normal: &Vector3, offset: Float) -> Self {
+        SlicePlane {
+            normal: normal.normalized(),
+            offset,
+        }
+    }
+
+    fn intersect(&self, position: &Point3, ray: &Vector3) -> Option<Point3> {
+        let denom = self.normal.dot_product(ray);
+        if Float::abs(denom) < 1e-9 {
+            return None;
+        }
+
+        let t = (self.offset - self.normal.dot_product(&position.to_vector())) / denom;
+        if t < 0.0 {
+            return None;
+        }
+
+        Some(*position + ray.scale(t))
+    }
+}
+
+#[derive(Debug)]
+pub enum DebugMode
+{
+    // Heatmap of march iterations per pixel
+    Steps,
+    // Closest approach to any surface for rays that missed
+    Distance,
+    // Surface normal as RGB
+    Normals,
+    // Distance field value on a plane, with isolines
+    Slice(SlicePlane),
+    // Normal shading, with rays that ran out of iterations in magenta,
+    // whatever the exhausted policy
+    Exhausted,
+}
+
+impl DebugMode
+{
+    pub fn parse(name: &str, slice: SlicePlane) -> Result<Self> {
+        Ok(match name {
+            "steps" => DebugMode::Steps,
+            "distance" => DebugMode::Distance,
+            "normals" => DebugMode::Normals,
+            "slice" => DebugMode::Slice(slice),
+            "exhausted" => DebugMode::Exhausted,
+            _ => bail!("Unknown debug mode {} (steps, distance, normals, slice, exhausted)", name),
+        })
+    }
+}
+
+pub fn shade(mode: &DebugMode, position: &Point3, view_ray: &Vector3, options: &Options) -> Rgb<u8> {
+    let settings = &options.march;
+
+    if let DebugMode::Slice(plane) = mode {
+        return match plane.intersect(position, view_ray) {
+            Some(p) => slice_colour(sdf(&p)),
+            None => Rgb([0, 0, 0]),
+        };
+    }
+
+    let result = cast_ray(position, view_ray, settings);
+    match mode {
+        DebugMode::Steps => heatmap(result.steps as Float / settings.max_steps as Float),
+        DebugMode::Distance => {
+            if result.is_hit(settings.exhausted) {
+                Rgb([32, 32, 32])
+            } else {
+                heatmap(Float::exp(-2.0 * result.min_dist))
+            }
+        }
+        DebugMode::Normals => {
+            if result.is_hit(settings.exhausted) {
+                let normal = calc_normal(&Scene, &result.position, result.t, options.normals);
+                to_rgb(
+                    normal.x * 0.5 + 0.5,
+                    normal.y * 0.5 + 0.5,
+                    normal.z * 0.5 + 0.5,
+                )
+            } else {
+                Rgb([0, 0, 0])
+            }
+        }
+        DebugMode::Exhausted => {
+            if result.status == HitStatus::Exhausted {
+                Rgb([255, 0, 255])
+            } else {
+                crate::shade(position, view_ray, options)
+            }
+        }
+        DebugMode::Slice(_) => unreachable!(),
+    }
+}
+
+// Blue (0) through green to red (1)
+fn heatmap(v: Float) -> Rgb<u8> {
+    let v = v.clamp(0.0, 1.0);
+    to_rgb(
+        (v * 2.0 - 1.0).clamp(0.0, 1.0),
+        1.0 - Float::abs(v * 2.0 - 1.0),
+        (1.0 - v * 2.0).clamp(0.0, 1.0),
+    )
+}
+
+// After iq's distance field visualisation: orange outside, blue inside,
+// banded every 0.25 units and with the zero isoline in white
+fn slice_colour(d: Float) -> Rgb<u8> {
+    let (r, g, b) = if d > 0.0 { (0.9, 0.6, 0.3) } else { (0.65, 0.85, 1.0) };
+    let band = (1.0 - Float::exp(-4.0 * Float::abs(d)))
+        * (0.8 + 0.2 * Float::cos(2.0 * std::f64::consts::PI as Float * d / 0.25));
+    let edge = 1.0 - (Float::abs(d) / 0.02).clamp(0.0, 1.0);
+
+    let mix = |c: Float| c * band * (1.0 - edge) + edge;
+    to_rgb(mix(r), mix(g), mix(b))
+}
+
+fn to_rgb(r: Float, g: Float, b: Float) -> Rgb<u8> {
+    let c = |v: Float| (v.clamp(0.0, 1.0) * 255.0) as u8;
+    Rgb([c(r), c(g), c(b)])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn heatmap_ends() {
+        assert_eq!(Rgb([0, 0, 255]), heatmap(0.0));
+        assert_eq!(Rgb([0, 255, 0]), heatmap(0.5));
+        assert_eq!(Rgb([255, 0, 0]), heatmap(1.0));
+        assert_eq!(Rgb([255, 0, 0]), heatmap(2.0));
+    }
+
+    #[test]
+    fn slice_zero_isoline() {
+        assert_eq!(Rgb([255, 255, 255]), slice_colour(0.0));
+        let Rgb([r, _, b]) = slice_colour(0.5);
+        assert!(r > b);
+        let Rgb([r, _, b]) = slice_colour(-0.5);
+        assert!(b > r);
+    }
+
+    #[test]
+    fn plane_intersection() {
+        let plane = SlicePlane::default();
+        let p = Point3::new(0.0, 5.0, 0.0);
+
+        let hit = plane.intersect(&p, &Vector3::new(0.0, -1.0, 0.0));
+        assert_eq!(Some(Point3::origin()), hit);
+        assert_eq!(None, plane.intersect(&p, &Vector3::new(0.0, 1.0, 0.0)));
+        assert_eq!(None, plane.intersect(&p, &Vector3::new(1.0, 0.0, 0.0)));
+    }
+}
@@ -0,0 +1,374 @@
+// Non-rigid deformations. Bending space stretches distances as well as
+// moving them, so a deformed field can report more than the true distance
+// to its surface. Each node works out a Lipschitz bound, how much faster than
+// 1 its distance can change, and `DistanceField::lipschitz` passes it up the
+// scene so marching can shorten its steps to match.
+//
+// The bounds for twist, bend and taper depend on how far the child reaches,
+// so those take an `extent`: the radius of a ball about the origin that holds
+// the child. They hold within that ball; further out distances are only
+// approximate, but there they're large enough that a march still closes in
+// safely.
+
+use crate::point::{Point3, Vector3};
+use crate::real::{Float, Real};
+use crate::DistanceField;
+
+// Twisted about the y axis by `rate` radians per unit of height
+#[derive(Debug, Copy, Clone)]
+pub struct Twist<N>
+{
+    node: N,
+    rate: Float,
+    extent: Float,
+}
+
+impl<N: DistanceField> Twist<N>
+{
+    pub fn new(node: N, rate: Float, extent: Float) -> Self {
+        Twist { node, rate, extent }
+    }
+}
+
+impl<N: DistanceField> DistanceField for Twist<N>
+{
+    fn distance<T: Real>(&self, p: &Point3<T>) -> T {
+        let a = p.y * T::from_float(self.rate);
+        let (s, c) = (a.sin(), a.cos());
+        self.node.distance(&Point3::new(c * p.x - s * p.z, p.y, s * p.x + c * p.z))
+    }
+
+    // The Jacobian is a rotation times a shear of rate * radius
+    fn lipschitz(&self) -> Float {
+        self.node.lipschitz() * (1.0 + Float::abs(self.rate) * self.extent)
+    }
+}
+
+// Bent in the xy plane, turning anticlockwise by `rate` radians per unit
+// along x, so a positive rate curls the ends up. The cheap version: a
+// rotation that varies along x rather than a true bend about a centre, so
+// it's only good for gentle curves.
+#[derive(Debug, Copy, Clone)]
+pub struct Bend<N>
+{
+    node: N,
+    rate: Float,
+    extent: Float,
+}
+
+impl<N: DistanceField> Bend<N>
+{
+    pub fn new(node: N, rate: Float, extent: Float) -> Self {
+        Bend { node, rate, extent }
+    }
+}
+
+impl<N: DistanceField> DistanceField for Bend<N>
+{
+    fn distance<T: Real>(&self, p: &Point3<T>) -> T {
+        let a = p.x * T::from_float(self.rate);
+        let (s, c) = (a.sin(), a.cos());
+        self.node.distance(&Point3::new(c * p.x + s * p.y, c * p.y - s * p.x, p.z))
+    }
+
+    fn lipschitz(&self) -> Float {
+        self.node.lipschitz() * (1.0 + Float::abs(self.rate) * self.extent)
+    }
+}
+
+// Cross sections across y scaled by 1 + rate * y, so a positive rate widens
+// it going up. The scale stops at `MIN_TAPER` rather than pinching through
+// zero.
+#[derive(Debug, Copy, Clone)]
+pub struct Taper<N>
+{
+    node: N,
+    rate: Float,
+    extent: Float,
+}
+
+const MIN_TAPER: Float = 0.1;
+
+impl<N: DistanceField> Taper<N>
+{
+    pub fn new(node: N, rate: Float, extent: Float) -> Self {
+        Taper { node, rate, extent }
+    }
+}
+
+impl<N: DistanceField> DistanceField for Taper<N>
+{
+    fn distance<T: Real>(&self, p: &Point3<T>) -> T {
+        let one = T::from_float(1.0);
+        let s = (one + p.y * T::from_float(self.rate)).max(T::from_float(MIN_TAPER));
+        // Scaled down where it's widened, so distances across y never grow
+        self.node.distance(&Point3::new(p.x / s, p.y, p.z / s)) * s.min(one)
+    }
+
+    // Across y the scale keeps the field 1-Lipschitz. Up it, the rate of
+    // change of the scale drags points in and out by up to rate * extent /
+    // s², and also multiplies the distance itself, which is at most about
+    // the extent again.
+    fn lipschitz(&self) -> Float {
+        let s = Float::max(1.0 - Float::abs(self.rate) * self.extent, MIN_TAPER);
+        self.node.lipschitz() * (1.0 + 2.0 * Float::abs(self.rate) * self.extent / (s * s))
+    }
+}
+
+// Stretched by inserting `2 * size` along each axis through the middle.
+// Exact outside; inside a stretched region the distance is only a bound.
+#[derive(Debug, Copy, Clone)]
+pub struct Elongate<N>
+{
+    node: N,
+    size: Vector3,
+}
+
+impl<N: DistanceField> Elongate<N>
+{
+    pub fn new(node: N, size: &Vector3) -> Self {
+        Elongate { node, size: *size }
+    }
+}
+
+impl<N: DistanceField> DistanceField for Elongate<N>
+{
+    fn distance<T: Real>(&self, p: &Point3<T>) -> T {
+        let clamp = |v: T, h: Float| v - v.max(T::from_float(-h)).min(T::from_float(h));
+        self.node.distance(&Point3::new(
+            clamp(p.x, self.size.x),
+            clamp(p.y, self.size.y),
+            clamp(p.z, self.size.z),
+        ))
+    }
+
+    fn lipschitz(&self) -> Float {
+        self.node.lipschitz()
+    }
+}
+
+// Grown outwards by `radius`, rounding off edges and corners
+#[derive(Debug, Copy, Clone)]
+pub struct Round<N>
+{
+    pub node: N,
+    pub radius: Float,
+}
+
+impl<N: DistanceField> DistanceField for Round<N>
+{
+    fn distance<T: Real>(&self, p: &Point3<T>) -> T {
+        self.node.distance(p) - T::from_float(self.radius)
+    }
+
+    fn lipschitz(&self) -> Float {
+        self.node.lipschitz()
+    }
+}
+
+// A hollow shell `thickness` either side of the surface
+#[derive(Debug, Copy, Clone)]
+pub struct Onion<N>
+{
+    pub node: N,
+    pub thickness: Float,
+}
+
+impl<N: DistanceField> DistanceField for Onion<N>
+{
+    fn distance<T: Real>(&self, p: &Point3<T>) -> T {
+        self.node.distance(p).abs() - T::from_float(self.thickness)
+    }
+
+    fn lipschitz(&self) -> Float {
+        self.node.lipschitz()
+    }
+}
+
+// A function added on to a distance to roughen the surface. Like a distance
+// field it's generic over the scalar, so displaced shapes still get exact
+// normals and run on packets.
+pub trait Displacement
+{
+    fn displacement<T: Real>(&self, p: &Point3<T>) -> T;
+
+    // Bound on the gradient of the displacement
+    fn lipschitz(&self) -> Float;
+}
+
+impl<D: Displacement> Displacement for &D
+{
+    fn displacement<T: Real>(&self, p: &Point3<T>) -> T {
+        (*self).displacement(p)
+    }
+
+    fn lipschitz(&self) -> Float {
+        (*self).lipschitz()
+    }
+}
+
+// amplitude * sin(fx) sin(fy) sin(fz), the classic bumpy sphere
+#[derive(Debug, Copy, Clone)]
+pub struct Ripples
+{
+    pub amplitude: Float,
+    pub frequency: Float,
+}
+
+impl Displacement for Ripples
+{
+    fn displacement<T: Real>(&self, p: &Point3<T>) -> T {
+        let f = T::from_float(self.frequency);
+        (p.x * f).sin() * (p.y * f).sin() * (p.z * f).sin() * T::from_float(self.amplitude)
+    }
+
+    fn lipschitz(&self) -> Float {
+        Float::abs(self.amplitude * self.frequency) * Float::sqrt(3.0)
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Displace<N, D>
+{
+    pub node: N,
+    pub displacement: D,
+}
+
+impl<N: DistanceField, D: Displacement> DistanceField for Displace<N, D>
+{
+    fn distance<T: Real>(&self, p: &Point3<T>) -> T {
+        self.node.distance(p) + self.displacement.displacement(p)
+    }
+
+    fn lipschitz(&self) -> Float {
+        self.node.lipschitz() + self.displacement.lipschitz()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::march::{cast_field, HitStatus, MarchSettings};
+    use crate::node::{Cuboid, Sphere};
+    use crate::testing::{assert_packet_and_gradient, scatter_points};
+
+    fn near(a: Float, b: Float) -> bool {
+        Float::abs(a - b) < 1e-5
+    }
+
+    fn post() -> Cuboid {
+        Cuboid { dimensions: Vector3::new(0.5, 2.0, 0.5) }
+    }
+
+    // The largest rate of change of `field` between nearby sample points, in
+    // a cube reaching r along each axis
+    fn steepest<F: DistanceField>(field: &F, r: Float) -> Float {
+        let mut steepest: Float = 0.0;
+        for p in scatter_points(300, r) {
+            for d in [Vector3::new(1e-3, 0.0, 0.0), Vector3::new(0.0, 1e-3, 0.0), Vector3::new(0.0, 0.0, 1e-3)] {
+                let dd = Float::abs(field.distance(&(p + d)) - field.distance(&p));
+                steepest = steepest.max(dd / d.mag());
+            }
+        }
+        steepest
+    }
+
+    #[test]
+    fn twist() {
+        // A quarter turn over the height of the post
+        let rate = std::f64::consts::FRAC_PI_4 as Float;
+        let t = post().twist(rate, 2.2);
+        assert!(near(post().distance(&Point3::new(1.0, 0.0, 0.0)), t.distance(&Point3::new(1.0, 0.0, 0.0))));
+        // At y = 2 it's turned an eighth of the way round, so a corner points along x
+        let corner = Point3::new(Float::sqrt(0.5), 1.0, 0.0);
+        assert!(t.distance(&Point3::new(corner.x - 0.01, 1.0, 0.0)) < post().distance(&Point3::new(corner.x - 0.01, 1.0, 0.0)));
+        assert!(t.lipschitz() > 1.0);
+        assert!(steepest(&t, 2.2) <= t.lipschitz());
+    }
+
+    #[test]
+    fn bend() {
+        let plank = Cuboid { dimensions: Vector3::new(2.0, 0.2, 0.5) };
+        let b = plank.bend(0.3, 2.0);
+        assert!(near(plank.distance(&Point3::new(0.0, 1.0, 0.0)), b.distance(&Point3::new(0.0, 1.0, 0.0))));
+        // The ends curl up
+        assert!(b.distance(&Point3::new(1.5, 0.7, 0.0)) < 0.0);
+        assert!(b.distance(&Point3::new(-1.5, 0.7, 0.0)) < 0.0);
+        assert!(plank.distance(&Point3::new(1.5, 0.7, 0.0)) > 0.0);
+        assert!(steepest(&b, 2.0) <= b.lipschitz());
+    }
+
+    #[test]
+    fn taper() {
+        let t = post().taper(-0.2, 2.2);
+        // Unchanged at y = 0, narrower at the top, wider at the bottom
+        assert!(near(post().distance(&Point3::new(0.8, 0.0, 0.0)), t.distance(&Point3::new(0.8, 0.0, 0.0))));
+        assert!(t.distance(&Point3::new(0.45, 1.5, 0.0)) > 0.0);
+        assert!(t.distance(&Point3::new(0.65, -1.5, 0.0)) < 0.0);
+        assert!(steepest(&t, 2.2) <= t.lipschitz());
+    }
+
+    #[test]
+    fn elongate() {
+        let e = Sphere { radius: 1.0 }.elongate(&Vector3::new(2.0, 0.0, 0.0));
+        assert!(near(0.0, e.distance(&Point3::new(3.0, 0.0, 0.0))));
+        assert!(near(0.0, e.distance(&Point3::new(1.5, 1.0, 0.0))));
+        assert!(near(1.0, e.distance(&Point3::new(-2.0, 0.0, 2.0))));
+        assert_eq!(1.0, e.lipschitz());
+    }
+
+    #[test]
+    fn round_and_onion() {
+        let b = Cuboid { dimensions: Vector3::new(1.0, 1.0, 1.0) };
+        let p = Point3::new(2.0, 0.0, 0.0);
+        assert!(near(b.distance(&p) - 0.25, b.rounded(0.25).distance(&p)));
+
+        let shell = Sphere { radius: 1.0 }.onion(0.1);
+        assert!(near(-0.1, shell.distance(&Point3::new(1.0, 0.0, 0.0))));
+        assert!(near(0.9, shell.distance(&Point3::origin())));
+        assert!(near(0.4, shell.distance(&Point3::new(0.0, 1.5, 0.0))));
+    }
+
+    #[test]
+    fn displace() {
+        let ripples = Ripples { amplitude: 0.2, frequency: 5.0 };
+        let d = Sphere { radius: 1.5 }.displace(ripples);
+        let p = Point3::new(0.3, 1.0, -0.2);
+        assert!(near(Sphere { radius: 1.5 }.distance(&p) + ripples.displacement(&p), d.distance(&p)));
+        assert!(near(Float::sqrt(3.0) + 1.0, d.lipschitz()));
+        assert!(steepest(&d, 2.0) <= d.lipschitz());
+    }
+
+    #[test]
+    fn bounds_compose() {
+        let t = post().twist(1.0, 2.0).rounded(0.1).union(Sphere { radius: 1.0 });
+        assert_eq!(3.0, t.lipschitz());
+        assert_eq!(3.0, (&t).union(&t).lipschitz());
+        assert_eq!(1.0, Sphere { radius: 1.0 }.transformed(&crate::matrix::Mat4::rotation_x(0.5)).unwrap().lipschitz());
+    }
+
+    #[test]
+    fn packets_and_gradients() {
+        let shape = post().twist(0.7, 2.2).taper(0.1, 2.2).displace(Ripples { amplitude: 0.05, frequency: 3.0 });
+        assert_packet_and_gradient(&shape);
+    }
+
+    #[test]
+    fn marching_compensates() {
+        // A strong twist, overestimating distances by a factor of up to 5
+        let shape = post().twist(2.0, 2.2);
+        let settings = MarchSettings { t_min: 0.0, max_steps: 500, ..Default::default() };
+        let from = Point3::new(-5.0, 1.3, 0.2);
+        let ray = Vector3::new(1.0, 0.0, 0.0);
+
+        // The first crossing of the surface, found by creeping along
+        let mut t = 0.0;
+        while shape.distance(&(from + ray.scale(t))) > 0.0 {
+            t += 1e-4;
+        }
+
+        let r = cast_field(&shape, &from, &ray, &settings);
+        assert_eq!(HitStatus::Hit, r.status);
+        assert!(Float::abs(r.t - t) < 1e-3, "{} {}", r.t, t);
+    }
+}
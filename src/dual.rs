@@ -0,0 +1,237 @@
+use std::cmp::Ordering;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+use crate::real::{Float, Real};
+use crate::point::{Point3, Vector3};
+
+// A dual number carrying a value and its gradient with respect to the query
+// position. Evaluating a distance function on a `Point3<Dual>` gives the
+// distance and its gradient (the unnormalised surface normal) in one pass.
+#[derive(Debug, Copy, Clone)]
+pub struct Dual
+{
+    pub v: Float,
+    pub grad: Vector3,
+}
+
+impl Dual
+{
+    pub fn constant(v: Float) -> Self {
+        Dual {
+            v,
+            grad: Vector3::zero(),
+        }
+    }
+
+    // The position as dual numbers, each component varying along its own axis
+    pub fn position(p: &Point3) -> Point3<Dual> {
+        Point3 {
+            x: Dual { v: p.x, grad: Vector3::new(1.0, 0.0, 0.0) },
+            y: Dual { v: p.y, grad: Vector3::new(0.0, 1.0, 0.0) },
+            z: Dual { v: p.z, grad: Vector3::new(0.0, 0.0, 1.0) },
+        }
+    }
+}
+
+// Ordering and equality only look at the value, so that min and max pick the
+// branch the plain float evaluation would
+impl PartialEq for Dual
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.v == other.v
+    }
+}
+
+impl PartialOrd for Dual
+{
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        self.v.partial_cmp(&other.v)
+    }
+}
+
+impl Add for Dual
+{
+    type Output = Dual;
+
+    fn add(self, other: Dual) -> Dual {
+        Dual {
+            v: self.v + other.v,
+            grad: self.grad + other.grad,
+        }
+    }
+}
+
+impl Sub for Dual
+{
+    type Output = Dual;
+
+    fn sub(self, other: Dual) -> Dual {
+        Dual {
+            v: self.v - other.v,
+            grad: self.grad - other.grad,
+        }
+    }
+}
+
+impl Mul for Dual
+{
+    type Output = Dual;
+
+    fn mul(self, other: Dual) -> Dual {
+        Dual {
+            v: self.v * other.v,
+            grad: self.grad * other.v + other.grad * self.v,
+        }
+    }
+}
+
+impl Div for Dual
+{
+    type Output = Dual;
+
+    fn div(self, other: Dual) -> Dual {
+        Dual {
+            v: self.v / other.v,
+            grad: (self.grad * other.v - other.grad * self.v) / (other.v * other.v),
+        }
+    }
+}
+
+impl Neg for Dual
+{
+    type Output = Dual;
+
+    fn neg(self) -> Dual {
+        Dual {
+            v: -self.v,
+            grad: -self.grad,
+        }
+    }
+}
+
+impl Real for Dual
+{
+    #[allow(clippy::unnecessary_cast)]
+    fn from_f64(v: f64) -> Self {
+        Dual::constant(v as Float)
+    }
+
+    #[allow(clippy::unnecessary_cast)]
+    fn to_f64(self) -> f64 {
+        self.v as f64
+    }
+
+    fn sqrt(self) -> Self {
+        let v = Float::sqrt(self.v);
+        // The derivative blows up at zero; call it flat rather than NaN
+        let grad = if v > 0.0 {
+            self.grad * (0.5 / v)
+        } else {
+            Vector3::zero()
+        };
+        Dual { v, grad }
+    }
+
+    fn abs(self) -> Self {
+        if self.v < 0.0 { -self } else { self }
+    }
+
+    fn max(self, other: Self) -> Self {
+        if self >= other { self } else { other }
+    }
+
+    fn min(self, other: Self) -> Self {
+        if self <= other { self } else { other }
+    }
+
+    // Piecewise constant, so flat everywhere it's differentiable
+    fn floor(self) -> Self {
+        Dual::constant(Float::floor(self.v))
+    }
+
+    fn sin(self) -> Self {
+        Dual { v: self.v.sin(), grad: self.grad * self.v.cos() }
+    }
+
+    fn cos(self) -> Self {
+        Dual { v: self.v.cos(), grad: self.grad * -self.v.sin() }
+    }
+
+    // d atan2(y, x) = (x dy - y dx) / (x^2 + y^2); flat at the origin
+    fn atan2(self, other: Self) -> Self {
+        let (y, x) = (self, other);
+        let r2 = x.v * x.v + y.v * y.v;
+        let grad = if r2 > 0.0 {
+            (y.grad * x.v - x.grad * y.v) / r2
+        } else {
+            Vector3::zero()
+        };
+        Dual { v: y.v.atan2(x.v), grad }
+    }
+
+    // The chain rule, through the gradient f gives
+    fn apply3(p: &Point3<Self>, _: impl Fn(&Point3) -> Float, sample: impl Fn(&Point3) -> (Float, Vector3)) -> Self {
+        let (v, g) = sample(&p.value());
+        Dual {
+            v,
+            grad: p.x.grad * g.x + p.y.grad * g.y + p.z.grad * g.z,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn x(v: Float) -> Dual {
+        Dual::position(&Point3::new(v, 0.0, 0.0)).x
+    }
+
+    #[test]
+    fn arithmetic() {
+        let a = x(3.0);
+        let two = Dual::constant(2.0);
+
+        assert_eq!(6.0, (a * two).v);
+        assert_eq!(1.0, (a + two).grad.x);
+        assert_eq!(-1.0, (two - a).grad.x);
+        assert_eq!(2.0, (a * two).grad.x);
+        assert_eq!(6.0, (a * a).grad.x);
+        // d/dx 2/x = -2/x^2
+        assert_eq!(-2.0 / 9.0, (two / a).grad.x);
+        assert_eq!(-1.0, (-a).grad.x);
+    }
+
+    #[test]
+    fn functions() {
+        // d/dx sqrt(x) = 1/(2 sqrt(x))
+        assert_eq!(0.25, x(4.0).sqrt().grad.x);
+        assert_eq!(Vector3::zero(), x(0.0).sqrt().grad);
+        assert_eq!(-1.0, x(-2.0).abs().grad.x);
+        assert_eq!(1.0, x(2.0).abs().grad.x);
+        assert_eq!(0.0, x(2.0).min(Dual::constant(1.0)).grad.x);
+        assert_eq!(1.0, x(2.0).max(Dual::constant(1.0)).grad.x);
+        assert_eq!(Float::cos(0.5), x(0.5).sin().grad.x);
+        assert_eq!(-Float::sin(0.5), x(0.5).cos().grad.x);
+    }
+
+    #[test]
+    fn atan2_gradient() {
+        // The angle around the z axis increases anticlockwise, with gradient
+        // (-y, x) / r^2
+        let p = Dual::position(&Point3::new(3.0, 4.0, 0.0));
+        let a = p.y.atan2(p.x);
+        assert_eq!(Float::atan2(4.0, 3.0), a.v);
+        assert!((a.grad - Vector3::new(-4.0, 3.0, 0.0) / 25.0).mag() < 1e-12);
+        assert_eq!(Vector3::zero(), Dual::constant(0.0).atan2(Dual::constant(0.0)).grad);
+    }
+
+    #[test]
+    fn vector_magnitude_gradient() {
+        // The gradient of |p| is p / |p|
+        let p = Point3::new(1.0, 2.0, 2.0);
+        let m = Dual::position(&p).to_vector().mag();
+        assert_eq!(3.0, m.v);
+        assert_eq!(p.to_vector().scale(1.0 / 3.0), m.grad);
+    }
+}
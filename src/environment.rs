@@ -0,0 +1,510 @@
+// What rays see when they leave the scene, and the light it casts back in.
+//
+// Directions map to equirectangular coordinates with u going round the y
+// axis from -x and v down from straight up, so row 0 of an environment map
+// is the zenith.
+
+use anyhow::{bail, Context, Result};
+use image::codecs::hdr::HdrDecoder;
+use image::Rgb32FImage;
+
+use crate::point::Vector3;
+use crate::real::Float;
+use crate::texture::Colour;
+
+const PI: Float = std::f64::consts::PI as Float;
+
+fn lerp(a: &Colour, b: &Colour, t: Float) -> Colour {
+    *a + (*b - *a).scale(t)
+}
+
+// Equirectangular coordinates of a unit direction, both in [0, 1]
+fn to_uv(d: &Vector3) -> (Float, Float) {
+    (d.z.atan2(d.x) / (2.0 * PI) + 0.5, d.y.clamp(-1.0, 1.0).acos() / PI)
+}
+
+fn from_uv(u: Float, v: Float) -> Vector3 {
+    let (phi, theta) = ((u - 0.5) * 2.0 * PI, v * PI);
+    Vector3::new(theta.sin() * phi.cos(), theta.cos(), theta.sin() * phi.sin())
+}
+
+#[derive(Debug, Clone)]
+pub enum Environment
+{
+    Solid(Colour),
+    // Horizon to zenith above, horizon to ground below
+    Gradient { ground: Colour, horizon: Colour, zenith: Colour },
+    Sky(Sky),
+    Map(EnvironmentMap),
+}
+
+impl Environment
+{
+    // A pale blue sky over brown ground
+    pub fn gradient() -> Self {
+        Environment::Gradient {
+            ground: Vector3::new(0.3, 0.27, 0.24),
+            horizon: Vector3::new(0.85, 0.88, 0.92),
+            zenith: Vector3::new(0.3, 0.5, 0.9),
+        }
+    }
+
+    // From the command line: solid=r,g,b, gradient, sky[=turbidity] or
+    // map=file.hdr. `sun` is the direction of the scene's sunlight.
+    pub fn parse(s: &str, sun: &Vector3) -> Result<Self> {
+        Ok(match s.split_once('=') {
+            None if s == "gradient" => Environment::gradient(),
+            None if s == "sky" => Environment::Sky(Sky::new(sun)),
+            Some(("sky", turbidity)) => Environment::Sky(Sky { turbidity: turbidity.parse()?, ..Sky::new(sun) }),
+            Some(("map", path)) => Environment::Map(EnvironmentMap::open(path)?),
+            Some(("solid", rgb)) => {
+                let c = rgb
+                    .split(',')
+                    .map(|c| c.trim().parse::<Float>())
+                    .collect::<Result<Vec<_>, _>>()?;
+                match c[..] {
+                    [r, g, b] => Environment::Solid(Vector3::new(r, g, b)),
+                    _ => bail!("Solid colour must be r,g,b, got {}", rgb),
+                }
+            }
+            _ => bail!("Unknown environment {} (solid=r,g,b, gradient, sky[=turbidity], map=file)", s),
+        })
+    }
+
+    // Radiance arriving from `direction`, a unit vector
+    pub fn colour(&self, direction: &Vector3) -> Colour {
+        match self {
+            Environment::Sky(sky) => sky.colour(direction) + sky.sun_disc(direction),
+            _ => self.ambient(direction),
+        }
+    }
+
+    // The same, leaving out the sun, which is lit directly
+    fn ambient(&self, d: &Vector3) -> Colour {
+        match self {
+            Environment::Solid(c) => *c,
+            Environment::Gradient { ground, horizon, zenith } => {
+                if d.y >= 0.0 {
+                    lerp(horizon, zenith, d.y)
+                } else {
+                    lerp(horizon, ground, -d.y)
+                }
+            }
+            Environment::Sky(sky) => sky.colour(d),
+            Environment::Map(map) => map.sample(d),
+        }
+    }
+}
+
+// The Preetham et al. daylight model ("A Practical Analytic Model for
+// Daylight", 1999): the clear sky's colour from the sun's height and the
+// turbidity, 2 for a clear day up to about 10 for haze. `exposure` scales
+// luminance from kcd/m², so the sky darkens as the sun sets. The horizon
+// carries on below it.
+#[derive(Debug, Copy, Clone)]
+pub struct Sky
+{
+    // Unit vector towards the sun
+    pub sun: Vector3,
+    pub turbidity: Float,
+    pub exposure: Float,
+    // Angular radius of the sun's disc, radians. The real sun is 0.0047.
+    pub sun_size: Float,
+    pub sun_colour: Colour,
+}
+
+// Perez distribution coefficients A to E for Y, x and y, each a * T + b
+const PEREZ: [[(Float, Float); 5]; 3] = [
+    [(0.1787, -1.4630), (-0.3554, 0.4275), (-0.0227, 5.3251), (0.1206, -2.5771), (-0.0670, 0.3703)],
+    [(-0.0193, -0.2592), (-0.0665, 0.0008), (-0.0004, 0.2125), (-0.0641, -0.8989), (-0.0033, 0.0452)],
+    [(-0.0167, -0.2608), (-0.0950, 0.0092), (-0.0079, 0.2102), (-0.0441, -1.6537), (-0.0109, 0.0529)],
+];
+
+// Zenith chromaticity, polynomials in the sun's zenith angle per power of T
+const ZENITH_X: [[Float; 4]; 3] = [
+    [0.00166, -0.00375, 0.00209, 0.0],
+    [-0.02903, 0.06377, -0.03202, 0.00394],
+    [0.11693, -0.21196, 0.06052, 0.25886],
+];
+const ZENITH_Y: [[Float; 4]; 3] = [
+    [0.00275, -0.00610, 0.00317, 0.0],
+    [-0.04214, 0.08970, -0.04153, 0.00516],
+    [0.15346, -0.26756, 0.06670, 0.26688],
+];
+
+impl Sky
+{
+    pub fn new(sun: &Vector3) -> Self {
+        Sky {
+            sun: sun.normalized(),
+            turbidity: 3.0,
+            exposure: 0.08,
+            sun_size: 0.02,
+            sun_colour: Vector3::new(20.0, 18.0, 15.0),
+        }
+    }
+
+    // The distribution of light over the sky relative to the zenith, for
+    // view zenith angle theta and angle gamma from the sun
+    fn perez(&self, coefficients: &[(Float, Float); 5], theta: Float, gamma: Float) -> Float {
+        let [a, b, c, d, e] = coefficients.map(|(a, b)| a * self.turbidity + b);
+        (1.0 + a * (b / theta.cos()).exp()) * (1.0 + c * (d * gamma).exp() + e * gamma.cos().powi(2))
+    }
+
+    fn zenith(&self, theta_sun: Float) -> (Float, Float, Float) {
+        let t = self.turbidity;
+        let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_sun);
+        let luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+
+        let poly = |c: &[[Float; 4]; 3]| {
+            let cubic = |k: &[Float; 4]| ((k[0] * theta_sun + k[1]) * theta_sun + k[2]) * theta_sun + k[3];
+            cubic(&c[0]) * t * t + cubic(&c[1]) * t + cubic(&c[2])
+        };
+        (luminance, poly(&ZENITH_X), poly(&ZENITH_Y))
+    }
+
+    // Sky colour, without the sun's disc
+    pub fn colour(&self, direction: &Vector3) -> Colour {
+        // Keep the sun and the view just above the horizon, where the model
+        // holds up
+        let up = |y: Float| y.clamp(0.01, 1.0).acos();
+        let theta_sun = up(self.sun.y);
+        let theta = up(direction.y);
+        let gamma = direction.dot_product(&self.sun).clamp(-1.0, 1.0).acos();
+
+        let zenith = self.zenith(theta_sun);
+        let relative = |k: usize| self.perez(&PEREZ[k], theta, gamma) / self.perez(&PEREZ[k], 0.0, theta_sun);
+        let luminance = self.exposure * zenith.0 * relative(0);
+        let x = zenith.1 * relative(1);
+        let y = zenith.2 * relative(2);
+
+        // xyY to XYZ to linear sRGB
+        let cx = x / y * luminance;
+        let cz = (1.0 - x - y) / y * luminance;
+        let rgb = Vector3::new(
+            3.2406 * cx - 1.5372 * luminance - 0.4986 * cz,
+            -0.9689 * cx + 1.8758 * luminance + 0.0415 * cz,
+            0.0557 * cx - 0.2040 * luminance + 1.0570 * cz,
+        );
+        Vector3::new(rgb.x.max(0.0), rgb.y.max(0.0), rgb.z.max(0.0))
+    }
+
+    // The sun itself, with a soft edge a fifth of its radius wide
+    pub fn sun_disc(&self, direction: &Vector3) -> Colour {
+        let gamma = direction.dot_product(&self.sun).clamp(-1.0, 1.0).acos();
+        let edge = ((self.sun_size - gamma) / (0.2 * self.sun_size) + 0.5).clamp(0.0, 1.0);
+        self.sun_colour.scale(edge)
+    }
+}
+
+// An equirectangular image, usually HDR, sampled bilinearly
+#[derive(Debug, Clone)]
+pub struct EnvironmentMap
+{
+    image: Rgb32FImage,
+}
+
+impl EnvironmentMap
+{
+    pub fn new(image: Rgb32FImage) -> Result<Self> {
+        if image.width() == 0 || image.height() == 0 {
+            bail!("Environment map is empty ({}x{})", image.width(), image.height());
+        }
+        Ok(EnvironmentMap { image })
+    }
+
+    // Radiance .hdr files, or any other image the `image` crate reads.
+    // Going through `image::open` would squash .hdr files down to 8 bits.
+    pub fn open(path: &str) -> Result<Self> {
+        let context = || format!("Can't load environment map {}", path);
+        if path.to_lowercase().ends_with(".hdr") {
+            let file = std::io::BufReader::new(std::fs::File::open(path).with_context(context)?);
+            let decoder = HdrDecoder::new(file).with_context(context)?;
+            let (w, h) = (decoder.metadata().width, decoder.metadata().height);
+            let pixels = decoder.read_image_hdr().with_context(context)?;
+            let image = Rgb32FImage::from_fn(w, h, |x, y| pixels[(y * w + x) as usize]);
+            return EnvironmentMap::new(image).with_context(context);
+        }
+        let image = image::open(path).with_context(context)?;
+        EnvironmentMap::new(image.to_rgb32f()).with_context(context)
+    }
+
+    // Wrapping round in x, clamped at the poles
+    fn pixel(&self, x: i64, y: i64) -> Colour {
+        let (w, h) = (self.image.width() as i64, self.image.height() as i64);
+        let p = self.image.get_pixel(x.rem_euclid(w) as u32, y.clamp(0, h - 1) as u32);
+        Vector3::new(p[0] as Float, p[1] as Float, p[2] as Float)
+    }
+
+    pub fn sample(&self, direction: &Vector3) -> Colour {
+        let (u, v) = to_uv(direction);
+        let x = u * self.image.width() as Float - 0.5;
+        let y = v * self.image.height() as Float - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        let top = lerp(&self.pixel(x0, y0), &self.pixel(x0 + 1, y0), fx);
+        let bottom = lerp(&self.pixel(x0, y0 + 1), &self.pixel(x0 + 1, y0 + 1), fx);
+        lerp(&top, &bottom, fy)
+    }
+
+    // Averages over a width x height grid of equirectangular cells
+    fn downsampled(&self, width: usize, height: usize) -> Vec<Colour> {
+        let mut sums = vec![(Vector3::zero(), 0); width * height];
+        let (w, h) = (self.image.width() as usize, self.image.height() as usize);
+        for (x, y, p) in self.image.enumerate_pixels() {
+            let cell = (y as usize * height / h) * width + x as usize * width / w;
+            sums[cell].0 += Vector3::new(p[0] as Float, p[1] as Float, p[2] as Float);
+            sums[cell].1 += 1;
+        }
+        // Cells no pixel landed in, for maps smaller than the grid
+        sums.iter()
+            .enumerate()
+            .map(|(i, (sum, n))| match n {
+                0 => self.sample(&cell_direction(i, width, height)),
+                n => sum.scale(1.0 / *n as Float),
+            })
+            .collect()
+    }
+}
+
+// The direction through the middle of a cell of an equirectangular grid
+fn cell_direction(i: usize, width: usize, height: usize) -> Vector3 {
+    let (x, y) = (i % width, i / width);
+    from_uv((x as Float + 0.5) / width as Float, (y as Float + 0.5) / height as Float)
+}
+
+// Grid sizes for the environment as seen by the integration, and for the
+// lookup table it fills
+const SOURCE: (usize, usize) = (64, 32);
+const TABLE: (usize, usize) = (32, 16);
+
+// Image-based lighting: the light reaching a surface from the whole
+// environment (apart from the sun) over a hemisphere, cosine weighted and
+// divided by pi, so a uniform environment gives back its own colour.
+// Worked out once into a small equirectangular table indexed by normal, as
+// it varies slowly.
+#[derive(Debug, Clone)]
+pub struct Irradiance
+{
+    table: Vec<Colour>,
+}
+
+impl Irradiance
+{
+    pub fn new(environment: &Environment) -> Self {
+        let (sw, sh) = SOURCE;
+        let radiance = match environment {
+            Environment::Map(map) => map.downsampled(sw, sh),
+            _ => (0..sw * sh).map(|i| environment.ambient(&cell_direction(i, sw, sh))).collect(),
+        };
+        // Each source cell's direction and solid angle
+        let cells: Vec<(Vector3, Float)> = (0..sw * sh)
+            .map(|i| {
+                let theta = ((i / sw) as Float + 0.5) / sh as Float * PI;
+                let solid_angle = (2.0 * PI / sw as Float) * (PI / sh as Float) * theta.sin();
+                (cell_direction(i, sw, sh), solid_angle)
+            })
+            .collect();
+
+        let (tw, th) = TABLE;
+        let table = (0..tw * th)
+            .map(|i| {
+                let n = cell_direction(i, tw, th);
+                let mut sum = Vector3::zero();
+                for ((d, solid_angle), l) in cells.iter().zip(&radiance) {
+                    sum += l.scale(n.dot_product(d).max(0.0) * solid_angle);
+                }
+                sum.scale(1.0 / PI)
+            })
+            .collect();
+        Irradiance { table }
+    }
+
+    fn texel(&self, x: i64, y: i64) -> Colour {
+        let (w, h) = (TABLE.0 as i64, TABLE.1 as i64);
+        self.table[(y.clamp(0, h - 1) * w + x.rem_euclid(w)) as usize]
+    }
+
+    // For a surface facing `normal`, a unit vector
+    pub fn get(&self, normal: &Vector3) -> Colour {
+        let (u, v) = to_uv(normal);
+        let x = u * TABLE.0 as Float - 0.5;
+        let y = v * TABLE.1 as Float - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        let top = lerp(&self.texel(x0, y0), &self.texel(x0 + 1, y0), fx);
+        let bottom = lerp(&self.texel(x0, y0 + 1), &self.texel(x0 + 1, y0 + 1), fx);
+        lerp(&top, &bottom, fy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgb;
+
+    fn near(a: &Colour, b: &Colour, tolerance: Float) -> bool {
+        (*a - *b).mag() < tolerance
+    }
+
+    // Unit vectors spread over the sphere
+    fn directions() -> impl Iterator<Item = Vector3> {
+        (0..200).map(|i| from_uv((i as Float * 0.618) % 1.0, (i as Float + 0.5) / 200.0))
+    }
+
+    fn up() -> Vector3 {
+        Vector3::new(0.0, 1.0, 0.0)
+    }
+
+    #[test]
+    fn uv_round_trip() {
+        for d in directions() {
+            let (u, v) = to_uv(&d);
+            assert!(near(&d, &from_uv(u, v), 1e-4), "{:?}", d);
+        }
+        assert!(Float::abs(to_uv(&up()).1) < 1e-6);
+    }
+
+    #[test]
+    fn solid_and_gradient() {
+        let grey = Vector3::new(0.5, 0.5, 0.5);
+        assert!(directions().all(|d| Environment::Solid(grey).colour(&d) == grey));
+
+        let Environment::Gradient { ground, horizon, zenith } = Environment::gradient() else { unreachable!() };
+        let g = Environment::gradient();
+        assert!(near(&zenith, &g.colour(&up()), 1e-6));
+        assert!(near(&horizon, &g.colour(&Vector3::new(1.0, 0.0, 0.0)), 1e-6));
+        assert!(near(&ground, &g.colour(&Vector3::new(0.0, -1.0, 0.0)), 1e-6));
+    }
+
+    #[test]
+    fn sky() {
+        let sky = Sky::new(&Vector3::new(0.0, 0.5, 1.0));
+        let env = Environment::Sky(sky);
+        let zenith = env.colour(&up());
+        // Blue overhead
+        assert!(zenith.z > zenith.x && zenith.z > zenith.y);
+        // Brighter towards the sun than away from it, and paler at the
+        // horizon
+        let towards = env.colour(&Vector3::new(0.0, 0.2, 1.0).normalized());
+        let away = env.colour(&Vector3::new(0.0, 0.2, -1.0).normalized());
+        assert!(towards.mag() > away.mag());
+        let horizon = env.colour(&Vector3::new(1.0, 0.01, 0.0));
+        assert!(horizon.x / horizon.z > zenith.x / zenith.z);
+        // The disc is the sun's colour, and only around it
+        assert!(near(&(sky.colour(&sky.sun) + sky.sun_colour), &env.colour(&sky.sun), 1e-6));
+        assert_eq!(Vector3::zero(), sky.sun_disc(&Vector3::new(0.0, 0.2, 1.0).normalized()));
+        // Hazier skies are whiter
+        let hazy = Sky { turbidity: 8.0, ..sky }.colour(&up());
+        assert!(hazy.x / hazy.z > zenith.x / zenith.z);
+        // Everything is finite, under the horizon too
+        for d in directions() {
+            let c = env.colour(&d);
+            assert!(c.x.is_finite() && c.y.is_finite() && c.z.is_finite() && c.x >= 0.0, "{:?}", d);
+        }
+    }
+
+    #[test]
+    fn sky_darkens_at_sunset() {
+        let noon = Sky::new(&Vector3::new(0.0, 1.0, 0.3)).colour(&up());
+        let dusk = Sky::new(&Vector3::new(0.0, 0.05, 1.0)).colour(&up());
+        assert!(dusk.mag() < 0.5 * noon.mag());
+    }
+
+    // Red in the top half, blue in the bottom, with a green column facing +x
+    fn test_map() -> EnvironmentMap {
+        EnvironmentMap::new(Rgb32FImage::from_fn(8, 4, |x, y| match (x, y) {
+            (4, _) => Rgb([0.0, 1.0, 0.0]),
+            (_, 0..=1) => Rgb([2.0, 0.0, 0.0]),
+            _ => Rgb([0.0, 0.0, 1.0]),
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn map() {
+        let map = test_map();
+        assert!(near(&Vector3::new(2.0, 0.0, 0.0), &map.sample(&Vector3::new(-0.3, 0.8, -0.3).normalized()), 1e-6));
+        assert!(near(&Vector3::new(0.0, 0.0, 1.0), &map.sample(&Vector3::new(-0.3, -0.8, -0.3).normalized()), 1e-6));
+        // Column 4 is centred at u = 4.5 / 8
+        let d = from_uv(4.5 / 8.0, 0.125);
+        assert!(near(&Vector3::new(0.0, 1.0, 0.0), &map.sample(&d), 1e-6));
+        // Bilinear, halfway to the next column
+        let d = from_uv(5.0 / 8.0, 0.125);
+        assert!(near(&Vector3::new(1.0, 0.5, 0.0), &map.sample(&d), 1e-6));
+        // Wrapping round the back
+        let d = from_uv(0.999, 0.125);
+        assert!(near(&Vector3::new(2.0, 0.0, 0.0), &map.sample(&d), 1e-6));
+    }
+
+    #[test]
+    fn open_hdr() {
+        let path = std::env::temp_dir().join("sdf-rs-environment.hdr");
+        let pixels: Vec<Rgb<f32>> = test_map().image.pixels().copied().collect();
+        let file = std::fs::File::create(&path).unwrap();
+        image::codecs::hdr::HdrEncoder::new(file).encode(&pixels, 8, 4).unwrap();
+
+        let map = EnvironmentMap::open(path.to_str().unwrap()).unwrap();
+        // Brighter than white survives
+        let d = Vector3::new(-0.3, 0.8, -0.3).normalized();
+        assert!(near(&Vector3::new(2.0, 0.0, 0.0), &map.sample(&d), 1e-2));
+        assert!(EnvironmentMap::open("no/such/sky.hdr").is_err());
+        assert!(EnvironmentMap::new(Rgb32FImage::new(0, 0)).is_err());
+        assert!(EnvironmentMap::new(Rgb32FImage::new(0, 4)).is_err());
+    }
+
+    #[test]
+    fn uniform_irradiance() {
+        let grey = Vector3::new(0.25, 0.5, 0.75);
+        let irradiance = Irradiance::new(&Environment::Solid(grey));
+        for d in directions() {
+            assert!(near(&grey, &irradiance.get(&d), 0.01), "{:?} {:?}", d, irradiance.get(&d));
+        }
+    }
+
+    #[test]
+    fn hemisphere_irradiance() {
+        // White above the horizon, black below: a surface facing up sees
+        // all of it, one facing sideways half, one facing down none
+        let image = Rgb32FImage::from_fn(256, 128, |_, y| if y < 64 { Rgb([1.0; 3]) } else { Rgb([0.0; 3]) });
+        let irradiance = Irradiance::new(&Environment::Map(EnvironmentMap::new(image).unwrap()));
+        let grey = |c: Colour| Vector3::splat(c.x);
+        assert!(near(&Vector3::splat(1.0), &grey(irradiance.get(&up())), 0.02));
+        assert!(near(&Vector3::splat(0.5), &grey(irradiance.get(&Vector3::new(1.0, 0.0, 0.0))), 0.02));
+        assert!(near(&Vector3::zero(), &grey(irradiance.get(&Vector3::new(0.0, -1.0, 0.0))), 0.02));
+
+        // Gradients and skies are lit from above too
+        let gradient = Irradiance::new(&Environment::gradient());
+        assert!(gradient.get(&up()).z > gradient.get(&Vector3::new(0.0, -1.0, 0.0)).z);
+    }
+
+    #[test]
+    fn small_maps() {
+        // Fewer pixels than the irradiance grid still covers it
+        let irradiance = Irradiance::new(&Environment::Map(test_map()));
+        assert!(irradiance.get(&up()).x > irradiance.get(&Vector3::new(0.0, -1.0, 0.0)).x);
+    }
+
+    #[test]
+    fn parse() {
+        let sun = Vector3::new(0.0, 1.0, 0.0);
+        assert!(matches!(Environment::parse("gradient", &sun).unwrap(), Environment::Gradient { .. }));
+        match Environment::parse("sky=6", &sun).unwrap() {
+            Environment::Sky(sky) => {
+                assert_eq!(6.0, sky.turbidity);
+                assert_eq!(sun, sky.sun);
+            }
+            _ => panic!("Expected a sky"),
+        }
+        match Environment::parse("solid=0.1,0.2,0.3", &sun).unwrap() {
+            Environment::Solid(c) => assert_eq!(Vector3::new(0.1, 0.2, 0.3), c),
+            _ => panic!("Expected a solid colour"),
+        }
+        assert!(Environment::parse("solid=1,2", &sun).is_err());
+        assert!(Environment::parse("sky=hazy", &sun).is_err());
+        assert!(Environment::parse("map=no/such/sky.hdr", &sun).is_err());
+        assert!(Environment::parse("space", &sun).is_err());
+    }
+}
@@ -0,0 +1,728 @@
+// Writing meshes out for other tools: Wavefront OBJ with normals, STL
+// (binary, or ASCII) for 3D printing, PLY with a colour per vertex, and
+// glTF 2.0 as a single binary .glb file.
+//
+// Everything is written little endian with 32-bit floats, whatever
+// precision the mesh was made in.
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+
+use crate::mesh::Mesh;
+use crate::point::Vector3;
+use crate::texture::{to_rgb, Colour};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Format
+{
+    Obj,
+    // Binary
+    Stl,
+    StlAscii,
+    Ply,
+    Glb,
+}
+
+impl Format
+{
+    pub fn parse(s: &str) -> Result<Self> {
+        Ok(match s {
+            "obj" => Format::Obj,
+            "stl" => Format::Stl,
+            "stl-ascii" => Format::StlAscii,
+            "ply" => Format::Ply,
+            "glb" => Format::Glb,
+            _ => bail!("Mesh format must be obj, stl, stl-ascii, ply or glb, got {}", s),
+        })
+    }
+
+    // From the file's extension. STL files are written binary.
+    pub fn from_path(path: &str) -> Result<Self> {
+        let extension = Path::new(path).extension().and_then(|e| e.to_str()).unwrap_or("");
+        Format::parse(&extension.to_ascii_lowercase()).with_context(|| format!("Can't tell the mesh format of {}", path))
+    }
+
+    // `colours`, one per vertex, are written where the format has room for
+    // them (PLY and glTF) and may be empty
+    pub fn write<W: Write>(self, mesh: &Mesh, colours: &[Colour], w: &mut W) -> Result<()> {
+        match self {
+            Format::Obj => write_obj(mesh, w),
+            Format::Stl => write_stl(mesh, w),
+            Format::StlAscii => write_stl_ascii(mesh, w),
+            Format::Ply => write_ply(mesh, colours, w),
+            Format::Glb => write_glb(mesh, colours, w),
+        }
+    }
+}
+
+// A mesh file to write, and how
+#[derive(Debug, Clone, PartialEq)]
+pub struct MeshOutput
+{
+    pub path: String,
+    pub format: Format,
+}
+
+pub fn save(path: &str, format: Format, mesh: &Mesh, colours: &[Colour]) -> Result<()> {
+    let file = File::create(path).with_context(|| format!("Can't create {}", path))?;
+    let mut w = BufWriter::new(file);
+    format.write(mesh, colours, &mut w)?;
+    w.flush()?;
+    Ok(())
+}
+
+fn f32s<W: Write>(w: &mut W, values: &[f32]) -> Result<()> {
+    for v in values {
+        w.write_all(&v.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+#[allow(clippy::unnecessary_cast)]
+fn components(v: &Vector3) -> [f32; 3] {
+    [v.x as f32, v.y as f32, v.z as f32]
+}
+
+fn checked_colours<'a>(mesh: &Mesh, colours: &'a [Colour]) -> Result<Option<&'a [Colour]>> {
+    match colours.len() {
+        0 => Ok(None),
+        n if n == mesh.vertices.len() => Ok(Some(colours)),
+        n => bail!("{} colours for {} vertices", n, mesh.vertices.len()),
+    }
+}
+
+// Indices in OBJ count from 1
+pub fn write_obj<W: Write>(mesh: &Mesh, w: &mut W) -> Result<()> {
+    writeln!(w, "# sdf-rs")?;
+    for p in &mesh.vertices {
+        writeln!(w, "v {} {} {}", p.x, p.y, p.z)?;
+    }
+    for n in &mesh.normals {
+        writeln!(w, "vn {} {} {}", n.x, n.y, n.z)?;
+    }
+    for t in &mesh.triangles {
+        let [a, b, c] = t.map(|i| i + 1);
+        writeln!(w, "f {a}//{a} {b}//{b} {c}//{c}")?;
+    }
+    Ok(())
+}
+
+// STL has no shared vertices, only triangles, each with its facet normal
+pub fn write_stl<W: Write>(mesh: &Mesh, w: &mut W) -> Result<()> {
+    let mut header = [0u8; 80];
+    header[..6].copy_from_slice(b"sdf-rs");
+    w.write_all(&header)?;
+    w.write_all(&(mesh.triangles.len() as u32).to_le_bytes())?;
+    for t in &mesh.triangles {
+        f32s(w, &components(&mesh.face_normal(t)))?;
+        for &i in t {
+            f32s(w, &components(&mesh.vertices[i as usize].to_vector()))?;
+        }
+        // Attribute byte count, unused
+        w.write_all(&[0, 0])?;
+    }
+    Ok(())
+}
+
+pub fn write_stl_ascii<W: Write>(mesh: &Mesh, w: &mut W) -> Result<()> {
+    writeln!(w, "solid sdf-rs")?;
+    for t in &mesh.triangles {
+        let [x, y, z] = components(&mesh.face_normal(t));
+        writeln!(w, "  facet normal {:e} {:e} {:e}", x, y, z)?;
+        writeln!(w, "    outer loop")?;
+        for &i in t {
+            let [x, y, z] = components(&mesh.vertices[i as usize].to_vector());
+            writeln!(w, "      vertex {:e} {:e} {:e}", x, y, z)?;
+        }
+        writeln!(w, "    endloop")?;
+        writeln!(w, "  endfacet")?;
+    }
+    writeln!(w, "endsolid sdf-rs")?;
+    Ok(())
+}
+
+// Binary PLY, with colours as bytes if there are any
+pub fn write_ply<W: Write>(mesh: &Mesh, colours: &[Colour], w: &mut W) -> Result<()> {
+    let colours = checked_colours(mesh, colours)?;
+    writeln!(w, "ply")?;
+    writeln!(w, "format binary_little_endian 1.0")?;
+    writeln!(w, "comment sdf-rs")?;
+    writeln!(w, "element vertex {}", mesh.vertices.len())?;
+    for name in ["x", "y", "z", "nx", "ny", "nz"] {
+        writeln!(w, "property float {}", name)?;
+    }
+    if colours.is_some() {
+        for name in ["red", "green", "blue"] {
+            writeln!(w, "property uchar {}", name)?;
+        }
+    }
+    writeln!(w, "element face {}", mesh.triangles.len())?;
+    writeln!(w, "property list uchar uint vertex_indices")?;
+    writeln!(w, "end_header")?;
+
+    for (i, (p, n)) in mesh.vertices.iter().zip(&mesh.normals).enumerate() {
+        f32s(w, &components(&p.to_vector()))?;
+        f32s(w, &components(n))?;
+        if let Some(colours) = colours {
+            w.write_all(&to_rgb(&colours[i]).0)?;
+        }
+    }
+    for t in &mesh.triangles {
+        w.write_all(&[3])?;
+        for i in t {
+            w.write_all(&i.to_le_bytes())?;
+        }
+    }
+    Ok(())
+}
+
+// glTF's constants for the types in accessors and the buffers they view
+const FLOAT: u32 = 5126;
+const UNSIGNED_INT: u32 = 5125;
+const UNSIGNED_BYTE: u32 = 5121;
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+
+// One mesh in one node in one scene. The binary chunk holds positions,
+// normals, indices and then colours (RGBA bytes, to keep the 4 byte
+// alignment vertex attributes need), each in its own buffer view.
+pub fn write_glb<W: Write>(mesh: &Mesh, colours: &[Colour], w: &mut W) -> Result<()> {
+    let colours = checked_colours(mesh, colours)?;
+    let mut bin = vec![];
+    let mut views = vec![];
+    let mut accessors = vec![];
+    let count = mesh.vertices.len();
+
+    let mut view = |bin: &mut Vec<u8>, data: Vec<u8>, target: u32| {
+        views.push(format!(
+            r#"{{"buffer":0,"byteOffset":{},"byteLength":{},"target":{}}}"#,
+            bin.len(), data.len(), target
+        ));
+        bin.extend(data);
+        views.len() - 1
+    };
+
+    let positions: Vec<[f32; 3]> = mesh.vertices.iter().map(|p| components(&p.to_vector())).collect();
+    let (mut min, mut max) = ([f32::INFINITY; 3], [f32::NEG_INFINITY; 3]);
+    for p in &positions {
+        for i in 0..3 {
+            min[i] = min[i].min(p[i]);
+            max[i] = max[i].max(p[i]);
+        }
+    }
+    if count == 0 {
+        (min, max) = ([0.0; 3], [0.0; 3]);
+    }
+    let v = view(&mut bin, positions.iter().flatten().flat_map(|v| v.to_le_bytes()).collect(), ARRAY_BUFFER);
+    accessors.push(format!(
+        r#"{{"bufferView":{},"componentType":{},"count":{},"type":"VEC3","min":[{},{},{}],"max":[{},{},{}]}}"#,
+        v, FLOAT, count, min[0], min[1], min[2], max[0], max[1], max[2]
+    ));
+
+    let normals = mesh.normals.iter().flat_map(components).flat_map(|v| v.to_le_bytes()).collect();
+    let v = view(&mut bin, normals, ARRAY_BUFFER);
+    accessors.push(format!(r#"{{"bufferView":{},"componentType":{},"count":{},"type":"VEC3"}}"#, v, FLOAT, count));
+
+    let indices = mesh.triangles.iter().flatten().flat_map(|i| i.to_le_bytes()).collect();
+    let v = view(&mut bin, indices, ELEMENT_ARRAY_BUFFER);
+    accessors.push(format!(
+        r#"{{"bufferView":{},"componentType":{},"count":{},"type":"SCALAR"}}"#,
+        v, UNSIGNED_INT, 3 * mesh.triangles.len()
+    ));
+
+    let mut attributes = r#""POSITION":0,"NORMAL":1"#.to_string();
+    if let Some(colours) = colours {
+        let rgba = colours.iter().flat_map(|c| {
+            let [r, g, b] = to_rgb(c).0;
+            [r, g, b, 255]
+        }).collect();
+        let v = view(&mut bin, rgba, ARRAY_BUFFER);
+        accessors.push(format!(
+            r#"{{"bufferView":{},"componentType":{},"normalized":true,"count":{},"type":"VEC4"}}"#,
+            v, UNSIGNED_BYTE, count
+        ));
+        attributes += r#","COLOR_0":3"#;
+    }
+
+    let mut json = format!(
+        concat!(
+            r#"{{"asset":{{"version":"2.0","generator":"sdf-rs"}},"scene":0,"scenes":[{{"nodes":[0]}}],"#,
+            r#""nodes":[{{"mesh":0}}],"meshes":[{{"primitives":[{{"attributes":{{{}}},"indices":2,"mode":4}}]}}],"#,
+            r#""buffers":[{{"byteLength":{}}}],"bufferViews":[{}],"accessors":[{}]}}"#
+        ),
+        attributes, bin.len(), views.join(","), accessors.join(",")
+    ).into_bytes();
+
+    // Chunks are padded to 4 bytes, JSON with spaces and binary with zeros
+    while json.len() % 4 != 0 {
+        json.push(b' ');
+    }
+    while bin.len() % 4 != 0 {
+        bin.push(0);
+    }
+
+    let length = 12 + 8 + json.len() + 8 + bin.len();
+    w.write_all(b"glTF")?;
+    w.write_all(&2u32.to_le_bytes())?;
+    w.write_all(&(length as u32).to_le_bytes())?;
+    w.write_all(&(json.len() as u32).to_le_bytes())?;
+    w.write_all(b"JSON")?;
+    w.write_all(&json)?;
+    w.write_all(&(bin.len() as u32).to_le_bytes())?;
+    w.write_all(b"BIN\0")?;
+    w.write_all(&bin)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use crate::mesh::{marching_cubes, Grid};
+    use crate::node::Sphere;
+    use crate::point::Point3;
+    use crate::real::Float;
+
+    // Each format is read back by as little parser as it takes to check what
+    // was written
+
+    fn ball() -> Mesh {
+        let grid = Grid::new(&Point3::new(-1.5, -1.5, -1.5), &Point3::new(1.5, 1.5, 1.5), 6);
+        marching_cubes(&Sphere { radius: 1.0 }, &grid)
+    }
+
+    // Made up, but different for every vertex
+    fn colours(mesh: &Mesh) -> Vec<Colour> {
+        (0..mesh.vertices.len()).map(|i| Vector3::new((i % 7) as Float / 6.0, (i % 5) as Float / 4.0, 0.5)).collect()
+    }
+
+    fn written(format: Format, mesh: &Mesh, colours: &[Colour]) -> Vec<u8> {
+        let mut bytes = vec![];
+        format.write(mesh, colours, &mut bytes).unwrap();
+        bytes
+    }
+
+    fn close(a: &Vector3, b: &Vector3) -> bool {
+        (*a - *b).mag() < 1e-5
+    }
+
+    fn same_shape(expected: &Mesh, actual: &Mesh) {
+        assert_eq!(expected.triangles, actual.triangles);
+        assert_eq!(expected.vertices.len(), actual.vertices.len());
+        for (a, b) in expected.vertices.iter().zip(&actual.vertices) {
+            assert!(close(&a.to_vector(), &b.to_vector()), "{:?} {:?}", a, b);
+        }
+        for (a, b) in expected.normals.iter().zip(&actual.normals) {
+            assert!(close(a, b), "{:?} {:?}", a, b);
+        }
+    }
+
+    struct Reader<'a>(&'a [u8]);
+
+    impl<'a> Reader<'a>
+    {
+        fn bytes(&mut self, n: usize) -> &'a [u8] {
+            let (head, tail) = self.0.split_at(n);
+            self.0 = tail;
+            head
+        }
+
+        fn u32(&mut self) -> u32 {
+            u32::from_le_bytes(self.bytes(4).try_into().unwrap())
+        }
+
+        fn f32(&mut self) -> Float {
+            f32::from_le_bytes(self.bytes(4).try_into().unwrap()) as Float
+        }
+
+        fn vector(&mut self) -> Vector3 {
+            Vector3::new(self.f32(), self.f32(), self.f32())
+        }
+
+        fn line(&mut self) -> String {
+            let end = self.0.iter().position(|&b| b == b'\n').unwrap();
+            let line = String::from_utf8(self.bytes(end).to_vec()).unwrap();
+            self.bytes(1);
+            line
+        }
+    }
+
+    fn point(v: Vector3) -> Point3 {
+        Point3::new(v.x, v.y, v.z)
+    }
+
+    fn numbers(words: &[&str]) -> Vector3 {
+        let v: Vec<Float> = words.iter().map(|w| w.parse().unwrap()).collect();
+        Vector3::new(v[0], v[1], v[2])
+    }
+
+    fn read_obj(text: &str) -> Mesh {
+        let mut mesh = Mesh::new();
+        for line in text.lines() {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words[0] {
+                "v" => mesh.vertices.push(point(numbers(&words[1..]))),
+                "vn" => mesh.normals.push(numbers(&words[1..])),
+                "f" => {
+                    let corner = |w: &str| {
+                        let (v, n) = w.split_once("//").unwrap();
+                        assert_eq!(v, n);
+                        v.parse::<u32>().unwrap() - 1
+                    };
+                    mesh.triangles.push([corner(words[1]), corner(words[2]), corner(words[3])]);
+                }
+                _ => assert!(words[0].starts_with('#')),
+            }
+        }
+        mesh
+    }
+
+    // Facet normals and corners
+    fn read_stl(bytes: &[u8]) -> Vec<(Vector3, [Point3; 3])> {
+        let mut r = Reader(bytes);
+        r.bytes(80);
+        let count = r.u32() as usize;
+        assert_eq!(84 + 50 * count, bytes.len());
+        (0..count).map(|_| {
+            let normal = r.vector();
+            let corners = [r.vector(), r.vector(), r.vector()].map(point);
+            r.bytes(2);
+            (normal, corners)
+        }).collect()
+    }
+
+    fn read_stl_ascii(text: &str) -> Vec<(Vector3, [Point3; 3])> {
+        let mut lines = text.lines().map(|l| l.split_whitespace().collect::<Vec<_>>());
+        assert_eq!(vec!["solid", "sdf-rs"], lines.next().unwrap());
+        let mut facets = vec![];
+        loop {
+            let line = lines.next().unwrap();
+            if line[0] == "endsolid" {
+                break;
+            }
+            assert_eq!(["facet", "normal"], line[..2]);
+            let normal = numbers(&line[2..]);
+            assert_eq!(vec!["outer", "loop"], lines.next().unwrap());
+            let corners = [(); 3].map(|_| {
+                let line = lines.next().unwrap();
+                assert_eq!("vertex", line[0]);
+                point(numbers(&line[1..]))
+            });
+            assert_eq!(vec!["endloop"], lines.next().unwrap());
+            assert_eq!(vec!["endfacet"], lines.next().unwrap());
+            facets.push((normal, corners));
+        }
+        assert!(lines.next().is_none());
+        facets
+    }
+
+    fn read_ply(bytes: &[u8]) -> (Mesh, Vec<[u8; 3]>) {
+        let mut r = Reader(bytes);
+        assert_eq!("ply", r.line());
+        assert_eq!("format binary_little_endian 1.0", r.line());
+        let (mut vertices, mut faces, mut properties) = (0, 0, vec![]);
+        loop {
+            let line = r.line();
+            let words: Vec<&str> = line.split_whitespace().collect();
+            match words[..] {
+                ["end_header"] => break,
+                ["element", "vertex", n] => vertices = n.parse().unwrap(),
+                ["element", "face", n] => faces = n.parse().unwrap(),
+                ["property", "list", "uchar", "uint", "vertex_indices"] => (),
+                ["property", kind, name] => properties.push((kind.to_string(), name.to_string())),
+                _ => assert_eq!("comment", words[0]),
+            }
+        }
+
+        let mut mesh = Mesh::new();
+        let mut colours = vec![];
+        for _ in 0..vertices {
+            let mut values = HashMap::new();
+            for (kind, name) in &properties {
+                let value = match kind.as_str() {
+                    "float" => r.f32(),
+                    "uchar" => r.bytes(1)[0] as Float,
+                    _ => panic!("Unexpected property type {}", kind),
+                };
+                values.insert(name.as_str(), value);
+            }
+            let get = |names: [&str; 3]| Vector3::new(values[names[0]], values[names[1]], values[names[2]]);
+            mesh.vertices.push(point(get(["x", "y", "z"])));
+            mesh.normals.push(get(["nx", "ny", "nz"]));
+            if values.contains_key("red") {
+                colours.push(["red", "green", "blue"].map(|c| values[c] as u8));
+            }
+        }
+        for _ in 0..faces {
+            assert_eq!(3, r.bytes(1)[0]);
+            mesh.triangles.push([r.u32(), r.u32(), r.u32()]);
+        }
+        assert!(r.0.is_empty());
+        (mesh, colours)
+    }
+
+    #[derive(Debug, PartialEq)]
+    enum Json
+    {
+        Null,
+        Bool(bool),
+        Number(f64),
+        String(String),
+        Array(Vec<Json>),
+        Object(HashMap<String, Json>),
+    }
+
+    impl Json
+    {
+        fn parse(text: &str) -> Json {
+            let mut chars = text.trim_end().chars().peekable();
+            let value = Json::value(&mut chars);
+            assert!(chars.next().is_none());
+            value
+        }
+
+        fn value(chars: &mut std::iter::Peekable<std::str::Chars>) -> Json {
+            let word = |chars: &mut std::iter::Peekable<std::str::Chars>, w: &str, value| {
+                for expected in w.chars() {
+                    assert_eq!(Some(expected), chars.next());
+                }
+                value
+            };
+            match chars.peek() {
+                Some('n') => word(chars, "null", Json::Null),
+                Some('t') => word(chars, "true", Json::Bool(true)),
+                Some('f') => word(chars, "false", Json::Bool(false)),
+                Some('"') => Json::String(Json::string(chars)),
+                Some('[') => {
+                    chars.next();
+                    let mut items = vec![];
+                    while chars.peek() != Some(&']') {
+                        items.push(Json::value(chars));
+                        if chars.peek() == Some(&',') {
+                            chars.next();
+                        }
+                    }
+                    chars.next();
+                    Json::Array(items)
+                }
+                Some('{') => {
+                    chars.next();
+                    let mut members = HashMap::new();
+                    while chars.peek() != Some(&'}') {
+                        let key = Json::string(chars);
+                        assert_eq!(Some(':'), chars.next());
+                        members.insert(key, Json::value(chars));
+                        if chars.peek() == Some(&',') {
+                            chars.next();
+                        }
+                    }
+                    chars.next();
+                    Json::Object(members)
+                }
+                _ => {
+                    let mut number = String::new();
+                    while let Some(&c) = chars.peek().filter(|c| "+-.eE0123456789".contains(**c)) {
+                        number.push(c);
+                        chars.next();
+                    }
+                    Json::Number(number.parse().unwrap())
+                }
+            }
+        }
+
+        // No escapes, which glTF's names don't need
+        fn string(chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+            assert_eq!(Some('"'), chars.next());
+            chars.by_ref().take_while(|&c| c != '"').collect()
+        }
+
+        fn get(&self, key: &str) -> &Json {
+            match self {
+                Json::Object(members) => &members[key],
+                _ => panic!("Not an object"),
+            }
+        }
+
+        fn at(&self, i: usize) -> &Json {
+            match self {
+                Json::Array(items) => &items[i],
+                _ => panic!("Not an array"),
+            }
+        }
+
+        fn number(&self) -> usize {
+            match self {
+                Json::Number(n) => *n as usize,
+                _ => panic!("Not a number"),
+            }
+        }
+    }
+
+    fn read_glb(bytes: &[u8]) -> (Mesh, Vec<[u8; 4]>) {
+        let mut r = Reader(bytes);
+        assert_eq!(b"glTF", r.bytes(4));
+        assert_eq!(2, r.u32());
+        assert_eq!(bytes.len(), r.u32() as usize);
+        let length = r.u32() as usize;
+        assert_eq!(b"JSON", r.bytes(4));
+        let json = Json::parse(std::str::from_utf8(r.bytes(length)).unwrap());
+        let length = r.u32() as usize;
+        assert_eq!(b"BIN\0", r.bytes(4));
+        let bin = r.bytes(length);
+        assert!(r.0.is_empty());
+
+        assert_eq!(&Json::String("2.0".to_string()), json.get("asset").get("version"));
+        assert!(json.get("buffers").at(0).get("byteLength").number() <= bin.len());
+        let primitive = json.get("meshes").at(0).get("primitives").at(0);
+        assert_eq!(4, primitive.get("mode").number());
+
+        // The accessor's data, checking its type
+        let data = |accessor: usize, component: u32, kind: &str| {
+            let accessor = json.get("accessors").at(accessor);
+            assert_eq!(component as usize, accessor.get("componentType").number());
+            assert_eq!(&Json::String(kind.to_string()), accessor.get("type"));
+            let view = json.get("bufferViews").at(accessor.get("bufferView").number());
+            let start = view.get("byteOffset").number();
+            let size = [("SCALAR", 1), ("VEC3", 3), ("VEC4", 4)].iter().find(|k| k.0 == kind).unwrap().1
+                * if component == UNSIGNED_BYTE { 1 } else { 4 };
+            let count = accessor.get("count").number();
+            assert_eq!(count * size, view.get("byteLength").number());
+            (Reader(&bin[start..start + count * size]), count)
+        };
+        let attributes = primitive.get("attributes");
+
+        let mut mesh = Mesh::new();
+        let (mut positions, count) = data(attributes.get("POSITION").number(), FLOAT, "VEC3");
+        mesh.vertices = (0..count).map(|_| point(positions.vector())).collect();
+        let (mut normals, count) = data(attributes.get("NORMAL").number(), FLOAT, "VEC3");
+        mesh.normals = (0..count).map(|_| normals.vector()).collect();
+        let (mut indices, count) = data(primitive.get("indices").number(), UNSIGNED_INT, "SCALAR");
+        mesh.triangles = (0..count / 3).map(|_| [indices.u32(), indices.u32(), indices.u32()]).collect();
+
+        let mut colours = vec![];
+        if let Json::Object(members) = attributes {
+            if let Some(colour) = members.get("COLOR_0") {
+                let (mut rgba, count) = data(colour.number(), UNSIGNED_BYTE, "VEC4");
+                colours = (0..count).map(|_| rgba.bytes(4).try_into().unwrap()).collect();
+            }
+        }
+
+        // Bounds, which POSITION has to have
+        let position = json.get("accessors").at(attributes.get("POSITION").number());
+        for (i, p) in mesh.vertices.iter().enumerate() {
+            let [x, y, z] = components(&p.to_vector());
+            let bound = |key, axis: usize| match position.get(key).at(axis) {
+                Json::Number(n) => *n as f32,
+                _ => panic!("Bounds aren't numbers"),
+            };
+            for (axis, v) in [x, y, z].into_iter().enumerate() {
+                assert!(bound("min", axis) <= v && v <= bound("max", axis), "{} {:?}", i, p);
+            }
+        }
+        (mesh, colours)
+    }
+
+    fn facets_match(mesh: &Mesh, facets: &[(Vector3, [Point3; 3])]) {
+        assert_eq!(mesh.triangles.len(), facets.len());
+        for (t, (normal, corners)) in mesh.triangles.iter().zip(facets) {
+            assert!(close(&mesh.face_normal(t), normal));
+            for (&i, corner) in t.iter().zip(corners) {
+                assert!(close(&mesh.vertices[i as usize].to_vector(), &corner.to_vector()));
+            }
+        }
+    }
+
+    #[test]
+    fn formats() {
+        assert_eq!(Format::Stl, Format::parse("stl").unwrap());
+        assert_eq!(Format::StlAscii, Format::parse("stl-ascii").unwrap());
+        assert!(Format::parse("gltf").is_err());
+        assert_eq!(Format::Obj, Format::from_path("out/scene.obj").unwrap());
+        assert_eq!(Format::Glb, Format::from_path("scene.GLB").unwrap());
+        assert_eq!(Format::Stl, Format::from_path("scene.stl").unwrap());
+        assert!(Format::from_path("scene").is_err());
+        assert!(Format::from_path("scene.3mf").is_err());
+    }
+
+    #[test]
+    fn obj_round_trip() {
+        let mesh = ball();
+        let text = String::from_utf8(written(Format::Obj, &mesh, &[])).unwrap();
+        same_shape(&mesh, &read_obj(&text));
+    }
+
+    #[test]
+    fn stl_round_trip() {
+        let mesh = ball();
+        let bytes = written(Format::Stl, &mesh, &[]);
+        facets_match(&mesh, &read_stl(&bytes));
+        let text = String::from_utf8(written(Format::StlAscii, &mesh, &[])).unwrap();
+        facets_match(&mesh, &read_stl_ascii(&text));
+    }
+
+    #[test]
+    fn ply_round_trip() {
+        let mesh = ball();
+        let colours = colours(&mesh);
+        let (read, read_colours) = read_ply(&written(Format::Ply, &mesh, &colours));
+        same_shape(&mesh, &read);
+        assert_eq!(colours.iter().map(|c| to_rgb(c).0).collect::<Vec<_>>(), read_colours);
+
+        // Without colours, there are none
+        let (read, read_colours) = read_ply(&written(Format::Ply, &mesh, &[]));
+        same_shape(&mesh, &read);
+        assert!(read_colours.is_empty());
+    }
+
+    #[test]
+    fn glb_round_trip() {
+        let mesh = ball();
+        let colours = colours(&mesh);
+        let bytes = written(Format::Glb, &mesh, &colours);
+        assert_eq!(0, bytes.len() % 4);
+        let (read, read_colours) = read_glb(&bytes);
+        same_shape(&mesh, &read);
+        let expected: Vec<[u8; 4]> = colours.iter().map(|c| {
+            let [r, g, b] = to_rgb(c).0;
+            [r, g, b, 255]
+        }).collect();
+        assert_eq!(expected, read_colours);
+
+        let (read, read_colours) = read_glb(&written(Format::Glb, &mesh, &[]));
+        same_shape(&mesh, &read);
+        assert!(read_colours.is_empty());
+    }
+
+    #[test]
+    fn empty_meshes() {
+        let empty = Mesh::new();
+        for format in [Format::Obj, Format::Stl, Format::StlAscii, Format::Ply, Format::Glb] {
+            assert!(!written(format, &empty, &[]).is_empty());
+        }
+        assert_eq!(Mesh::new(), read_glb(&written(Format::Glb, &empty, &[])).0);
+        assert!(read_stl(&written(Format::Stl, &empty, &[])).is_empty());
+    }
+
+    #[test]
+    fn colours_must_match() {
+        let mesh = ball();
+        let mut bytes = vec![];
+        assert!(write_ply(&mesh, &colours(&mesh)[1..], &mut bytes).is_err());
+        assert!(write_glb(&mesh, &[Vector3::zero()], &mut bytes).is_err());
+    }
+
+    #[test]
+    fn save_to_file() {
+        let path = std::env::temp_dir().join(format!("sdf-rs-export-{}.obj", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mesh = ball();
+        save(path, Format::from_path(path).unwrap(), &mesh, &[]).unwrap();
+        let text = std::fs::read_to_string(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+        same_shape(&mesh, &read_obj(&text));
+        assert!(save("/nonexistent/dir/mesh.obj", Format::Obj, &mesh, &[]).is_err());
+    }
+}
@@ -0,0 +1,228 @@
+// GLSL-style free functions, so distance functions from shadertoy and the
+// like can be ported line by line. They work on scalars and on Vector2 and
+// Vector3, component-wise, and like GLSL take scalars wherever a vector
+// argument could be a splat (`max(q, 0.0)`).
+
+use crate::point::{Vector2, Vector3};
+use crate::real::Real;
+
+pub trait GenType: Copy
+{
+    type Scalar: Real;
+
+    fn map(self, f: impl Fn(Self::Scalar) -> Self::Scalar) -> Self;
+    fn zip(self, other: Self, f: impl Fn(Self::Scalar, Self::Scalar) -> Self::Scalar) -> Self;
+    fn sum(self) -> Self::Scalar;
+}
+
+impl<T: Real> GenType for T
+{
+    type Scalar = T;
+
+    fn map(self, f: impl Fn(T) -> T) -> T {
+        f(self)
+    }
+
+    fn zip(self, other: T, f: impl Fn(T, T) -> T) -> T {
+        f(self, other)
+    }
+
+    fn sum(self) -> T {
+        self
+    }
+}
+
+impl<T: Real> GenType for Vector2<T>
+{
+    type Scalar = T;
+
+    fn map(self, f: impl Fn(T) -> T) -> Self {
+        Vector2::new(f(self.x), f(self.y))
+    }
+
+    fn zip(self, other: Self, f: impl Fn(T, T) -> T) -> Self {
+        Vector2::new(f(self.x, other.x), f(self.y, other.y))
+    }
+
+    fn sum(self) -> T {
+        self.x + self.y
+    }
+}
+
+impl<T: Real> GenType for Vector3<T>
+{
+    type Scalar = T;
+
+    fn map(self, f: impl Fn(T) -> T) -> Self {
+        Vector3::new(f(self.x), f(self.y), f(self.z))
+    }
+
+    fn zip(self, other: Self, f: impl Fn(T, T) -> T) -> Self {
+        Vector3::new(f(self.x, other.x), f(self.y, other.y), f(self.z, other.z))
+    }
+
+    fn sum(self) -> T {
+        self.x + self.y + self.z
+    }
+}
+
+fn c<V: GenType>(v: f64) -> V::Scalar {
+    V::Scalar::from_f64(v)
+}
+
+pub fn abs<V: GenType>(x: V) -> V {
+    x.map(Real::abs)
+}
+
+pub fn min<V: GenType>(x: V, y: impl Into<V>) -> V {
+    x.zip(y.into(), Real::min)
+}
+
+pub fn max<V: GenType>(x: V, y: impl Into<V>) -> V {
+    x.zip(y.into(), Real::max)
+}
+
+pub fn clamp<V: GenType>(x: V, lo: impl Into<V>, hi: impl Into<V>) -> V {
+    min(max(x, lo), hi)
+}
+
+pub fn floor<V: GenType>(x: V) -> V {
+    x.map(Real::floor)
+}
+
+pub fn fract<V: GenType>(x: V) -> V {
+    x.map(|v| v - v.floor())
+}
+
+// Linear interpolation, x at a = 0 and y at a = 1
+pub fn mix<V: GenType>(x: V, y: V, a: impl Into<V>) -> V {
+    let a = a.into();
+    x.zip(a, |x, a| x * (c::<V>(1.0) - a)).zip(y.zip(a, |y, a| y * a), |l, r| l + r)
+}
+
+// 0 below the edge, 1 at or above it
+pub fn step<V: GenType>(edge: impl Into<V>, x: V) -> V {
+    edge.into().zip(x, |e, x| if x < e { c::<V>(0.0) } else { c::<V>(1.0) })
+}
+
+// Hermite interpolation from 0 at e0 to 1 at e1
+pub fn smoothstep<V: GenType>(e0: impl Into<V>, e1: impl Into<V>, x: V) -> V {
+    let (e0, e1) = (e0.into(), e1.into());
+    let t = x.zip(e0, |x, e| x - e).zip(e1.zip(e0, |a, b| a - b), |n, d| n / d);
+    t.map(|t| {
+        let t = t.max(c::<V>(0.0)).min(c::<V>(1.0));
+        t * t * (c::<V>(3.0) - c::<V>(2.0) * t)
+    })
+}
+
+pub fn dot<V: GenType>(x: V, y: V) -> V::Scalar {
+    x.zip(y, |a, b| a * b).sum()
+}
+
+pub fn length<V: GenType>(x: V) -> V::Scalar {
+    dot(x, x).sqrt()
+}
+
+pub fn distance<V: GenType>(x: V, y: V) -> V::Scalar {
+    length(x.zip(y, |a, b| a - b))
+}
+
+pub fn normalize<V: GenType>(x: V) -> V {
+    let l = length(x);
+    x.map(|v| v / l)
+}
+
+pub fn cross<T: Real>(x: Vector3<T>, y: Vector3<T>) -> Vector3<T> {
+    x.cross_product(&y)
+}
+
+// Reflect incident vector i about the surface normal n (unit length)
+pub fn reflect<T: Real>(i: Vector3<T>, n: Vector3<T>) -> Vector3<T> {
+    i - n * (T::from_float(2.0) * dot(n, i))
+}
+
+// Refract unit incident vector i through a surface with unit normal n and
+// ratio of refractive indices eta; zero on total internal reflection
+pub fn refract<T: Real>(i: Vector3<T>, n: Vector3<T>, eta: T) -> Vector3<T> {
+    let one = T::from_float(1.0);
+    let d = dot(n, i);
+    let k = one - eta * eta * (one - d * d);
+    if k < T::from_float(0.0) {
+        Vector3::zero()
+    } else {
+        i * eta - n * (eta * d + k.sqrt())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::real::Float;
+
+    #[test]
+    fn scalars() {
+        assert_eq!(2.0, max(-1.0, 2.0));
+        assert_eq!(0.5, clamp(0.5, 0.0, 1.0));
+        assert_eq!(1.0, clamp(3.0, 0.0, 1.0));
+        assert_eq!(0.25, fract(2.25));
+        assert_eq!(0.75, fract(-1.25));
+        assert_eq!(2.5, mix(2.0, 3.0, 0.5));
+        assert_eq!(0.0, step(1.0, 0.5));
+        assert_eq!(1.0, step(1.0, 1.0));
+        assert_eq!(0.5, smoothstep(0.0, 2.0, 1.0));
+        assert_eq!(0.0, smoothstep(0.0, 2.0, -1.0));
+        assert_eq!(1.0, smoothstep(0.0, 2.0, 5.0));
+        assert_eq!(3.0, length(-3.0));
+    }
+
+    #[test]
+    fn vectors() {
+        let v = Vector3::new(-1.0, 0.5, 2.0);
+        assert_eq!(Vector3::new(0.0, 0.5, 2.0), max(v, 0.0));
+        assert_eq!(Vector3::new(-1.0, 0.0, 0.0), min(v, 0.0));
+        assert_eq!(Vector3::new(0.0, 0.5, 1.0), clamp(v, 0.0, 1.0));
+        assert_eq!(Vector3::new(1.0, 0.5, 2.0), abs(v));
+        assert_eq!(Vector3::new(0.0, 0.5, 0.0), fract(v));
+        assert_eq!(Vector3::new(0.0, 1.0, 1.0), step(0.0, v));
+        assert_eq!(Vector3::new(0.0, 1.0, 0.0), step(Vector3::new(0.0, 0.5, 3.0), v));
+        assert_eq!(Vector2::new(1.5, 2.0), mix(Vector2::new(1.0, 2.0), Vector2::new(2.0, 2.0), 0.5));
+        assert_eq!(Vector2::new(1.0, 2.0), mix(Vector2::new(1.0, 2.0), Vector2::new(2.0, 4.0), Vector2::new(0.0, 0.0)));
+        assert_eq!(5.0, length(Vector2::new(3.0, 4.0)));
+        assert_eq!(5.0, distance(Vector2::new(3.0, 4.0), Vector2::splat(0.0)));
+        assert_eq!(Vector3::new(0.0, 0.0, 1.0), normalize(Vector3::new(0.0, 0.0, 5.0)));
+        assert_eq!(Vector3::new(0.0, 0.0, 1.0), cross(Vector3::new(1.0, 0.0, 0.0), Vector3::new(0.0, 1.0, 0.0)));
+    }
+
+    #[test]
+    fn reflection_and_refraction() {
+        let n = Vector3::new(0.0, 1.0, 0.0);
+        let i = Vector3::new(1.0, -1.0, 0.0).normalized();
+        assert_eq!(Vector3::new(i.x, -i.y, 0.0), reflect(i, n));
+
+        // Straight through with matched indices
+        let r = refract(i, n, 1.0);
+        assert!(length(r - i) < 1e-12);
+
+        // Bent towards the normal going into a denser medium
+        let r = refract(i, n, 1.0 / 1.5);
+        assert!(Float::abs(length(r) - 1.0) < 1e-12);
+        assert!(r.x < i.x);
+
+        // Total internal reflection going out at a shallow angle
+        let shallow = Vector3::new(1.0, -0.1, 0.0).normalized();
+        assert_eq!(Vector3::zero(), refract(shallow, n, 1.5));
+    }
+
+    #[test]
+    fn shadertoy_box() {
+        // iq's sdBox, as written in GLSL:
+        //   vec3 q = abs(p) - b;
+        //   return length(max(q,0.0)) + min(max(q.x,max(q.y,q.z)),0.0);
+        let sd_box = |p: Vector3, b: Vector3| {
+            let q = abs(p) - b;
+            length(max(q, 0.0)) + min(max(q.x, max(q.y, q.z)), 0.0)
+        };
+        assert_eq!(1.0, sd_box(Vector3::new(2.0, 0.0, 0.0), Vector3::splat(1.0)));
+        assert_eq!(-0.5, sd_box(Vector3::new(0.5, 0.0, 0.0), Vector3::splat(1.0)));
+    }
+}
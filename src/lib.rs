@@ -0,0 +1,585 @@
+#[macro_use]
+mod ops;
+#[cfg(all(feature = "simd", target_arch = "x86_64"))]
+mod simd;
+pub mod vector;
+pub mod point;
+pub mod glsl;
+pub mod real;
+pub mod dual;
+pub mod matrix;
+pub mod quat;
+pub mod camera;
+pub mod packet;
+pub mod node;
+pub mod transform;
+pub mod repeat;
+pub mod deform;
+pub mod symmetry;
+pub mod noise;
+pub mod terrain;
+pub mod texture;
+pub mod environment;
+pub mod media;
+pub mod material;
+pub mod mesh;
+pub mod contour;
+pub mod export;
+pub mod march;
+pub mod normal;
+pub mod debug;
+pub mod options;
+#[cfg(test)]
+mod testing;
+
+use matrix::Mat4;
+use point::{Point3, Vector3};
+use real::{Float, Real};
+use march::{cast_ray, cast_scene_packet, CastResult};
+use normal::calc_normal;
+use options::Options;
+use camera::Orbit;
+use glsl::{abs, length, max};
+use node::{SmoothUnion, Union};
+use transform::Transform;
+use repeat::{Repeat, RepeatPolar};
+use deform::{Bend, Displace, Displacement, Elongate, Onion, Round, Taper, Twist};
+use symmetry::{Kaleidoscope, Mirror, Polyhedral, Symmetric};
+use noise::{Fractal, Perlin};
+use terrain::{NoiseHeights, Terrain};
+use texture::{to_rgb, Checker, Colour, Mapped, Mapping, Texture};
+use media::Lighting;
+use mesh::Mesh;
+use material::{random_walk, Material, Rng, Subsurface, SubsurfaceMode};
+use anyhow::Result;
+
+// Anything that can be rendered. Generic over the scalar so the same
+// function gives distances on floats and gradients on dual numbers.
+pub trait DistanceField {
+    fn distance<T: Real>(&self, p: &Point3<T>) -> T;
+
+    // How much faster than 1 the distance can change from point to point.
+    // Above 1 the field overestimates, and marching scales its steps down by
+    // this to stay on the near side of the surface.
+    fn lipschitz(&self) -> Float {
+        1.0
+    }
+
+    // Place in the world by `matrix`; fails if it can't be inverted
+    fn transformed(self, matrix: &Mat4) -> Result<Transform<Self>>
+    where
+        Self: Sized,
+    {
+        Transform::new(self, matrix)
+    }
+
+    // Copies `period` apart along each axis with a non-zero period
+    fn repeat(self, period: &Vector3) -> Repeat<Self>
+    where
+        Self: Sized,
+    {
+        Repeat::new(self, period)
+    }
+
+    // Copies around the y axis
+    fn repeat_polar(self, count: u32) -> RepeatPolar<Self>
+    where
+        Self: Sized,
+    {
+        RepeatPolar::new(self, count)
+    }
+
+    // See `deform` for what `extent` is
+    fn twist(self, rate: Float, extent: Float) -> Twist<Self>
+    where
+        Self: Sized,
+    {
+        Twist::new(self, rate, extent)
+    }
+
+    fn bend(self, rate: Float, extent: Float) -> Bend<Self>
+    where
+        Self: Sized,
+    {
+        Bend::new(self, rate, extent)
+    }
+
+    fn taper(self, rate: Float, extent: Float) -> Taper<Self>
+    where
+        Self: Sized,
+    {
+        Taper::new(self, rate, extent)
+    }
+
+    fn elongate(self, size: &Vector3) -> Elongate<Self>
+    where
+        Self: Sized,
+    {
+        Elongate::new(self, size)
+    }
+
+    fn rounded(self, radius: Float) -> Round<Self>
+    where
+        Self: Sized,
+    {
+        Round { node: self, radius }
+    }
+
+    fn onion(self, thickness: Float) -> Onion<Self>
+    where
+        Self: Sized,
+    {
+        Onion { node: self, thickness }
+    }
+
+    fn displace<D: Displacement>(self, displacement: D) -> Displace<Self, D>
+    where
+        Self: Sized,
+    {
+        Displace { node: self, displacement }
+    }
+
+    // Mirrored across the plane through `offset * normal`, modelling the
+    // side the normal points to; fails if the normal is zero
+    fn mirror(self, normal: &Vector3, offset: Float) -> Result<Mirror<Self>>
+    where
+        Self: Sized,
+    {
+        Mirror::new(self, normal, offset)
+    }
+
+    // Mirrored across the coordinate planes with `axes` set
+    fn symmetric(self, axes: [bool; 3]) -> Symmetric<Self>
+    where
+        Self: Sized,
+    {
+        Symmetric::new(self, axes)
+    }
+
+    fn kaleidoscope(self, group: Polyhedral) -> Kaleidoscope<Self>
+    where
+        Self: Sized,
+    {
+        Kaleidoscope { node: self, group }
+    }
+
+    fn union<B: DistanceField>(self, other: B) -> Union<Self, B>
+    where
+        Self: Sized,
+    {
+        Union(self, other)
+    }
+
+    fn smooth_union<B: DistanceField>(self, other: B, k: Float) -> SmoothUnion<Self, B>
+    where
+        Self: Sized,
+    {
+        SmoothUnion(self, other, k)
+    }
+}
+
+impl<F: DistanceField> DistanceField for &F {
+    fn distance<T: Real>(&self, p: &Point3<T>) -> T {
+        (*self).distance(p)
+    }
+
+    fn lipschitz(&self) -> Float {
+        (*self).lipschitz()
+    }
+}
+
+// The demo scene in `sdf()`
+pub struct Scene;
+
+impl DistanceField for Scene {
+    fn distance<T: Real>(&self, p: &Point3<T>) -> T {
+        sdf(p)
+    }
+
+    fn lipschitz(&self) -> Float {
+        ground().lipschitz()
+    }
+}
+
+// The demo scene without its ground, for marching separately from it
+pub struct Objects;
+
+impl DistanceField for Objects {
+    fn distance<T: Real>(&self, p: &Point3<T>) -> T {
+        objects(p)
+    }
+}
+
+// Move into the space of an object centred on `v`
+fn translate<T: Real>(position: &Point3<T>, v: &Vector3) -> Point3<T> {
+    *position - v.lift()
+}
+
+// Move into the space of an object placed by some transform, given the
+// inverse of that transform. Rotations and translations keep the distance
+// exact; scales and shears don't.
+pub fn transform<T: Real>(position: &Point3<T>, inverse: &Mat4) -> Point3<T> {
+    inverse.cast::<T>().transform_point(position)
+}
+
+pub fn sphere<T: Real>(radius: Float, p: &Point3<T>) -> T {
+    p.to_vector().mag() - T::from_float(radius)
+}
+
+pub fn cuboid<T: Real>(dimensions: &Vector3, p: &Point3<T>) -> T {
+    let q = abs(p.to_vector()) - dimensions.lift();
+    length(max(q, T::from_float(0.0))) - T::from_float(0.1)
+}
+
+// As used by Media Molecule, apparently
+pub fn smooth_union<T: Real>(d1: T, d2: T, k: Float) -> T {
+    let k = T::from_float(k);
+    let h = (k - (d1 - d2).abs()).max(T::from_float(0.0)) / k;
+    d1.min(d2) - h * h * k * T::from_float(1.0 / 4.0)
+}
+
+pub fn union<T: Real>(d1: T, d2: T) -> T {
+    d1.min(d2)
+}
+
+// The rolling ground the demo scene stands on, hills about half a unit
+// either side of y = -5
+pub fn ground() -> Terrain<NoiseHeights<Perlin>> {
+    let heights = NoiseHeights::new(Fractal::fbm(Perlin::new(1)), 0.5, 0.1);
+    Terrain { base: -5.0, ..Terrain::new(heights) }
+}
+
+// The pair of spheres on the left, made of wax
+fn wax<T: Real>(position: &Point3<T>) -> T {
+    smooth_union(
+        sphere(1.0, &translate(position, &Vector3::new(-3.0, -1.0, 0.0))),
+        sphere(1.0, &translate(position, &Vector3::new(-2.0, 1.0, 0.0))),
+        1.5
+    )
+}
+
+// Everything in the demo scene but the ground
+fn objects<T: Real>(position: &Point3<T>) -> T {
+    union(
+        union(
+            wax(position),
+            smooth_union(
+                sphere(1.0, &translate(position, &Vector3::new(2.0, -1.0, 0.0))),
+                sphere(1.0, &translate(position, &Vector3::new(3.0, 1.0, 0.0))),
+                2.0
+            )
+        ),
+        smooth_union(
+            cuboid(&Vector3::new(0.5, 0.5, 0.5), position),
+            cuboid(&Vector3::new(1.0, 1.0, 1.0), &translate(position, &Vector3::new(1.0, 1.0, 1.0))),
+            1.0
+        )
+    )
+}
+
+pub fn sdf<T: Real>(position: &Point3<T>) -> T {
+    union(objects(position), ground().distance(position))
+}
+
+// Materials in the demo scene: a checker floor, one square per unit, a wax
+// pair of spheres on the left, and everything else white
+pub fn scene_material(position: &Point3, normal: &Vector3) -> Material {
+    let d = sdf(position);
+    if ground().distance(position) <= d {
+        let floor = Mapped {
+            texture: Checker { a: Vector3::new(0.9, 0.9, 0.9), b: Vector3::new(0.4, 0.4, 0.4) },
+            mapping: Mapping::Planar { u: Vector3::new(0.5, 0.0, 0.0), v: Vector3::new(0.0, 0.0, 0.5) },
+        };
+        Material::diffuse(&floor.colour(position, normal))
+    } else if wax(position) <= d {
+        Material::translucent(&Vector3::new(0.95, 0.9, 0.8), Subsurface::wax())
+    } else {
+        Material::diffuse(&Vector3::new(1.0, 1.0, 1.0))
+    }
+}
+
+// Direction of the demo scene's sunlight
+pub fn sun() -> Vector3 {
+    Vector3::new(300.0, 500.0, -300.0).normalized()
+}
+
+// Fraction of the environment's light that reaches surfaces, on top of the
+// sun
+const AMBIENT: Float = 0.3;
+
+fn illuminate(position: &Point3, normal: &Vector3, options: &Options) -> Colour {
+    let settings = &options.march;
+    let sun = sun();
+    let ambient = options.irradiance.get(normal).scale(AMBIENT);
+
+    // Start just off the surface so the shadow ray doesn't hit it straight away
+    let origin = *position + normal.scale(0.01);
+    if cast_ray(&origin, &sun, &settings.shadow()).is_hit(settings.exhausted) {
+        ambient
+    } else {
+        ambient + Vector3::splat(sun.dot_product(normal).max(0.0))
+    }
+}
+
+pub fn shade(position: &Point3, view_ray: &Vector3, options: &Options) -> image::Rgb<u8> {
+    shade_result(&cast_ray(position, view_ray, &options.march), position, view_ray, options)
+}
+
+// Light leaving a surface the view ray hit, `t` along it
+fn shade_surface(position: &Point3, t: Float, view_ray: &Vector3, options: &Options) -> Colour {
+    let normal = calc_normal(&Scene, position, t, options.normals);
+    let material = scene_material(position, &normal);
+    let diffuse = || material.albedo * illuminate(position, &normal, options);
+
+    match (material.subsurface, options.subsurface) {
+        (Some(sss), SubsurfaceMode::Approximate) => {
+            diffuse() + material.albedo * sss.translucency(&Scene, position, &normal, &-*view_ray, &sun())
+        }
+        (Some(sss), SubsurfaceMode::RandomWalk(samples)) => {
+            // Light comes out wherever the walks do, lit as it is there. The
+            // walks' throughput already carries the colour, so no albedo on
+            // top. The same walks for the same point, so renders repeat.
+            let seed = (position.to_vector().dot_product(&Vector3::new(7919.0, 104729.0, 1299709.0)) * 1000.0) as i64;
+            let mut rng = Rng::new(seed as u64);
+            let mut sum = Vector3::zero();
+            for _ in 0..samples {
+                if let Some(walk) = random_walk(&Scene, position, &normal, &sss, &mut rng) {
+                    let exit_normal = calc_normal(&Scene, &walk.exit, t, options.normals);
+                    sum += walk.throughput * illuminate(&walk.exit, &exit_normal, options);
+                }
+            }
+            sum.scale(1.0 / samples.max(1) as Float)
+        }
+        _ => diffuse(),
+    }
+}
+
+// Colour for a view ray from `eye` that has already been marched
+fn shade_result(result: &CastResult, eye: &Point3, view_ray: &Vector3, options: &Options) -> image::Rgb<u8> {
+    let (colour, t) = if result.is_hit(options.march.exhausted) {
+        (shade_surface(&result.position, result.t, view_ray, options), result.t)
+    } else {
+        (options.environment.colour(view_ray), Float::INFINITY)
+    };
+
+    let colour = match &options.haze {
+        Some(medium) => {
+            let settings = options.march.shadow();
+            let sunlit = |p: &Point3| !cast_ray(p, &sun(), &settings).is_hit(settings.exhausted);
+            let up = Vector3::new(0.0, 1.0, 0.0);
+            let lighting = Lighting {
+                direction: sun(),
+                colour: Vector3::splat(1.0),
+                // Irradiance from above and below together sums the
+                // light from everywhere
+                ambient: (options.irradiance.get(&up) + options.irradiance.get(&-up)).scale(0.5),
+            };
+            medium.integrate(eye, view_ray, t, &lighting, sunlit).apply(&colour)
+        }
+        None => colour,
+    };
+    let colour = match &options.fog {
+        Some(fog) => fog.apply(&colour, eye, view_ray, t),
+        None => colour,
+    };
+    to_rgb(&colour)
+}
+
+// The demo scene as a mesh, as `options` say, with each vertex coloured by
+// its material
+pub fn scene_mesh(options: &Options) -> (Mesh, Vec<Colour>) {
+    let mesh = options.mesher.mesh(&Scene, &options.mesh_grid);
+    let colours = mesh.vertices.iter().zip(&mesh.normals).map(|(p, n)| scene_material(p, n).albedo).collect();
+    (mesh, colours)
+}
+
+pub fn render(options: &Options) -> image::RgbImage {
+    let (xsize, ysize) = (options.width, options.height);
+    let mut image = image::RgbImage::new(xsize, ysize);
+
+    let res = Vector3::new(xsize as Float, ysize as Float, 0.0);
+    let scale = 1.0 / ysize as Float;
+
+    let orbit = Orbit::new(&Point3::new(5.0, 5.0, -10.0), &Point3::origin()).turn(options.orbit.to_radians());
+    let position = orbit.eye();
+    let camera = orbit.camera();
+
+    for y in 0..ysize {
+        let rays: Vec<Vector3> = (0..xsize).map(|x| {
+            // Convert render coord to (-1, -1) -> (1,1)
+
+            let render_pos = Vector3::new(x as Float, y as Float, 0.0);
+            let mut pos = (render_pos.scale(2.0) - res).scale(scale);
+            pos.z = 2.5;
+
+            // And normalise for direction from (0,0)
+            let normal_pos = pos.normalized();
+
+            &camera * &normal_pos
+        }).collect();
+
+        let colours = match (&options.debug, options.packet) {
+            (Some(mode), _) => rays.iter().map(|ray| debug::shade(mode, &position, ray, options)).collect(),
+            (None, Some(4)) => shade_packets::<4>(&position, &rays, options),
+            (None, Some(8)) => shade_packets::<8>(&position, &rays, options),
+            (None, Some(16)) => shade_packets::<16>(&position, &rays, options),
+            (None, _) => rays.iter().map(|ray| shade(&position, ray, options)).collect::<Vec<_>>(),
+        };
+
+        for (x, colour) in colours.into_iter().enumerate() {
+            image.put_pixel(x as u32, (ysize-1) -y, colour);
+        }
+    }
+    image
+}
+
+// Shade rays from a common origin, marching them N at a time. The last
+// packet is padded out by repeating its final ray.
+fn shade_packets<const N: usize>(position: &Point3, rays: &[Vector3], options: &Options) -> Vec<image::Rgb<u8>> {
+    let mut colours = Vec::with_capacity(rays.len());
+    for chunk in rays.chunks(N) {
+        let lanes = std::array::from_fn(|i| chunk[i.min(chunk.len() - 1)]);
+        let results = cast_scene_packet(&[*position; N], &lanes, &options.march);
+        colours.extend(results[..chunk.len()].iter().zip(chunk).map(|(r, ray)| shade_result(r, position, ray, options)));
+    }
+    colours
+}
+
+#[cfg(test)]
+mod tests
+{
+
+    use super::*;
+    use march::MarchSettings;
+    use normal::NormalMethod;
+    use mesh::Grid;
+
+    fn near_enough(v1: Float, v2: Float) -> bool{
+        let tolerance = 0.000001;
+        Float::abs(v2-v1) < tolerance
+    }
+
+    fn vec_near_enough(v1: Vector3, v2: Vector3) -> bool {
+        near_enough(v1.x, v2.x) &&
+            near_enough(v1.y, v2.y) &&
+            near_enough(v1.z, v2.z)
+    }
+
+    #[test]
+    fn check()
+    {
+        let p = Point3::new(0.0, 0.0, -10.0);
+        let d = Vector3::new(0.0, 0.0, 1.0);
+        let pt = cast_ray(&p, &d, &MarchSettings::default());
+
+        assert!(pt.is_hit(march::ExhaustedPolicy::Miss), "Expected hit");
+        let norm = calc_normal(&Scene, &pt.position, pt.t, NormalMethod::default());
+        let expected = Vector3::new(0.0, 0.0, -1.0);
+        assert!(vec_near_enough(norm, expected));
+    }
+
+    #[test]
+    fn rotated_object()
+    {
+        // A box long in x, stood on end by a quarter turn about z
+        let dims = Vector3::new(1.0, 0.5, 0.5);
+        let placed: Mat4 = Mat4::rotation_z(std::f64::consts::FRAC_PI_2 as Float);
+        let inverse = placed.inverse().unwrap();
+
+        let p = Point3::new(0.0, 0.9, 0.0);
+        assert!(cuboid(&dims, &p) > 0.0);
+        assert!(near_enough(cuboid(&dims, &Point3::new(0.9, 0.0, 0.0)), cuboid(&dims, &transform(&p, &inverse))));
+        assert!(cuboid(&dims, &transform(&p, &inverse)) < 0.0);
+    }
+
+    #[test]
+    fn checker_floor()
+    {
+        let up = Vector3::new(0.0, 1.0, 0.0);
+        let a = scene_material(&Point3::new(0.5, -5.0, 0.5), &up).albedo;
+        let b = scene_material(&Point3::new(1.5, -5.0, 0.5), &up).albedo;
+        let c = scene_material(&Point3::new(1.5, -5.0, 1.5), &up).albedo;
+        assert!(a != b);
+        assert_eq!(a, c);
+        // The left pair of spheres is wax, everything else is white
+        assert!(scene_material(&Point3::new(-3.0, -2.0, 0.0), &up).subsurface.is_some());
+        let plain = scene_material(&Point3::new(0.0, 0.0, -0.6), &up);
+        assert_eq!(Vector3::new(1.0, 1.0, 1.0), plain.albedo);
+        assert!(plain.subsurface.is_none());
+    }
+
+    #[test]
+    fn scene_mesh_closed()
+    {
+        let options = Options { mesh_grid: Grid { resolution: 32, ..Options::default().mesh_grid }, ..Default::default() };
+        let (mesh, colours) = scene_mesh(&options);
+        // Three separate objects, no floor, nothing cut off
+        assert!(mesh.is_closed());
+        assert_eq!(mesh.vertices.len(), colours.len());
+        let left = mesh.vertices.iter().position(|p| p.x < -3.5).unwrap();
+        assert_eq!(Some(Subsurface::wax()), scene_material(&mesh.vertices[left], &mesh.normals[left]).subsurface);
+    }
+
+    #[test]
+    fn packet_render_matches()
+    {
+        let options = |packet| Options { width: 37, height: 20, packet, ..Default::default() };
+        let expected = render(&options(None));
+        for lanes in [4, 8, 16] {
+            assert!(expected == render(&options(Some(lanes))), "{} lanes", lanes);
+        }
+    }
+
+    #[test]
+    fn misses_see_the_environment()
+    {
+        let eye = Point3::new(0.0, 0.0, -10.0);
+        let up = Vector3::new(0.0, 1.0, 0.0);
+        let options = Options::default();
+        assert_eq!(to_rgb(&options.environment.colour(&up)), shade(&eye, &up, &options));
+        assert_ne!(image::Rgb([0, 0, 0]), shade(&eye, &up, &options));
+
+        let grey = Vector3::new(0.5, 0.5, 0.5);
+        let environment = environment::Environment::Solid(grey);
+        let options = Options { irradiance: environment::Irradiance::new(&environment), environment, ..Default::default() };
+        assert_eq!(to_rgb(&grey), shade(&eye, &up, &options));
+    }
+
+    #[test]
+    fn environment_lights_shadows()
+    {
+        // The point on the floor in the middle of the lower right sphere's
+        // shadow, lit only by the environment
+        let centre = Point3::new(2.0, -1.0, 0.0);
+        let position = centre - sun().scale(4.0 / sun().y);
+        let up = Vector3::new(0.0, 1.0, 0.0);
+        let lit = |c: Vector3| {
+            let environment = environment::Environment::Solid(c);
+            let options = Options { irradiance: environment::Irradiance::new(&environment), environment, ..Default::default() };
+            illuminate(&position, &up, &options)
+        };
+        assert!(lit(Vector3::zero()).mag() < 1e-6);
+        assert!((lit(Vector3::splat(1.0)) - Vector3::splat(AMBIENT)).mag() < 0.01);
+    }
+
+    #[test]
+    fn fog_and_haze()
+    {
+        let eye = Point3::new(0.0, 0.0, -10.0);
+        let rays = [Vector3::new(0.0, 0.0, 1.0), Vector3::new(0.0, 1.0, 0.0), Vector3::new(0.3, -0.4, 1.0).normalized()];
+        let clear = Options::default();
+
+        // Thick fog hides everything, rays that hit and miss alike
+        let colour = Vector3::new(0.5, 0.5, 0.5);
+        let fog = Options { fog: Some(media::Fog::Exponential { density: 100.0, colour }), ..Default::default() };
+        // Empty haze changes nothing
+        let haze = Options { haze: Some(media::Medium::new(media::Uniform(0.0))), ..Default::default() };
+        // Real haze brightens the shadows and dims the sky
+        let thick = Options { haze: Some(media::Medium::new(media::Uniform(0.05))), ..Default::default() };
+        for ray in &rays {
+            assert_eq!(to_rgb(&colour), shade(&eye, ray, &fog));
+            assert_eq!(shade(&eye, ray, &clear), shade(&eye, ray, &haze));
+            assert_ne!(shade(&eye, ray, &clear), shade(&eye, ray, &thick));
+        }
+    }
+}
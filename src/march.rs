@@ -0,0 +1,717 @@
+use anyhow::{bail, Result};
+
+use crate::{ground, DistanceField, Objects};
+use crate::packet::Packet;
+use crate::real::Float;
+use crate::terrain::{Heights, Terrain};
+use crate::point::{Point3, Vector3};
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum HitStatus
+{
+    // Converged onto a surface
+    Hit,
+    // Left the scene bounds
+    Miss,
+    // Ran out of iterations before either of the above
+    Exhausted,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct CastResult
+{
+    pub status: HitStatus,
+    // Distance along the ray where marching stopped
+    pub t: Float,
+    pub steps: u32,
+    // Closest approach to any surface seen along the ray
+    pub min_dist: Float,
+    // Distance field value at `position`
+    pub distance: Float,
+    pub position: Point3,
+}
+
+// How to treat rays that ran out of iterations
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum ExhaustedPolicy
+{
+    // Shade wherever the ray stopped (the old behaviour)
+    Hit,
+    // Treat as having escaped the scene
+    Miss,
+    // Hit only if the final distance is below `k * t`, i.e. the surface is
+    // within roughly a pixel's footprint for k around the pixel angle
+    Threshold(Float),
+}
+
+impl Default for ExhaustedPolicy
+{
+    fn default() -> Self {
+        ExhaustedPolicy::Threshold(0.001)
+    }
+}
+
+impl ExhaustedPolicy
+{
+    pub fn parse(s: &str) -> Result<Self> {
+        Ok(match s.split_once('=') {
+            None if s == "hit" => ExhaustedPolicy::Hit,
+            None if s == "miss" => ExhaustedPolicy::Miss,
+            None if s == "threshold" => ExhaustedPolicy::default(),
+            Some(("threshold", k)) => ExhaustedPolicy::Threshold(k.parse()?),
+            _ => bail!("Unknown exhausted policy {} (hit, miss, threshold[=k])", s),
+        })
+    }
+}
+
+// How far to advance along the ray given the distance to the nearest surface
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Stepping
+{
+    // Plain sphere tracing, step by the distance
+    Sphere,
+    // Over-relaxed sphere tracing (Keinert et al., "Enhanced Sphere Tracing"):
+    // step by omega * distance, in [1, 2), falling back to plain sphere tracing
+    // from the previous point if the unbounding spheres stop overlapping
+    OverRelaxed(Float),
+    // Step by k * distance, k < 1, for fields that overestimate the distance
+    // (i.e. aren't 1-Lipschitz), such as deformed or displaced shapes
+    Scaled(Float),
+}
+
+impl Stepping
+{
+    pub fn parse(s: &str) -> Result<Self> {
+        Ok(match s.split_once('=') {
+            None if s == "sphere" => Stepping::Sphere,
+            None if s == "relaxed" => Stepping::OverRelaxed(1.2),
+            Some(("relaxed", w)) => Stepping::OverRelaxed(w.parse()?),
+            Some(("scaled", k)) => Stepping::Scaled(k.parse()?),
+            _ => bail!("Unknown stepping {} (sphere, relaxed[=w], scaled=k)", s),
+        })
+    }
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct MarchSettings
+{
+    pub t_min: Float,
+    pub t_max: Float,
+    pub max_steps: u32,
+    // A hit is a distance below epsilon * t, so the tolerance grows with
+    // distance from the eye in proportion to the pixel footprint
+    pub epsilon: Float,
+    pub stepping: Stepping,
+    pub exhausted: ExhaustedPolicy,
+}
+
+impl Default for MarchSettings
+{
+    fn default() -> Self {
+        MarchSettings {
+            t_min: 1.0,
+            t_max: 200.0,
+            max_steps: 50,
+            epsilon: 0.0001,
+            stepping: Stepping::Sphere,
+            exhausted: ExhaustedPolicy::default(),
+        }
+    }
+}
+
+impl MarchSettings
+{
+    // Settings for rays leaving a surface towards a light. These start right
+    // next to the surface (the caller should offset the origin along the
+    // normal) so nearby occluders aren't skipped.
+    pub fn shadow(&self) -> Self {
+        MarchSettings {
+            t_min: 0.0,
+            ..*self
+        }
+    }
+}
+
+impl CastResult
+{
+    pub fn is_hit(&self, policy: ExhaustedPolicy) -> bool {
+        match self.status {
+            HitStatus::Hit => true,
+            HitStatus::Miss => false,
+            HitStatus::Exhausted => match policy {
+                ExhaustedPolicy::Hit => true,
+                ExhaustedPolicy::Miss => false,
+                ExhaustedPolicy::Threshold(k) => self.distance < k * self.t,
+            },
+        }
+    }
+}
+
+// The state of one ray part way through marching. Shared by the scalar and
+// packet marchers so every lane of a packet makes exactly the decisions the
+// scalar march would.
+struct RayMarch
+{
+    result: CastResult,
+    omega: Float,
+    scale: Float,
+    step: Float,
+    prev_dist: Float,
+}
+
+impl RayMarch
+{
+    // `lipschitz` is the field's bound, which scales every step down
+    fn new(position: &Point3, settings: &MarchSettings, lipschitz: Float) -> Self {
+        let (omega, scale) = match settings.stepping {
+            Stepping::Sphere => (1.0, 1.0),
+            Stepping::OverRelaxed(omega) => (omega, 1.0),
+            Stepping::Scaled(k) => (1.0, k),
+        };
+        let scale = scale / lipschitz;
+
+        RayMarch {
+            result: CastResult {
+                status: HitStatus::Exhausted,
+                t: settings.t_min,
+                steps: 0,
+                min_dist: Float::MAX,
+                distance: Float::MAX,
+                position: *position,
+            },
+            omega,
+            scale,
+            step: 0.0,
+            prev_dist: 0.0,
+        }
+    }
+
+    // Where the next distance should be taken
+    fn position(&mut self, origin: &Point3, ray: &Vector3) -> Point3 {
+        self.result.position = *origin + ray.scale(self.result.t);
+        self.result.position
+    }
+
+    // Take the distance at `position()` and move along. True once the ray
+    // has hit or missed.
+    fn advance(&mut self, distance: Float, settings: &MarchSettings) -> bool {
+        let result = &mut self.result;
+        result.distance = distance;
+        result.steps += 1;
+        result.min_dist = Float::min(result.min_dist, result.distance);
+
+        if self.omega > 1.0 && (self.prev_dist + result.distance) * self.scale < self.step {
+            // The spheres around this point and the last don't overlap, so we
+            // may have stepped over a surface: go back and stop relaxing
+            result.t -= self.step;
+            self.step = self.prev_dist * self.scale;
+            self.omega = 1.0;
+        } else {
+            if result.distance < settings.epsilon * result.t {
+                result.status = HitStatus::Hit;
+                return true;
+            }
+            self.step = result.distance * self.omega * self.scale;
+            self.prev_dist = result.distance;
+        }
+
+        result.t += self.step;
+        if result.t > settings.t_max {
+            result.status = HitStatus::Miss;
+            return true;
+        }
+        false
+    }
+}
+
+// March the demo scene: the objects by sphere tracing, and the ground with
+// the terrain march
+pub fn cast_ray(position: &Point3, ray: &Vector3, settings: &MarchSettings) -> CastResult {
+    onto_ground(cast_field(&Objects, position, ray, settings), position, ray, settings)
+}
+
+// March N rays of the demo scene, the objects together and the ground ray by
+// ray. Gives the same results as calling `cast_ray` on each ray.
+pub fn cast_scene_packet<const N: usize>(positions: &[Point3; N], rays: &[Vector3; N], settings: &MarchSettings) -> [CastResult; N] {
+    let objects = cast_packet(&Objects, positions, rays, settings);
+    std::array::from_fn(|i| onto_ground(objects[i], &positions[i], &rays[i], settings))
+}
+
+// Follow a march of the demo scene's objects with one of its ground, only as
+// far as where the objects stopped it, and keep whichever comes first
+fn onto_ground(objects: CastResult, position: &Point3, ray: &Vector3, settings: &MarchSettings) -> CastResult {
+    let t_max = match objects.status {
+        HitStatus::Miss => settings.t_max,
+        _ => objects.t,
+    };
+    let ground = cast_terrain(&ground(), position, ray, &MarchSettings { t_max, ..*settings });
+    let first = match (objects.status, ground.status) {
+        (_, HitStatus::Hit) | (HitStatus::Miss, HitStatus::Exhausted) => ground,
+        _ => objects,
+    };
+    CastResult {
+        steps: objects.steps + ground.steps,
+        min_dist: Float::min(objects.min_dist, ground.min_dist),
+        ..first
+    }
+}
+
+// March any field, with steps scaled down by its Lipschitz bound so
+// deformed fields that overestimate don't step through the surface
+pub fn cast_field<F: DistanceField>(field: &F, position: &Point3, ray: &Vector3, settings: &MarchSettings) -> CastResult {
+    let mut march = RayMarch::new(position, settings, field.lipschitz());
+
+    for _ in 0..settings.max_steps {
+        let p = march.position(position, ray);
+        if march.advance(field.distance(&p), settings) {
+            break;
+        }
+    }
+
+    march.result
+}
+
+// Bisection steps to pin down where a terrain march crossed the ground
+const TERRAIN_REFINE: u32 = 12;
+
+// March a ray over terrain. The gap y - h can't shrink faster than
+// s * |ray.xz| - ray.y per unit t, s bounding the slope, so stepping by the
+// gap over that rate never passes through the ground. That's never shorter
+// than a sphere tracing step, longer the more the ray runs along the ground,
+// and longer again as distant terrain loses detail and with it slope. Steps
+// are at least epsilon * t so they don't stall, and the ground changes as
+// detail drops out, so the ray can still end up under it, in which case
+// bisection between the last two points finds the crossing.
+pub fn cast_terrain<H: Heights>(terrain: &Terrain<H>, position: &Point3, ray: &Vector3, settings: &MarchSettings) -> CastResult {
+    let lipschitz = terrain.lipschitz();
+    let gap = |t: Float| {
+        let p = *position + ray.scale(t);
+        (p, p.y - terrain.get(p.x, p.z, t))
+    };
+
+    let mut result = CastResult {
+        status: HitStatus::Exhausted,
+        t: settings.t_min,
+        steps: 0,
+        min_dist: Float::MAX,
+        distance: Float::MAX,
+        position: *position,
+    };
+    let mut last = result.t;
+
+    for _ in 0..settings.max_steps {
+        let (p, g) = gap(result.t);
+        result.steps += 1;
+        result.position = p;
+        result.distance = g / lipschitz;
+        result.min_dist = Float::min(result.min_dist, result.distance);
+
+        if g < 0.0 {
+            let (mut above, mut below) = (last, result.t);
+            for _ in 0..TERRAIN_REFINE {
+                let mid = 0.5 * (above + below);
+                if gap(mid).1 < 0.0 {
+                    below = mid;
+                } else {
+                    above = mid;
+                }
+            }
+            let (p, g) = gap(above);
+            result.steps += TERRAIN_REFINE + 1;
+            result.t = above;
+            result.position = p;
+            result.distance = g / lipschitz;
+            result.min_dist = Float::min(result.min_dist, result.distance);
+            result.status = HitStatus::Hit;
+            break;
+        }
+        if result.distance < settings.epsilon * result.t {
+            result.status = HitStatus::Hit;
+            break;
+        }
+        // Climbing faster than the ground ever can
+        let closing = terrain.slope(result.t) * Float::hypot(ray.x, ray.z) - ray.y;
+        if closing <= 0.0 {
+            result.status = HitStatus::Miss;
+            break;
+        }
+
+        last = result.t;
+        result.t += Float::max(g / closing, settings.epsilon * result.t);
+        if result.t > settings.t_max {
+            result.status = HitStatus::Miss;
+            break;
+        }
+    }
+
+    result
+}
+
+// March N rays together, evaluating the field once per step for all of them.
+// Lanes that have finished are masked off but still ride along in the
+// evaluation until the whole packet is done. Gives the same results as
+// calling `cast_field` on each ray.
+pub fn cast_packet<F: DistanceField, const N: usize>(
+    field: &F,
+    positions: &[Point3; N],
+    rays: &[Vector3; N],
+    settings: &MarchSettings,
+) -> [CastResult; N] {
+    let lipschitz = field.lipschitz();
+    let mut lanes: [RayMarch; N] = std::array::from_fn(|i| RayMarch::new(&positions[i], settings, lipschitz));
+    let mut active = [true; N];
+
+    for _ in 0..settings.max_steps {
+        if !active.contains(&true) {
+            break;
+        }
+
+        let points = std::array::from_fn(|i| {
+            if active[i] {
+                lanes[i].position(&positions[i], &rays[i])
+            } else {
+                lanes[i].result.position
+            }
+        });
+        let d: Packet<N> = field.distance(&Packet::gather(&points));
+
+        for (i, march) in lanes.iter_mut().enumerate() {
+            if active[i] && march.advance(d[i], settings) {
+                active[i] = false;
+            }
+        }
+    }
+
+    lanes.map(|march| march.result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::matrix::Mat4;
+    use crate::Scene;
+    use crate::testing::Steep;
+    use crate::node::Cuboid;
+    use crate::vector::Vec4;
+    use crate::noise::{Fractal, Perlin};
+    use crate::terrain::{Gray16, Heightmap, NoiseHeights};
+    use image::Luma;
+    use std::num::NonZeroU32;
+
+    // Skims just above the flat top of the big box, creeping along in tiny steps
+    fn grazing() -> CastResult {
+        let settings = MarchSettings::default();
+        cast_ray(&Point3::new(-5.0, 2.11, 1.0), &Vector3::new(1.0, 0.0, 0.0), &settings)
+    }
+
+    #[test]
+    fn hit() {
+        let settings = MarchSettings::default();
+        let p = Point3::new(0.0, 0.0, -10.0);
+        let r = cast_ray(&p, &Vector3::new(0.0, 0.0, 1.0), &settings);
+        assert_eq!(HitStatus::Hit, r.status);
+        assert!(r.steps < settings.max_steps);
+        assert!(r.distance < settings.epsilon * r.t);
+        assert_eq!(p + Vector3::new(0.0, 0.0, r.t), r.position);
+    }
+
+    #[test]
+    fn miss() {
+        let settings = MarchSettings::default();
+        let r = cast_ray(&Point3::new(0.0, 0.0, -10.0), &Vector3::new(0.0, 1.0, 0.0), &settings);
+        assert_eq!(HitStatus::Miss, r.status);
+        assert!(!r.is_hit(ExhaustedPolicy::Hit));
+        assert!(r.min_dist > 1.0);
+    }
+
+    #[test]
+    fn grazing_ray_exhausts() {
+        let r = grazing();
+        assert_eq!(HitStatus::Exhausted, r.status);
+        // Plus however far the ground march got
+        assert!(r.steps >= MarchSettings::default().max_steps);
+        assert!(r.min_dist > 0.0);
+        assert!(r.distance >= r.min_dist);
+    }
+
+    #[test]
+    fn exhausted_policies() {
+        let r = grazing();
+        assert!(r.is_hit(ExhaustedPolicy::Hit));
+        assert!(!r.is_hit(ExhaustedPolicy::Miss));
+        assert!(!r.is_hit(ExhaustedPolicy::Threshold(0.0)));
+        assert!(r.is_hit(ExhaustedPolicy::Threshold(1.0)));
+    }
+
+    #[test]
+    fn grazing_shadow_ray_exhausts() {
+        // Shadow ray leaving the top of the big box at a very shallow angle
+        let settings = MarchSettings::default().shadow();
+        let from = Point3::new(0.2, 2.105, 1.0);
+        let r = cast_ray(&from, &Vector3::new(1.0, 0.0001, 0.0).normalized(), &settings);
+        assert_eq!(HitStatus::Exhausted, r.status);
+        assert!(!r.is_hit(ExhaustedPolicy::Miss));
+    }
+
+    #[test]
+    fn shadow_rays_see_nearby_occluders() {
+        // Just below the left-hand sphere, looking up through its edge: the
+        // ray is inside the sphere for t in roughly [0.06, 0.94]
+        let from = Point3::new(-3.9, -1.5, 0.0);
+        let up = Vector3::new(0.0, 1.0, 0.0);
+
+        let settings = MarchSettings::default();
+        assert_ne!(HitStatus::Hit, cast_ray(&from, &up, &settings).status);
+        assert_eq!(HitStatus::Hit, cast_ray(&from, &up, &settings.shadow()).status);
+    }
+
+    #[test]
+    fn scene_ground_is_terrain() {
+        // Away from the objects, looking down onto the hills at a shallow angle
+        let from = Point3::new(10.0, -3.0, 10.0);
+        let ray = Vector3::new(1.0, -0.05, 0.5).normalized();
+        let settings = MarchSettings { max_steps: 1000, ..Default::default() };
+        let r = cast_ray(&from, &ray, &settings);
+        let terrain = cast_terrain(&ground(), &from, &ray, &settings);
+        assert_eq!(HitStatus::Hit, r.status);
+        assert_eq!(terrain.t, r.t);
+        assert_eq!(terrain.position, r.position);
+        // Sphere tracing the whole scene finds the same hill the long way round
+        let generic = cast_field(&Scene, &from, &ray, &settings);
+        assert_eq!(HitStatus::Hit, generic.status);
+        assert!(Float::abs(generic.t - r.t) < 0.1 * r.t, "{} {}", generic.t, r.t);
+        assert!(r.steps < generic.steps, "scene {} steps, sphere tracing {}", r.steps, generic.steps);
+    }
+
+    #[test]
+    fn stepping_strategies_agree() {
+        let p = Point3::new(0.0, 0.0, -10.0);
+        let d = Vector3::new(0.0, 0.0, 1.0);
+        let expected = cast_ray(&p, &d, &MarchSettings::default());
+
+        for stepping in [Stepping::OverRelaxed(1.6), Stepping::OverRelaxed(1.9), Stepping::Scaled(0.5)] {
+            let settings = MarchSettings { stepping, max_steps: 200, ..Default::default() };
+            let r = cast_ray(&p, &d, &settings);
+            assert_eq!(HitStatus::Hit, r.status, "{:?}", stepping);
+            assert!(Float::abs(r.t - expected.t) < 0.01, "{:?}", stepping);
+        }
+    }
+
+    #[test]
+    fn relaxed_stepping_respects_lipschitz() {
+        // Head on at a thin plate, which a relaxed step from afar overshoots
+        let plate = Steep(Cuboid { dimensions: Vector3::new(0.05, 2.0, 2.0) }, 2.0);
+        let from = Point3::new(-5.0, 0.0, 0.0);
+        let ray = Vector3::new(1.0, 0.0, 0.0);
+        let settings = MarchSettings { t_min: 0.0, max_steps: 200, ..Default::default() };
+        let expected = cast_field(&plate, &from, &ray, &settings);
+        assert_eq!(HitStatus::Hit, expected.status);
+
+        for omega in [1.2, 1.6, 1.9] {
+            let settings = MarchSettings { stepping: Stepping::OverRelaxed(omega), ..settings };
+            let r = cast_field(&plate, &from, &ray, &settings);
+            assert_eq!(HitStatus::Hit, r.status, "{}", omega);
+            assert!(Float::abs(r.t - expected.t) < 1e-3, "{} {} {}", omega, r.t, expected.t);
+        }
+    }
+
+    #[test]
+    fn scaled_stepping_takes_more_steps() {
+        let p = Point3::new(0.0, 0.0, -10.0);
+        let d = Vector3::new(0.0, 0.0, 1.0);
+        let sphere = cast_ray(&p, &d, &MarchSettings::default());
+        let scaled = cast_ray(&p, &d, &MarchSettings { stepping: Stepping::Scaled(0.5), ..Default::default() });
+        assert!(scaled.steps > sphere.steps);
+    }
+
+    #[test]
+    fn packets_match_single_rays() {
+        let eye = Point3::new(0.0, 0.0, -10.0);
+        let rays = [
+            Vector3::new(0.0, 0.0, 1.0),
+            Vector3::new(0.0, 1.0, 0.0),
+            Vector3::new(-0.3, -0.1, 1.0).normalized(),
+            Vector3::new(0.2, 0.3, 1.0).normalized(),
+        ];
+        let origins = [eye, eye, eye, Point3::new(-5.0, 2.11, 1.0)];
+        let rays = [rays[0], rays[1], rays[2], Vector3::new(1.0, 0.0, 0.0)];
+
+        for stepping in [Stepping::Sphere, Stepping::OverRelaxed(1.6), Stepping::Scaled(0.5)] {
+            let settings = MarchSettings { stepping, ..Default::default() };
+            let packet = cast_scene_packet(&origins, &rays, &settings);
+            for i in 0..4 {
+                let single = cast_ray(&origins[i], &rays[i], &settings);
+                assert_eq!(single.status, packet[i].status, "{:?} lane {}", stepping, i);
+                assert_eq!(single.t, packet[i].t);
+                assert_eq!(single.steps, packet[i].steps);
+                assert_eq!(single.min_dist, packet[i].min_dist);
+                assert_eq!(single.position, packet[i].position);
+            }
+            // A mix of outcomes, so lanes really do finish at different times
+            assert_eq!(HitStatus::Hit, packet[0].status);
+            assert_eq!(HitStatus::Miss, packet[1].status);
+            assert_eq!(HitStatus::Exhausted, packet[3].status);
+        }
+    }
+
+    // Eight octaves, down to bumps about 0.15 across
+    fn hills() -> Terrain<NoiseHeights<Perlin>> {
+        let fractal = Fractal { octaves: NonZeroU32::new(8).unwrap(), ..Fractal::fbm(Perlin::new(5)) };
+        Terrain::new(NoiseHeights::new(fractal, 3.0, 0.05))
+    }
+
+    // Rays fanning out from above the hills, most at shallow angles
+    fn terrain_rays() -> impl Iterator<Item = Vector3> {
+        (0..40).map(|i| {
+            let a = i as Float * 0.37;
+            Vector3::new(a.cos(), -0.02 - 0.01 * (i % 7) as Float, a.sin()).normalized()
+        })
+    }
+
+    #[test]
+    fn flat_terrain() {
+        let image = Gray16::from_pixel(2, 2, Luma([u16::MAX]));
+        let flat = Terrain::new(Heightmap::new(image, 100.0, 1.0).unwrap());
+        let r = cast_terrain(&flat, &Point3::new(0.0, 3.0, 0.0), &Vector3::new(1.0, -1.0, 0.0).normalized(), &MarchSettings::default());
+        assert_eq!(HitStatus::Hit, r.status);
+        assert!(Float::abs(r.t - Float::sqrt(8.0)) < 1e-3);
+        // Looking up never reaches the ground
+        let r = cast_terrain(&flat, &Point3::new(0.0, 3.0, 0.0), &Vector3::new(1.0, 0.01, 0.0).normalized(), &MarchSettings::default());
+        assert_eq!(HitStatus::Miss, r.status);
+    }
+
+    #[test]
+    fn terrain_finds_first_crossing() {
+        let terrain = Terrain { detail: 0.0, ..hills() };
+        let eye = Point3::new(0.0, 4.0, 0.0);
+        let settings = MarchSettings { max_steps: 1000, ..Default::default() };
+        let mut hits = 0;
+        for ray in terrain_rays() {
+            let r = cast_terrain(&terrain, &eye, &ray, &settings);
+            // Brute force, in tiny steps
+            let below = |t: Float| terrain.distance(&(eye + ray.scale(t))) < 0.0;
+            let crossing = (0..10000).map(|i| settings.t_min + i as Float * 0.02).find(|t| below(*t));
+            match crossing {
+                Some(t) => {
+                    hits += 1;
+                    assert_eq!(HitStatus::Hit, r.status, "{:?}", ray);
+                    // Stopped short of the crossing, within the hit tolerance
+                    // of the ground
+                    assert!(r.t < t, "{:?} {} {}", ray, r.t, t);
+                    assert!(r.distance < settings.epsilon * r.t, "{:?}", ray);
+                }
+                None => assert_ne!(HitStatus::Hit, r.status, "{:?}", ray),
+            }
+        }
+        assert!(hits > 20);
+    }
+
+    #[test]
+    fn terrain_march_beats_sphere_tracing() {
+        let eye = Point3::new(0.0, 4.0, 0.0);
+        let settings = MarchSettings { max_steps: 2000, ..Default::default() };
+        let fine = Terrain { detail: 0.0, ..hills() };
+        let (mut specialised, mut lod, mut generic) = (0, 0, 0);
+        for ray in terrain_rays() {
+            let a = cast_terrain(&fine, &eye, &ray, &settings);
+            let b = cast_field(&fine, &eye, &ray, &settings);
+            assert_eq!(a.status, b.status, "{:?}", ray);
+            if a.status == HitStatus::Hit {
+                // The same hill, though along shallow rays the hit tolerance
+                // stretches out a long way
+                assert!(Float::abs(a.t - b.t) < 0.1 * a.t, "{:?} {} {}", ray, a.t, b.t);
+            }
+            specialised += a.steps;
+            generic += b.steps;
+            lod += cast_terrain(&hills(), &eye, &ray, &settings).steps;
+        }
+        assert!(specialised < generic, "terrain {} steps, sphere tracing {}", specialised, generic);
+        assert!(lod <= specialised, "detail falling off {} steps, full detail {}", lod, specialised);
+    }
+
+    #[test]
+    fn terrain_detail_falls_off() {
+        // Coarser heights far away move the hits a little, but not much
+        let eye = Point3::new(0.0, 4.0, 0.0);
+        let settings = MarchSettings { max_steps: 200, ..Default::default() };
+        let fine = Terrain { detail: 0.0, ..hills() };
+        let coarse = Terrain { detail: 0.01, ..hills() };
+        for ray in terrain_rays() {
+            let a = cast_terrain(&fine, &eye, &ray, &settings);
+            let b = cast_terrain(&coarse, &eye, &ray, &settings);
+            if a.status == HitStatus::Hit && b.status == HitStatus::Hit {
+                // Where it hit is within the dropped octaves of the ground
+                let (h, _) = fine.height(b.position.x, b.position.z, 0.0);
+                assert!(Float::abs(b.position.y - h) < 0.5, "{:?}", ray);
+            }
+        }
+    }
+
+    #[test]
+    fn parse_stepping() {
+        assert_eq!(Stepping::Sphere, Stepping::parse("sphere").unwrap());
+        assert_eq!(Stepping::OverRelaxed(1.2), Stepping::parse("relaxed").unwrap());
+        assert_eq!(Stepping::OverRelaxed(1.2), Stepping::parse("relaxed=1.2").unwrap());
+        assert_eq!(Stepping::Scaled(0.5), Stepping::parse("scaled=0.5").unwrap());
+        assert!(Stepping::parse("scaled").is_err());
+        assert!(Stepping::parse("wibble").is_err());
+    }
+
+    #[test]
+    fn parse_policy() {
+        assert_eq!(ExhaustedPolicy::Hit, ExhaustedPolicy::parse("hit").unwrap());
+        assert_eq!(ExhaustedPolicy::Miss, ExhaustedPolicy::parse("miss").unwrap());
+        assert_eq!(ExhaustedPolicy::default(), ExhaustedPolicy::parse("threshold").unwrap());
+        assert_eq!(ExhaustedPolicy::Threshold(0.5), ExhaustedPolicy::parse("threshold=0.5").unwrap());
+        assert!(ExhaustedPolicy::parse("wibble").is_err());
+        assert!(ExhaustedPolicy::parse("threshold=x").is_err());
+    }
+
+    // Total march steps over a low resolution render of the scene's objects
+    // for each strategy. The ground has its own march, which stepping doesn't
+    // apply to. The timings are in benches/march.rs.
+    #[test]
+    fn stepping_step_counts() {
+        let eye = Point3::new(5.0, 5.0, -10.0);
+        let camera = Mat4::look(&eye.into(), &Vec4::position(0.0, 0.0, 0.0));
+        let (w, h) = (64, 36);
+
+        let count = |stepping: Stepping| -> (u32, u32) {
+            let settings = MarchSettings { stepping, max_steps: 200, ..Default::default() };
+            let mut steps = 0;
+            let mut hits = 0;
+            for y in 0..h {
+                for x in 0..w {
+                    let d = Vector3::new(
+                        (2 * x - w) as Float / h as Float,
+                        (2 * y - h) as Float / h as Float,
+                        2.5,
+                    );
+                    let d = &camera * &d.normalized();
+                    let r = cast_field(&Objects, &eye, &d, &settings);
+                    steps += r.steps;
+                    hits += r.is_hit(ExhaustedPolicy::Miss) as u32;
+                }
+            }
+            (steps, hits)
+        };
+
+        let (sphere, sphere_hits) = count(Stepping::Sphere);
+        let same_hits = |hits: u32, within: u32| u32::abs_diff(hits, sphere_hits) <= sphere_hits / within;
+
+        // A little over-relaxation saves steps and finds the same surfaces
+        let (relaxed, relaxed_hits) = count(Stepping::OverRelaxed(1.2));
+        assert!(relaxed < sphere, "relaxed {} steps, sphere {}", relaxed, sphere);
+        assert!(same_hits(relaxed_hits, 100), "relaxed {} hits, sphere {}", relaxed_hits, sphere_hits);
+
+        // Too much, and the overshoots it backs out of cost more than it saves
+        let (eager, eager_hits) = count(Stepping::OverRelaxed(1.6));
+        assert!(eager > relaxed, "relaxed=1.6 {} steps, relaxed=1.2 {}", eager, relaxed);
+        assert!(same_hits(eager_hits, 100), "relaxed=1.6 {} hits, sphere {}", eager_hits, sphere_hits);
+
+        // Half steps take about twice as many, and a few grazing rays run out
+        let (scaled, scaled_hits) = count(Stepping::Scaled(0.5));
+        assert!(scaled > sphere * 3 / 2 && scaled < sphere * 5 / 2, "scaled {} steps, sphere {}", scaled, sphere);
+        assert!(same_hits(scaled_hits, 50), "scaled {} hits, sphere {}", scaled_hits, sphere_hits);
+    }
+}
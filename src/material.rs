@@ -0,0 +1,400 @@
+// What surfaces are made of: a colour, and for wax, skin, jade and the like,
+// how light gets in under the surface and comes out somewhere else.
+//
+// There are two ways to shade subsurface scattering. The cheap one uses the
+// distance field to guess how thick the object is behind each point, and
+// lets light through thin parts from behind. The expensive one follows light
+// on random walks through the inside, which the distance field makes quick,
+// as every step inside can be as long as the distance to the surface.
+
+use anyhow::{bail, Result};
+
+use crate::point::{Point3, Vector3};
+use crate::real::Float;
+use crate::texture::Colour;
+use crate::DistanceField;
+
+const TAU: Float = std::f64::consts::TAU as Float;
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Material
+{
+    pub albedo: Colour,
+    pub subsurface: Option<Subsurface>,
+}
+
+impl Material
+{
+    // Plain and opaque
+    pub fn diffuse(albedo: &Colour) -> Self {
+        Material { albedo: *albedo, subsurface: None }
+    }
+
+    pub fn translucent(albedo: &Colour, subsurface: Subsurface) -> Self {
+        Material { albedo: *albedo, subsurface: Some(subsurface) }
+    }
+}
+
+// The approximate translucency follows Barré-Brisebois and Bouchard
+// ("Approximating Translucency for a Fast, Cheap and Convincing Subsurface
+// Scattering Look", GDC 2011): light behind the surface shows through as the
+// view lines up with it, bent towards the normal by `distortion` and
+// sharpened by `power`.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Subsurface
+{
+    // Tint of the light that comes through, and for random walks the colour
+    // light comes back out after all its scatterings
+    pub colour: Colour,
+    // Mean free path: how far light goes inside between scatterings
+    pub radius: Float,
+    pub distortion: Float,
+    pub power: Float,
+    pub scale: Float,
+    // Light coming through from all around, however the view lines up
+    pub ambient: Float,
+}
+
+// Inward samples of the distance field for the thickness estimate
+const THICKNESS_SAMPLES: u32 = 5;
+// How many mean free paths deep the thickness estimate looks
+const THICKNESS_RANGE: Float = 4.0;
+
+impl Subsurface
+{
+    // Warm, soft and very translucent
+    pub fn wax() -> Self {
+        Subsurface {
+            colour: Vector3::new(0.95, 0.8, 0.55),
+            radius: 0.25,
+            distortion: 0.2,
+            power: 3.0,
+            scale: 1.5,
+            ambient: 0.15,
+        }
+    }
+
+    // Green and glassy, with light travelling further
+    pub fn jade() -> Self {
+        Subsurface {
+            colour: Vector3::new(0.45, 0.85, 0.55),
+            radius: 0.4,
+            distortion: 0.1,
+            power: 6.0,
+            scale: 2.0,
+            ambient: 0.1,
+        }
+    }
+
+    // How much of the object lies behind p, from 0 where it's thin to 1
+    // where it's solid for a few mean free paths, probing inward along
+    // the normal. Samples nearer the surface count for more.
+    pub fn thickness<F: DistanceField>(&self, field: &F, p: &Point3, normal: &Vector3) -> Float {
+        let range = THICKNESS_RANGE * self.radius;
+        let mut filled = 0.0;
+        let mut total = 0.0;
+        for i in 1..=THICKNESS_SAMPLES {
+            let depth = range * i as Float / THICKNESS_SAMPLES as Float;
+            let inside = (-field.distance(&(*p - normal.scale(depth)))).clamp(0.0, depth) / depth;
+            let weight = 1.0 / i as Float;
+            filled += inside * weight;
+            total += weight;
+        }
+        filled / total
+    }
+
+    // Light from the unit direction `light` coming through to a viewer in
+    // unit direction `view` from p, for light of unit brightness
+    pub fn translucency<F: DistanceField>(&self, field: &F, p: &Point3, normal: &Vector3, view: &Vector3, light: &Vector3) -> Colour {
+        let through = (*light + normal.scale(self.distortion)).normalized();
+        let lined_up = view.dot_product(&-through).max(0.0).powf(self.power) * self.scale;
+        let transmission = (-THICKNESS_RANGE * self.thickness(field, p, normal)).exp();
+        self.colour.scale((lined_up + self.ambient) * transmission)
+    }
+}
+
+// A small, fast generator (xorshift64*) so random walks can be repeated
+#[derive(Debug, Clone)]
+pub struct Rng(u64);
+
+impl Rng
+{
+    pub fn new(seed: u64) -> Self {
+        // Scrambled (splitmix64's finaliser) so nearby seeds give unrelated
+        // streams, and never zero, which xorshift can't leave
+        let mut z = seed.wrapping_add(0x9e3779b97f4a7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        Rng((z ^ (z >> 31)) | 1)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545f4914f6cdd1d)
+    }
+
+    // In [0, 1)
+    pub fn float(&mut self) -> Float {
+        ((self.next_u64() >> 11) as f64 / (1u64 << 53) as f64) as Float
+    }
+
+    // Uniform over the sphere
+    pub fn direction(&mut self) -> Vector3 {
+        let z = 1.0 - 2.0 * self.float();
+        let r = (1.0 - z * z).max(0.0).sqrt();
+        let a = TAU * self.float();
+        Vector3::new(r * a.cos(), r * a.sin(), z)
+    }
+
+    // Cosine weighted over the hemisphere around the unit vector n
+    pub fn cosine_direction(&mut self, n: &Vector3) -> Vector3 {
+        // A point on the unit sphere about the end of n, back to the origin
+        let d = *n + self.direction();
+        if d.mag() < 1e-6 {
+            *n
+        } else {
+            d.normalized()
+        }
+    }
+}
+
+// Where a random walk came back out of the surface
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Walk
+{
+    pub exit: Point3,
+    // Fraction of the light that made it, per channel
+    pub throughput: Colour,
+    pub scatterings: u32,
+}
+
+// Scatterings before a walk is given up as absorbed
+const MAX_SCATTERINGS: u32 = 256;
+// How close to the surface counts as having left
+const WALK_EPSILON: Float = 1e-4;
+
+// Follow light in through the surface at p (with outward unit normal),
+// travelling exponentially distributed distances between scatterings, each
+// into a uniformly random direction and losing a little of the light, so
+// that on average what comes out is `colour`. Inside, the distance to the
+// surface is free space, so each free flight is sphere traced from the inside
+// rather than stepped. None if the walk never leaves.
+pub fn random_walk<F: DistanceField>(field: &F, p: &Point3, normal: &Vector3, subsurface: &Subsurface, rng: &mut Rng) -> Option<Walk> {
+    // A marched hit can stop just short of the surface, so start from p
+    // pushed just inside. Where the field is a bound rather than exact, one
+    // push along the normal can fall short.
+    let mut q = *p;
+    for _ in 0..8 {
+        let d = field.distance(&q);
+        if d < -WALK_EPSILON {
+            break;
+        }
+        q -= normal.scale(d.max(0.0) + 2.0 * WALK_EPSILON);
+    }
+    let lipschitz = field.lipschitz();
+    let mut direction = rng.cosine_direction(&-*normal);
+    let mut throughput = Vector3::splat(1.0);
+    let albedo = single_scattering(&subsurface.colour);
+
+    for scatterings in 0..MAX_SCATTERINGS {
+        // 1 - u is in (0, 1], so the log is finite
+        let flight = -(1.0 - rng.float()).ln() * subsurface.radius;
+        let mut travelled = 0.0;
+        while travelled < flight {
+            let d = field.distance(&q);
+            if d > -WALK_EPSILON {
+                return Some(Walk { exit: q, throughput, scatterings });
+            }
+            let step = Float::min(-d / lipschitz, flight - travelled).max(WALK_EPSILON);
+            q += direction.scale(step);
+            travelled += step;
+        }
+        throughput *= albedo;
+        direction = rng.direction();
+    }
+    None
+}
+
+// The albedo of one scattering that comes out as `colour` after all of them,
+// by the fit in Chiang, Kutz and Burley, "Practical and Controllable
+// Subsurface Scattering for Production Path Tracing" (2016). The fit is a
+// little off at 1, where nothing should be absorbed at all.
+fn single_scattering(colour: &Colour) -> Colour {
+    let invert = |a: Float| {
+        if a >= 1.0 {
+            1.0
+        } else {
+            1.0 - (4.09712 + 4.20863 * a - (9.59217 + 41.6808 * a + 17.7126 * a * a).sqrt()).powi(2)
+        }
+    };
+    Vector3::new(invert(colour.x), invert(colour.y), invert(colour.z))
+}
+
+// How the renderer shades subsurface scattering
+#[derive(Debug, Default, PartialEq, Copy, Clone)]
+pub enum SubsurfaceMode
+{
+    // Treated as plain diffuse
+    Off,
+    // The thickness and light direction estimate
+    #[default]
+    Approximate,
+    // Averaging this many random walks per hit
+    RandomWalk(u32),
+}
+
+impl SubsurfaceMode
+{
+    pub fn parse(s: &str) -> Result<Self> {
+        Ok(match s.split_once('=') {
+            None if s == "off" => SubsurfaceMode::Off,
+            None if s == "approx" => SubsurfaceMode::Approximate,
+            None if s == "walk" => SubsurfaceMode::RandomWalk(16),
+            Some(("walk", n)) => match n.parse()? {
+                0 => bail!("Random walk subsurface needs at least one sample"),
+                n => SubsurfaceMode::RandomWalk(n),
+            },
+            _ => bail!("Unknown subsurface mode {} (off, approx, walk[=samples])", s),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::{Cuboid, Sphere};
+    use crate::testing::Steep;
+
+    fn near(a: Float, b: Float, tolerance: Float) -> bool {
+        Float::abs(a - b) < tolerance
+    }
+
+    #[test]
+    fn thickness() {
+        let wax = Subsurface::wax();
+        let up = Vector3::new(0.0, 1.0, 0.0);
+        // On top of a big ball it's solid all the way down
+        let ball = Sphere { radius: 5.0 };
+        assert!(near(1.0, wax.thickness(&ball, &Point3::new(0.0, 5.0, 0.0), &up), 1e-6));
+        // A thin slab is mostly empty behind the surface. Cuboids are
+        // rounded off by 0.1, so this one is 0.3 thick.
+        let slab = Cuboid { dimensions: Vector3::new(5.0, 0.05, 5.0) };
+        let thin = wax.thickness(&slab, &Point3::new(0.0, 0.15, 0.0), &up);
+        assert!(thin < 0.3, "{}", thin);
+        // Thinner slabs are thinner still
+        let thinner = Cuboid { dimensions: Vector3::new(5.0, 0.0, 5.0) };
+        assert!(wax.thickness(&thinner, &Point3::new(0.0, 0.1, 0.0), &up) < thin);
+    }
+
+    #[test]
+    fn translucency() {
+        let wax = Subsurface::wax();
+        let ball = Sphere { radius: 0.3 };
+        let p = Point3::new(0.0, 0.3, 0.0);
+        let n = Vector3::new(0.0, 1.0, 0.0);
+        // Looking down at the top of a ball lit from below, light comes
+        // through; lit from above, only the ambient part does
+        let below = wax.translucency(&ball, &p, &n, &n, &-n);
+        let above = wax.translucency(&ball, &p, &n, &n, &n);
+        assert!(below.x > above.x * 2.0);
+        assert!(above.x > 0.0);
+        // Tinted by the colour
+        assert!(near(below.y / below.x, wax.colour.y / wax.colour.x, 1e-6));
+        // Less comes through a bigger ball
+        let big = wax.translucency(&Sphere { radius: 3.0 }, &Point3::new(0.0, 3.0, 0.0), &n, &n, &-n);
+        assert!(big.x < below.x);
+    }
+
+    #[test]
+    fn rng() {
+        let mut a = Rng::new(7);
+        let mut b = Rng::new(7);
+        let xs: Vec<Float> = (0..1000).map(|_| a.float()).collect();
+        assert!(xs.iter().all(|x| (0.0..1.0).contains(x)));
+        assert!(xs.iter().zip((0..1000).map(|_| b.float())).all(|(x, y)| *x == y));
+        let mean = xs.iter().sum::<Float>() / 1000.0;
+        assert!(near(0.5, mean, 0.05));
+
+        // Directions are unit length and average out to nothing
+        let mut sum = Vector3::zero();
+        for _ in 0..4000 {
+            let d = a.direction();
+            assert!(near(1.0, d.mag(), 1e-5));
+            sum += d;
+        }
+        assert!(sum.scale(1.0 / 4000.0).mag() < 0.05);
+
+        // Cosine weighted directions stay on n's side, with a mean cosine
+        // of 2/3
+        let n = Vector3::new(0.0, 0.0, 1.0);
+        let cosines: Float = (0..4000).map(|_| a.cosine_direction(&n).dot_product(&n)).sum();
+        assert!(near(2.0 / 3.0, cosines / 4000.0, 0.02));
+        assert!((0..1000).all(|_| a.cosine_direction(&n).z >= 0.0));
+    }
+
+    #[test]
+    fn walks_come_back_out() {
+        let ball = Sphere { radius: 1.0 };
+        let wax = Subsurface { colour: Vector3::splat(1.0), ..Subsurface::wax() };
+        let mut rng = Rng::new(1);
+        let p = Point3::new(0.0, 1.0, 0.0);
+        let n = Vector3::new(0.0, 1.0, 0.0);
+        let mut scatterings = 0;
+        for _ in 0..200 {
+            let walk = random_walk(&ball, &p, &n, &wax, &mut rng).expect("Walk never left");
+            assert!(near(0.0, ball.distance(&walk.exit), 1e-3), "{:?}", walk);
+            // Nothing is absorbed when the colour is white
+            assert_eq!(Vector3::splat(1.0), walk.throughput);
+            scatterings += walk.scatterings;
+        }
+        // Light goes a fair way in, a handful of scatterings on average
+        assert!(scatterings > 200);
+    }
+
+    #[test]
+    fn walks_respect_lipschitz() {
+        // Distances three times too big would step out well past the surface
+        let ball = Sphere { radius: 1.0 };
+        let steep = Steep(ball, 3.0);
+        let wax = Subsurface::wax();
+        let mut rng = Rng::new(3);
+        let p = Point3::new(0.0, 1.0, 0.0);
+        let n = Vector3::new(0.0, 1.0, 0.0);
+        for _ in 0..200 {
+            if let Some(walk) = random_walk(&steep, &p, &n, &wax, &mut rng) {
+                assert!(near(0.0, ball.distance(&walk.exit), 1e-3), "{:?}", walk);
+            }
+        }
+    }
+
+    #[test]
+    fn walks_absorb_and_spread() {
+        let ball = Sphere { radius: 1.0 };
+        let p = Point3::new(0.0, 1.0, 0.0);
+        let n = Vector3::new(0.0, 1.0, 0.0);
+        let spread = |radius: Float| {
+            let mut rng = Rng::new(2);
+            let sss = Subsurface { radius, ..Subsurface::jade() };
+            let walks: Vec<Walk> = (0..300).filter_map(|_| random_walk(&ball, &p, &n, &sss, &mut rng)).collect();
+            // Redder light is absorbed more in jade
+            assert!(walks.iter().all(|w| w.throughput.x <= w.throughput.y));
+            walks.iter().map(|w| (w.exit - p).mag()).sum::<Float>() / walks.len() as Float
+        };
+        // Light comes out further away when it goes further between
+        // scatterings
+        assert!(spread(0.3) > spread(0.05));
+    }
+
+    #[test]
+    fn parse_mode() {
+        assert_eq!(SubsurfaceMode::Off, SubsurfaceMode::parse("off").unwrap());
+        assert_eq!(SubsurfaceMode::Approximate, SubsurfaceMode::parse("approx").unwrap());
+        assert_eq!(SubsurfaceMode::RandomWalk(16), SubsurfaceMode::parse("walk").unwrap());
+        assert_eq!(SubsurfaceMode::RandomWalk(64), SubsurfaceMode::parse("walk=64").unwrap());
+        assert!(SubsurfaceMode::parse("walk=lots").is_err());
+        assert!(SubsurfaceMode::parse("walk=0").is_err());
+        assert!(SubsurfaceMode::parse("deep").is_err());
+    }
+}
@@ -1,31 +1,55 @@
 use std::{fmt, ops::{Index, IndexMut, Mul}};
 
+use crate::point::{Point3, Vector3};
+use crate::real::{cast, Float, Real};
 use crate::vector::Vec4;
 
-pub type Float = crate::vector::Float;
+// A 4x4 matrix acting on column vectors, `m * v`. Stored row by row, and
+// indexed `m[(x, y)]` by column then row, so `m[(x, y)]` is `d[x + y * 4]`
+// and the translation sits in column 3, at `d[3]`, `d[7]` and `d[11]`.
+// Products compose right to left like the maths: `(a * b) * v` applies `b`
+// first. `then` reads the other way round, which is usually clearer.
+//
+// `Mat4` on its own means `Mat4<Float>`
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Mat4<T = Float>
+{
+    d: [T; 16],
+}
+
+// Determinants this small relative to the most they could be for the
+// matrix's column lengths are rounding error, and the inverse would be noise
+const SINGULAR: Float = 1024.0 * Float::EPSILON;
 
-#[derive(Debug, PartialEq)]
-pub struct Mat4
+// The order Euler angle rotations are applied in, about fixed axes. `XYZ`
+// rotates about x first, then y, then z.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum EulerOrder
 {
-    d: [Float; 16],
+    XYZ,
+    XZY,
+    YXZ,
+    YZX,
+    ZXY,
+    ZYX,
 }
 
-impl Default for Mat4
+impl<T: Real> Default for Mat4<T>
 {
     fn default() -> Self {
         Self::new()
     }
 }
 
-impl Mat4
+impl<T: Real> Mat4<T>
 {
     pub fn new() -> Self {
-        Mat4 { d: [0.0; 16] }
+        Mat4 { d: [T::from_float(0.0); 16] }
     }
 
     pub fn i() -> Self {
-        let one = 1.0;
-        let zero = 0.0;
+        let one = T::from_float(1.0);
+        let zero = T::from_float(0.0);
 
         Mat4 {
             d: [
@@ -35,9 +59,9 @@ impl Mat4
         }
     }
 
-    pub fn camera(fwd: &Vec4, right: &Vec4, up: &Vec4, pos: &Vec4) -> Self {
-        let zero = 0.0;
-        let one = 1.0;
+    pub fn camera(fwd: &Vec4<T>, right: &Vec4<T>, up: &Vec4<T>, pos: &Vec4<T>) -> Self {
+        let zero = T::from_float(0.0);
+        let one = T::from_float(1.0);
         Mat4 {
             d: [
                 right.x, up.x, fwd.x, pos.x, right.y, up.y, fwd.y, pos.y, right.z, up.z, fwd.z,
@@ -46,17 +70,17 @@ impl Mat4
         }
     }
 
-    pub fn look(position: &Vec4, look_at: &Vec4) -> Self {
+    pub fn look(position: &Vec4<T>, look_at: &Vec4<T>) -> Self {
         let direction = (look_at - position).normalized();
-        let temp_up = Vec4::direction(0.0, 1.0, 0.0);
+        let temp_up = Vec4::direction(T::from_float(0.0), T::from_float(1.0), T::from_float(0.0));
         let right = temp_up.cross_product(&direction).normalized();
         let up = direction.cross_product(&right).normalized();
 
         Mat4::camera(&direction, &right, &up, position)
     }
 
-    pub fn translation(translation: &Vec4) -> Self {
-        let mut t: Mat4 = Mat4::i();
+    pub fn translation(translation: &Vec4<T>) -> Self {
+        let mut t: Mat4<T> = Mat4::i();
         t[(3, 0)] = translation.x;
         t[(3, 1)] = translation.y;
         t[(3, 2)] = translation.z;
@@ -64,8 +88,8 @@ impl Mat4
         t
     }
 
-    pub fn scale(factor: &Vec4) -> Self {
-        let mut s: Mat4 = Mat4::i();
+    pub fn scale(factor: &Vec4<T>) -> Self {
+        let mut s: Mat4<T> = Mat4::i();
         s[(0, 0)] = factor.x;
         s[(1, 1)] = factor.y;
         s[(2, 2)] = factor.z;
@@ -73,7 +97,166 @@ impl Mat4
         s
     }
 
-    pub fn inverse(&self) -> Self {
+    // From the upper 3x3 written out row by row, as on paper
+    fn linear(rows: [[Float; 3]; 3]) -> Self {
+        let mut m: Mat4<T> = Mat4::i();
+        for (y, row) in rows.iter().enumerate() {
+            for (x, v) in row.iter().enumerate() {
+                m[(x, y)] = T::from_float(*v);
+            }
+        }
+        m
+    }
+
+    // Rotations are anticlockwise looking down the axis towards the origin,
+    // so a quarter turn about z takes x to y. Angles are in radians.
+    pub fn rotation_x(angle: Float) -> Self {
+        let (s, c) = angle.sin_cos();
+        Mat4::linear([[1.0, 0.0, 0.0], [0.0, c, -s], [0.0, s, c]])
+    }
+
+    pub fn rotation_y(angle: Float) -> Self {
+        let (s, c) = angle.sin_cos();
+        Mat4::linear([[c, 0.0, s], [0.0, 1.0, 0.0], [-s, 0.0, c]])
+    }
+
+    pub fn rotation_z(angle: Float) -> Self {
+        let (s, c) = angle.sin_cos();
+        Mat4::linear([[c, -s, 0.0], [s, c, 0.0], [0.0, 0.0, 1.0]])
+    }
+
+    // About an arbitrary axis through the origin, which needn't be unit length
+    pub fn rotation(axis: &Vec4, angle: Float) -> Self {
+        let n = axis.as_direction().normalized();
+        let (x, y, z) = (n.x, n.y, n.z);
+        let (s, c) = angle.sin_cos();
+        let t = 1.0 - c;
+        Mat4::linear([
+            [c + x * x * t, x * y * t - z * s, x * z * t + y * s],
+            [x * y * t + z * s, c + y * y * t, y * z * t - x * s],
+            [x * z * t - y * s, y * z * t + x * s, c + z * z * t],
+        ])
+    }
+
+    // Rotate by `angles.x` about x, `angles.y` about y and `angles.z` about z,
+    // in the given order
+    pub fn euler(angles: &Vec4, order: EulerOrder) -> Self {
+        let (x, y, z) = (
+            Mat4::rotation_x(angles.x),
+            Mat4::rotation_y(angles.y),
+            Mat4::rotation_z(angles.z),
+        );
+        let (a, b, c) = match order {
+            EulerOrder::XYZ => (x, y, z),
+            EulerOrder::XZY => (x, z, y),
+            EulerOrder::YXZ => (y, x, z),
+            EulerOrder::YZX => (y, z, x),
+            EulerOrder::ZXY => (z, x, y),
+            EulerOrder::ZYX => (z, y, x),
+        };
+        a.then(&b).then(&c)
+    }
+
+    // From a unit quaternion `[x, y, z, w]`, w being the scalar part
+    pub fn from_quaternion(q: [T; 4]) -> Self {
+        let [x, y, z, w] = q;
+        let one = T::from_float(1.0);
+        let two = T::from_float(2.0);
+        let mut m: Mat4<T> = Mat4::i();
+        let rows = [
+            [one - two * (y * y + z * z), two * (x * y - z * w), two * (x * z + y * w)],
+            [two * (x * y + z * w), one - two * (x * x + z * z), two * (y * z - x * w)],
+            [two * (x * z - y * w), two * (y * z + x * w), one - two * (x * x + y * y)],
+        ];
+        for (y, row) in rows.iter().enumerate() {
+            for (x, v) in row.iter().enumerate() {
+                m[(x, y)] = *v;
+            }
+        }
+        m
+    }
+
+    // The rotation part as a unit quaternion `[x, y, z, w]`. Only meaningful
+    // if the upper 3x3 is a rotation, i.e. there's no scale or shear.
+    pub fn to_quaternion(&self) -> [T; 4] {
+        let m = |row: usize, col: usize| self[(col, row)];
+        let one = T::from_float(1.0);
+        let two = T::from_float(2.0);
+        let quarter = T::from_float(0.25);
+        let trace = m(0, 0) + m(1, 1) + m(2, 2);
+
+        // Shepperd's method: divide by the largest of the four candidates
+        if trace > T::from_float(0.0) {
+            let s = (trace + one).sqrt() * two;
+            [(m(2, 1) - m(1, 2)) / s, (m(0, 2) - m(2, 0)) / s, (m(1, 0) - m(0, 1)) / s, quarter * s]
+        } else if m(0, 0) > m(1, 1) && m(0, 0) > m(2, 2) {
+            let s = (one + m(0, 0) - m(1, 1) - m(2, 2)).sqrt() * two;
+            [quarter * s, (m(0, 1) + m(1, 0)) / s, (m(0, 2) + m(2, 0)) / s, (m(2, 1) - m(1, 2)) / s]
+        } else if m(1, 1) > m(2, 2) {
+            let s = (one + m(1, 1) - m(0, 0) - m(2, 2)).sqrt() * two;
+            [(m(0, 1) + m(1, 0)) / s, quarter * s, (m(1, 2) + m(2, 1)) / s, (m(0, 2) - m(2, 0)) / s]
+        } else {
+            let s = (one + m(2, 2) - m(0, 0) - m(1, 1)).sqrt() * two;
+            [(m(0, 2) + m(2, 0)) / s, (m(1, 2) + m(2, 1)) / s, quarter * s, (m(1, 0) - m(0, 1)) / s]
+        }
+    }
+
+    // Each coordinate picks up multiples of the other two: `xy` is how far x
+    // moves per unit of y, and so on
+    pub fn shear(xy: Float, xz: Float, yx: Float, yz: Float, zx: Float, zy: Float) -> Self {
+        Mat4::linear([[1.0, xy, xz], [yx, 1.0, yz], [zx, zy, 1.0]])
+    }
+
+    pub fn transpose(&self) -> Self {
+        let mut t = Mat4::new();
+        for y in 0..4 {
+            for x in 0..4 {
+                t[(y, x)] = self[(x, y)];
+            }
+        }
+        t
+    }
+
+    pub fn determinant(&self) -> T {
+        let a = |row: usize, col: usize| self[(col, row)];
+
+        // 2x2 minors of the top two rows and of the bottom two
+        let s0 = a(0, 0) * a(1, 1) - a(1, 0) * a(0, 1);
+        let s1 = a(0, 0) * a(1, 2) - a(1, 0) * a(0, 2);
+        let s2 = a(0, 0) * a(1, 3) - a(1, 0) * a(0, 3);
+        let s3 = a(0, 1) * a(1, 2) - a(1, 1) * a(0, 2);
+        let s4 = a(0, 1) * a(1, 3) - a(1, 1) * a(0, 3);
+        let s5 = a(0, 2) * a(1, 3) - a(1, 2) * a(0, 3);
+
+        let c5 = a(2, 2) * a(3, 3) - a(3, 2) * a(2, 3);
+        let c4 = a(2, 1) * a(3, 3) - a(3, 1) * a(2, 3);
+        let c3 = a(2, 1) * a(3, 2) - a(3, 1) * a(2, 2);
+        let c2 = a(2, 0) * a(3, 3) - a(3, 0) * a(2, 3);
+        let c1 = a(2, 0) * a(3, 2) - a(3, 0) * a(2, 2);
+        let c0 = a(2, 0) * a(3, 1) - a(3, 0) * a(2, 1);
+
+        s0 * c5 - s1 * c4 + s2 * c3 + s3 * c2 - s4 * c1 + s5 * c0
+    }
+
+    // The transform that applies `self` and then `next`
+    pub fn then(&self, next: &Mat4<T>) -> Self {
+        next * self
+    }
+
+    // Points pick up the translation, directions don't
+    pub fn transform_point(&self, p: &Point3<T>) -> Point3<T> {
+        let r = self * &Vec4::from(*p);
+        Point3::new(r.x, r.y, r.z)
+    }
+
+    pub fn transform_direction(&self, v: &Vector3<T>) -> Vector3<T> {
+        let r = self * &Vec4::from(*v);
+        Vector3::new(r.x, r.y, r.z)
+    }
+
+    // None if the matrix is singular, e.g. a scale by zero, or so nearly
+    // singular that rounding swamps the determinant, or not finite
+    pub fn inverse(&self) -> Option<Self> {
         let mut inv = Mat4::new();
 
         inv[0] = self[5] * self[10] * self[15]
@@ -190,28 +373,39 @@ impl Mat4
 
         let mut det = self[0] * inv[0] + self[1] * inv[4] + self[2] * inv[8] + self[3] * inv[12];
 
-        if det == 0.0 {
-            return Mat4::i();
+        // |det| is at most the product of the column lengths, reaching it
+        // when they're at right angles, so the ratio says how close the
+        // columns come to dependent without caring how each is scaled
+        let columns: Float = (0..4)
+            .map(|x| (0..4).map(|y| self[(x, y)].value().powi(2)).sum::<Float>().sqrt())
+            .product();
+        let d = det.value();
+        if !d.is_finite() || d.abs() <= SINGULAR * columns {
+            return None;
         }
 
-        det = 1.0 / det;
+        det = T::from_float(1.0) / det;
 
         for i in 0..16 {
-            inv[i] *= det;
+            inv[i] = inv[i] * det;
         }
 
-        inv
+        Some(inv)
+    }
+
+    pub fn cast<U: Real>(&self) -> Mat4<U> {
+        Mat4 { d: self.d.map(cast) }
     }
 }
 
-impl fmt::Display for Mat4
+impl<T: Real> fmt::Display for Mat4<T>
 {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         for row in 0..4 {
             f.write_str("[ ")?;
 
             for col in 0..4 {
-                f.write_fmt(format_args!("{:.3} ", self[(col, row)]))?;
+                f.write_fmt(format_args!("{:.3} ", self[(col, row)].value()))?;
             }
 
             f.write_str("]")?;
@@ -220,27 +414,27 @@ impl fmt::Display for Mat4
     }
 }
 
-impl Index<usize> for Mat4
+impl<T> Index<usize> for Mat4<T>
 {
-    type Output = Float;
+    type Output = T;
 
-    fn index(&self, index: usize) -> &Float {
+    fn index(&self, index: usize) -> &T {
         &self.d[index]
     }
 }
 
-impl IndexMut<usize> for Mat4
+impl<T> IndexMut<usize> for Mat4<T>
 {
-    fn index_mut(&mut self, index: usize) -> &mut Float {
+    fn index_mut(&mut self, index: usize) -> &mut T {
         &mut self.d[index]
     }
 }
 
-impl Index<(usize, usize)> for Mat4
+impl<T> Index<(usize, usize)> for Mat4<T>
 {
-    type Output = Float;
+    type Output = T;
 
-    fn index(&self, index: (usize, usize)) -> &Float {
+    fn index(&self, index: (usize, usize)) -> &T {
         let (x, y) = index;
         if x > 3 || y > 3 {
             core::panic!("Matrix index out of bounds");
@@ -249,9 +443,9 @@ impl Index<(usize, usize)> for Mat4
     }
 }
 
-impl IndexMut<(usize, usize)> for Mat4
+impl<T> IndexMut<(usize, usize)> for Mat4<T>
 {
-    fn index_mut(&mut self, index: (usize, usize)) -> &mut Float {
+    fn index_mut(&mut self, index: (usize, usize)) -> &mut T {
         let (x, y) = index;
         if x > 3 || y > 3 {
             core::panic!("Matrix index out of bounds");
@@ -260,38 +454,39 @@ impl IndexMut<(usize, usize)> for Mat4
     }
 }
 
-impl Mul for &Mat4
+impl<T: Real> Mul for &Mat4<T>
 {
-    type Output = Mat4;
+    type Output = Mat4<T>;
 
     fn mul(self, rhs: Self) -> Self::Output {
-        let mut result = Mat4::new();
-        for row in 0..4 {
-            for col in 0..4 {
-                result[(row, col)] = self[(row, 0)] * rhs[(0, col)]
-                    + self[(row, 1)] * rhs[(1, col)]
-                    + self[(row, 2)] * rhs[(2, col)]
-                    + self[(row, 3)] * rhs[(3, col)];
-            }
-        }
-        result
+        Mat4 { d: T::mat_mat(&self.d, &rhs.d) }
     }
 }
 
-impl Mul<&Vec4> for &Mat4
+impl<T: Real> Mul<&Vec4<T>> for &Mat4<T>
 {
-    type Output = Vec4;
+    type Output = Vec4<T>;
 
-    fn mul(self, rhs: &Vec4) -> Self::Output {
-        let mut r = Vec4::position(0.0, 0.0, 0.0);
+    fn mul(self, rhs: &Vec4<T>) -> Self::Output {
+        Vec4::from_array(T::mat_vec(&self.d, rhs.to_array()))
+    }
+}
 
-        for row in 0..4 {
-            r[row] = self[(0, row)] * rhs[0]
-                + self[(1, row)] * rhs[1]
-                + self[(2, row)] * rhs[2]
-                + self[(3, row)] * rhs[3];
-        }
-        r
+impl<T: Real> Mul<&Point3<T>> for &Mat4<T>
+{
+    type Output = Point3<T>;
+
+    fn mul(self, rhs: &Point3<T>) -> Self::Output {
+        self.transform_point(rhs)
+    }
+}
+
+impl<T: Real> Mul<&Vector3<T>> for &Mat4<T>
+{
+    type Output = Vector3<T>;
+
+    fn mul(self, rhs: &Vector3<T>) -> Self::Output {
+        self.transform_direction(rhs)
     }
 }
 
@@ -301,12 +496,12 @@ mod tests {
 
     #[test]
     fn construct() {
-        let _m = Mat4::i();
+        let _m: Mat4 = Mat4::i();
     }
 
     #[test]
     fn access_ro() {
-        let m = Mat4::i();
+        let m: Mat4 = Mat4::i();
         assert_eq!(1.0, m[(0, 0)]);
         assert_eq!(1.0, m[(1, 1)]);
         assert_eq!(1.0, m[(2, 2)]);
@@ -316,13 +511,13 @@ mod tests {
     #[test]
     #[should_panic]
     fn access_ro_oob() {
-        let m = Mat4::i();
+        let m: Mat4 = Mat4::i();
         let _f = m[(5, 5)];
     }
 
     #[test]
     fn access_rw() {
-        let mut m = Mat4::i();
+        let mut m: Mat4 = Mat4::i();
         m[(0, 0)] = 2.0;
         m[(1, 1)] = 2.0;
         m[(2, 2)] = 2.0;
@@ -333,7 +528,7 @@ mod tests {
     #[test]
     #[should_panic]
     fn access_rw_oob() {
-        let mut m = Mat4::i();
+        let mut m: Mat4 = Mat4::i();
         m[(5, 5)] = 2.0;
     }
 
@@ -345,12 +540,315 @@ mod tests {
         assert_eq!(i, result);
     }
 
+    #[test]
+    fn precision() {
+        let m: Mat4<f32> = Mat4::translation(&Vec4::position(1.0, 2.0, 3.0));
+        let p = Vec4::position(1.0f32, 1.0, 1.0);
+        assert_eq!(m.cast::<f64>()[(3, 1)], 2.0f64);
+        assert_eq!(m.cast::<f64>().cast(), m);
+        assert_eq!(&m * &p, (&m.cast::<f64>() * &p.cast()).cast());
+    }
+
+    #[test]
+    fn transform_point_and_vector() {
+        let t: Mat4 = Mat4::translation(&Vec4::direction(1.0, 2.0, 3.0));
+
+        // Translation moves points but leaves vectors alone
+        assert_eq!(Point3::new(2.0, 3.0, 4.0), &t * &Point3::new(1.0, 1.0, 1.0));
+        assert_eq!(Vector3::new(1.0, 1.0, 1.0), &t * &Vector3::new(1.0, 1.0, 1.0));
+
+        let s: Mat4 = Mat4::scale(&Vec4::direction(2.0, 2.0, 2.0));
+        assert_eq!(Vector3::new(2.0, 2.0, 2.0), &s * &Vector3::new(1.0, 1.0, 1.0));
+        assert_eq!(Point3::new(2.0, 2.0, 2.0), &s * &Point3::new(1.0, 1.0, 1.0));
+    }
+
+    fn near(a: &Mat4, b: &Mat4) -> bool {
+        (0..16).all(|i| Float::abs(a[i] - b[i]) < 1e-6)
+    }
+
+    fn near_point(a: Point3, b: Point3) -> bool {
+        (a - b).mag() < 1e-6
+    }
+
+    const QUARTER: Float = std::f64::consts::FRAC_PI_2 as Float;
+
+    #[test]
+    fn rotations() {
+        let (x, y, z) = (Point3::new(1.0, 0.0, 0.0), Point3::new(0.0, 1.0, 0.0), Point3::new(0.0, 0.0, 1.0));
+        assert!(near_point(y, Mat4::rotation_z(QUARTER).transform_point(&x)));
+        assert!(near_point(z, Mat4::rotation_x(QUARTER).transform_point(&y)));
+        assert!(near_point(x, Mat4::rotation_y(QUARTER).transform_point(&z)));
+
+        let axis: Mat4 = Mat4::rotation(&Vec4::direction(0.0, 0.0, 3.0), 0.3);
+        assert!(near(&Mat4::rotation_z(0.3), &axis));
+
+        // A third of a turn about the diagonal cycles the axes
+        let diagonal: Mat4 = Mat4::rotation(&Vec4::direction(1.0, 1.0, 1.0), QUARTER * 4.0 / 3.0);
+        assert!(near_point(y, diagonal.transform_point(&x)));
+    }
+
+    #[test]
+    fn euler() {
+        let angles = Vec4::direction(0.1, 0.2, 0.3);
+        let (x, y, z) = (Mat4::rotation_x(0.1), Mat4::rotation_y(0.2), Mat4::rotation_z(0.3));
+        assert!(near(&x.then(&y).then(&z), &Mat4::euler(&angles, EulerOrder::XYZ)));
+        assert!(near(&z.then(&y).then(&x), &Mat4::euler(&angles, EulerOrder::ZYX)));
+        assert!(near(&y.then(&z).then(&x), &Mat4::euler(&angles, EulerOrder::YZX)));
+        assert!(!near(&Mat4::euler(&angles, EulerOrder::XYZ), &Mat4::euler(&angles, EulerOrder::ZYX)));
+    }
+
+    #[test]
+    fn quaternions() {
+        let axis = Vec4::direction(1.0, 2.0, 3.0).normalized();
+        // Angles past a half turn exercise the negative trace branches
+        for angle in [0.0, 0.7, 2.5, 3.1, 4.0] {
+            let (s, c) = Float::sin_cos(angle / 2.0);
+            let q = [axis.x * s, axis.y * s, axis.z * s, c];
+            let m = Mat4::from_quaternion(q);
+            assert!(near(&Mat4::rotation(&axis, angle), &m), "{}", angle);
+
+            // q and -q are the same rotation
+            let back = m.to_quaternion();
+            let sign = if back[3] * q[3] < 0.0 { -1.0 } else { 1.0 };
+            for i in 0..4 {
+                assert!(Float::abs(back[i] * sign - q[i]) < 1e-6, "{} {:?} {:?}", angle, q, back);
+            }
+        }
+        for axis in [Vec4::direction(1.0, 0.0, 0.0), Vec4::direction(0.0, 1.0, 0.0), Vec4::direction(0.0, 0.0, 1.0)] {
+            let m: Mat4 = Mat4::rotation(&axis, 3.0);
+            assert!(near(&m, &Mat4::from_quaternion(m.to_quaternion())));
+        }
+    }
+
+    #[test]
+    fn shear() {
+        let m: Mat4 = Mat4::shear(2.0, 0.0, 0.0, 0.0, 0.0, 0.5);
+        assert_eq!(Point3::new(2.0, 1.0, 0.5), m.transform_point(&Point3::new(0.0, 1.0, 0.0)));
+        assert_eq!(Vector3::new(0.0, 0.0, 1.0), m.transform_direction(&Vector3::new(0.0, 0.0, 1.0)));
+    }
+
+    #[test]
+    fn transpose_and_determinant() {
+        let m: Mat4 = Mat4::rotation_x(0.4).then(&Mat4::translation(&Vec4::direction(1.0, 2.0, 3.0)));
+        assert_eq!(m, m.transpose().transpose());
+        assert_eq!(m[(3, 0)], m.transpose()[(0, 3)]);
+
+        let s: Mat4 = Mat4::scale(&Vec4::direction(2.0, 3.0, 4.0));
+        assert_eq!(24.0, s.determinant());
+        assert!(Float::abs(m.determinant() - 1.0) < 1e-6);
+        assert_eq!(1.0, Mat4::shear(1.0, 2.0, 0.0, 0.0, 0.0, 0.0).determinant());
+        assert_eq!(0.0, Mat4::new().determinant());
+        assert!(Float::abs(s.then(&m).determinant() - 24.0) < 1e-4);
+    }
+
+    #[test]
+    fn composition() {
+        let m: Mat4 = Mat4::rotation_z(QUARTER).then(&Mat4::translation(&Vec4::direction(1.0, 0.0, 0.0)));
+        assert!(near_point(Point3::new(1.0, 1.0, 0.0), m.transform_point(&Point3::new(1.0, 0.0, 0.0))));
+        assert!((Vector3::new(0.0, 1.0, 0.0) - &m * &Vector3::new(1.0, 0.0, 0.0)).mag() < 1e-6);
+    }
+
     #[test]
     fn look_at() {
         let pos = Vec4::position(0.0, 0.0, -10.0);
         let origin = Vec4::position(0.0, 0.0, 0.0);
         let camera = Mat4::look(&pos, &origin);
 
-        println!("{}", camera)
+        // Camera space has the eye at the origin looking down +z, with y up
+        assert_eq!(Point3::new(0.0, 0.0, -10.0), camera.transform_point(&Point3::origin()));
+        assert_eq!(Vector3::new(0.0, 0.0, 1.0), camera.transform_direction(&Vector3::new(0.0, 0.0, 1.0)));
+        assert_eq!(Vector3::new(0.0, 1.0, 0.0), camera.transform_direction(&Vector3::new(0.0, 1.0, 0.0)));
+        assert_eq!(Vector3::new(1.0, 0.0, 0.0), camera.transform_direction(&Vector3::new(1.0, 0.0, 0.0)));
+
+        let eye = Vec4::position(5.0, 5.0, -10.0);
+        let camera = Mat4::look(&eye, &origin);
+        let forward = camera.transform_direction(&Vector3::new(0.0, 0.0, 1.0));
+        assert!((forward - Vector3::new(-5.0, -5.0, 10.0).normalized()).mag() < 1e-6);
+        assert!(Float::abs(camera.determinant() - 1.0) < 1e-6);
+    }
+
+    #[test]
+    fn layout() {
+        // Translation, camera position and the Mul impls all agree on where
+        // the translation lives
+        let t: Mat4 = Mat4::translation(&Vec4::direction(1.0, 2.0, 3.0));
+        assert_eq!([1.0, 2.0, 3.0], [t[3], t[7], t[11]]);
+        assert_eq!(t[(3, 1)], t[7]);
+
+        let c: Mat4 = Mat4::camera(
+            &Vec4::direction(0.0, 0.0, 1.0),
+            &Vec4::direction(1.0, 0.0, 0.0),
+            &Vec4::direction(0.0, 1.0, 0.0),
+            &Vec4::position(1.0, 2.0, 3.0),
+        );
+        assert_eq!(t, c);
+        assert_eq!(Vec4::position(1.0, 2.0, 3.0), &t * &Vec4::position(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn product_order() {
+        // (a * b) * v = a * (b * v): b applies first
+        let a: Mat4 = Mat4::translation(&Vec4::direction(1.0, 0.0, 0.0));
+        let b: Mat4 = Mat4::scale(&Vec4::direction(2.0, 2.0, 2.0));
+        let p = Point3::new(1.0, 1.0, 1.0);
+        assert_eq!(Point3::new(3.0, 2.0, 2.0), (&a * &b).transform_point(&p));
+        assert_eq!(a.transform_point(&b.transform_point(&p)), (&a * &b).transform_point(&p));
+        assert_eq!(&a * &b, b.then(&a));
+
+        // Row times column, written out
+        let mut m: Mat4 = Mat4::new();
+        let mut n: Mat4 = Mat4::new();
+        for i in 0..16 {
+            m[i] = i as Float;
+            n[i] = (16 - i) as Float;
+        }
+        let r = &m * &n;
+        for y in 0..4 {
+            for x in 0..4 {
+                let expected: Float = (0..4).map(|k| m[(k, y)] * n[(x, k)]).sum();
+                assert_eq!(expected, r[(x, y)]);
+            }
+        }
+    }
+
+    #[test]
+    fn singular() {
+        assert_eq!(None, Mat4::<Float>::new().inverse());
+        assert_eq!(None, Mat4::scale(&Vec4::direction(1.0, 0.0, 1.0)).inverse());
+        let i: Mat4 = Mat4::i();
+        assert_eq!(Some(i), i.inverse());
+
+        // Nearly singular, or not finite
+        let mut nearly = Mat4::scale(&Vec4::direction(1.0, 1.0, 1.0));
+        nearly[(1, 0)] = 1.0;
+        nearly[(1, 1)] = Float::EPSILON;
+        assert_eq!(None, nearly.inverse());
+        assert_eq!(None, Mat4::scale(&Vec4::direction(1.0, Float::NAN, 1.0)).inverse());
+        assert_eq!(None, Mat4::translation(&Vec4::direction(Float::INFINITY, 0.0, 0.0)).inverse());
+
+        // Tiny but even scales are fine at either precision
+        let small: Mat4 = Mat4::scale(&Vec4::direction(1e-3, 1e-3, 1e-3));
+        assert!(small.inverse().is_some());
+    }
+
+    mod properties {
+        use super::*;
+        use proptest::prelude::*;
+
+        const TOLERANCE: Float = 1e-4;
+
+        fn close(a: &Mat4, b: &Mat4) -> bool {
+            (0..16).all(|i| Float::abs(a[i] - b[i]) < TOLERANCE * (1.0 + Float::abs(b[i])))
+        }
+
+        fn close_point(a: &Point3, b: &Point3) -> bool {
+            (*a - *b).mag() < TOLERANCE * (1.0 + b.to_vector().mag())
+        }
+
+        fn angle() -> impl Strategy<Value = Float> {
+            -3.0..3.0 as Float
+        }
+
+        fn coord() -> impl Strategy<Value = Float> {
+            -10.0..10.0 as Float
+        }
+
+        fn factor() -> impl Strategy<Value = Float> {
+            prop_oneof![-4.0..-0.25 as Float, 0.25..4.0 as Float]
+        }
+
+        // Well conditioned affine transforms: scale, shear, rotate, translate
+        fn affine() -> impl Strategy<Value = Mat4> {
+            (
+                (factor(), factor(), factor()),
+                (-1.0..1.0 as Float, -1.0..1.0 as Float),
+                (angle(), angle(), angle()),
+                (coord(), coord(), coord()),
+            )
+                .prop_map(|((sx, sy, sz), (h1, h2), (ax, ay, az), (tx, ty, tz))| {
+                    Mat4::scale(&Vec4::direction(sx, sy, sz))
+                        .then(&Mat4::shear(h1, 0.0, 0.0, h2, 0.0, 0.0))
+                        .then(&Mat4::euler(&Vec4::direction(ax, ay, az), EulerOrder::XYZ))
+                        .then(&Mat4::translation(&Vec4::direction(tx, ty, tz)))
+                })
+        }
+
+        // Affine transforms with one column a mix of the others, give or take
+        // rounding
+        fn dependent() -> impl Strategy<Value = Mat4> {
+            (affine(), 0..3usize, -2.0..2.0 as Float, -2.0..2.0 as Float).prop_map(|(mut m, x, a, b)| {
+                let (p, q) = ((x + 1) % 3, (x + 2) % 3);
+                for y in 0..4 {
+                    m[(x, y)] = a * m[(p, y)] + b * m[(q, y)];
+                }
+                m
+            })
+        }
+
+        fn point() -> impl Strategy<Value = Point3> {
+            (coord(), coord(), coord()).prop_map(|(x, y, z)| Point3::new(x, y, z))
+        }
+
+        proptest! {
+            #[test]
+            fn inverse_is_inverse(m in affine()) {
+                let inv = m.inverse().unwrap();
+                let i: Mat4 = Mat4::i();
+                prop_assert!(close(&(&inv * &m), &i));
+                prop_assert!(close(&(&m * &inv), &i));
+            }
+
+            #[test]
+            fn dependent_columns_have_no_inverse(m in dependent()) {
+                prop_assert_eq!(None, m.inverse());
+            }
+
+            #[test]
+            fn non_finite_has_no_inverse(m in affine(), i in 0..16usize, bad in prop_oneof![Just(Float::NAN), Just(Float::INFINITY)]) {
+                let mut m = m;
+                m[i] = bad;
+                prop_assert_eq!(None, m.inverse());
+            }
+
+            #[test]
+            fn transform_round_trip(m in affine(), p in point()) {
+                let inv = m.inverse().unwrap();
+                prop_assert!(close_point(&inv.transform_point(&m.transform_point(&p)), &p));
+                let v = p.to_vector();
+                let back = inv.transform_direction(&m.transform_direction(&v));
+                prop_assert!((back - v).mag() < TOLERANCE * (1.0 + v.mag()));
+            }
+
+            #[test]
+            fn product_is_composition(a in affine(), b in affine(), p in point()) {
+                let composed = (&a * &b).transform_point(&p);
+                prop_assert!(close_point(&composed, &a.transform_point(&b.transform_point(&p))));
+            }
+
+            #[test]
+            fn associative(a in affine(), b in affine(), c in affine()) {
+                prop_assert!(close(&(&(&a * &b) * &c), &(&a * &(&b * &c))));
+            }
+
+            #[test]
+            fn determinant_multiplies(a in affine(), b in affine()) {
+                let (da, db) = (a.determinant(), b.determinant());
+                let dab = (&a * &b).determinant();
+                prop_assert!(Float::abs(dab - da * db) < TOLERANCE * (1.0 + Float::abs(da * db)));
+            }
+
+            #[test]
+            fn transpose_of_product(a in affine(), b in affine()) {
+                prop_assert!(close(&(&a * &b).transpose(), &(&b.transpose() * &a.transpose())));
+            }
+
+            #[test]
+            fn rotations_are_orthonormal(ax in angle(), ay in angle(), az in angle()) {
+                let r: Mat4 = Mat4::euler(&Vec4::direction(ax, ay, az), EulerOrder::ZXY);
+                let i: Mat4 = Mat4::i();
+                prop_assert!(close(&(&r.transpose() * &r), &i));
+                prop_assert!(Float::abs(r.determinant() - 1.0) < TOLERANCE);
+            }
+        }
     }
 }
@@ -0,0 +1,424 @@
+// Light travelling through something other than a vacuum.
+//
+// Fog is the cheap kind: worked out in closed form from how far the ray went,
+// and blended over the finished colour. A `Medium` is the real thing, marched
+// in steps through a density field, dimming what's behind it by Beer-Lambert
+// and scattering sunlight towards the eye wherever the sun can see it, which
+// is what makes light shafts.
+
+use anyhow::{bail, Result};
+
+use crate::noise::Noise;
+use crate::point::{Point3, Vector3};
+use crate::real::Float;
+use crate::texture::Colour;
+use crate::DistanceField;
+
+const PI: Float = std::f64::consts::PI as Float;
+
+// Henyey-Greenstein: how much light scatters through an angle with cosine
+// `cos_theta`, per steradian. g in (-1, 1) runs from scattering back towards
+// the light, through all directions alike at 0, to carrying on forwards.
+pub fn henyey_greenstein(cos_theta: Float, g: Float) -> Float {
+    let denom = 1.0 + g * g - 2.0 * g * cos_theta;
+    (1.0 - g * g) / (4.0 * PI * denom * denom.sqrt())
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Fog
+{
+    // The same everywhere, so e^(-density * t) of the colour gets through
+    Exponential { density: Float, colour: Colour },
+    // density * e^(-falloff * (y - base)), pooling low down
+    Height { density: Float, falloff: Float, base: Float, colour: Colour },
+}
+
+impl Fog
+{
+    fn default_colour() -> Colour {
+        Vector3::new(0.7, 0.75, 0.8)
+    }
+
+    // From the command line: exp=density or height=density,falloff[,base]
+    pub fn parse(s: &str) -> Result<Self> {
+        let numbers = |v: &str| v.split(',').map(|c| c.trim().parse::<Float>()).collect::<Result<Vec<_>, _>>();
+        let colour = Fog::default_colour();
+        let fog = match s.split_once('=') {
+            Some(("exp", v)) => Fog::Exponential { density: v.parse()?, colour },
+            Some(("height", v)) => match numbers(v)?[..] {
+                [density, falloff] => Fog::Height { density, falloff, base: 0.0, colour },
+                [density, falloff, base] => Fog::Height { density, falloff, base, colour },
+                _ => bail!("Height fog must be density,falloff[,base], got {}", v),
+            },
+            _ => bail!("Unknown fog {} (exp=density, height=density,falloff[,base])", s),
+        };
+        let (Fog::Exponential { density, .. } | Fog::Height { density, .. }) = fog;
+        if density < 0.0 || !density.is_finite() {
+            bail!("Fog density must be at least zero and finite, got {}", s);
+        }
+        Ok(fog)
+    }
+
+    // Fraction of the light from `t` along the ray that reaches its origin.
+    // `t` can be infinite, for rays that left the scene.
+    pub fn transmittance(&self, origin: &Point3, ray: &Vector3, t: Float) -> Float {
+        let depth = match *self {
+            Fog::Exponential { density, .. } => density * t,
+            Fog::Height { density, falloff, base, .. } => {
+                let start = density * (-falloff * (origin.y - base)).exp();
+                let k = falloff * ray.y;
+                if Float::abs(k) < 1e-6 {
+                    start * t
+                } else {
+                    // The integral of start * e^(-k s) for s in [0, t]
+                    start * (1.0 - (-k * t).exp()) / k
+                }
+            }
+        };
+        // No fog at all, for an infinite distance
+        if depth.is_nan() {
+            return 1.0;
+        }
+        (-depth).exp()
+    }
+
+    pub fn apply(&self, colour: &Colour, origin: &Point3, ray: &Vector3, t: Float) -> Colour {
+        let fog = match self {
+            Fog::Exponential { colour, .. } | Fog::Height { colour, .. } => colour,
+        };
+        let transmittance = self.transmittance(origin, ray, t);
+        colour.scale(transmittance) + fog.scale(1.0 - transmittance)
+    }
+}
+
+// How much of a medium there is at each point: the fraction of light
+// absorbed or scattered per unit distance
+pub trait Density
+{
+    fn density(&self, p: &Point3) -> Float;
+}
+
+impl<D: Density> Density for &D
+{
+    fn density(&self, p: &Point3) -> Float {
+        (*self).density(p)
+    }
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Uniform(pub Float);
+
+impl Density for Uniform
+{
+    fn density(&self, _p: &Point3) -> Float {
+        self.0
+    }
+}
+
+// Another density, but only inside a shape, fading in over `softness` from
+// its surface
+#[derive(Debug, Copy, Clone)]
+pub struct Shaped<F, D>
+{
+    pub field: F,
+    pub inside: D,
+    pub softness: Float,
+}
+
+impl<F: DistanceField, D: Density> Density for Shaped<F, D>
+{
+    fn density(&self, p: &Point3) -> Float {
+        let d = self.field.distance(p);
+        if d >= 0.0 {
+            return 0.0;
+        }
+        let fade = if self.softness > 0.0 { Float::min(-d / self.softness, 1.0) } else { 1.0 };
+        fade * self.inside.density(p)
+    }
+}
+
+// Patchy, like cloud or smoke: `density` times the noise plus `coverage`,
+// where that's positive. Coverage from -1 (none) to 1 (everywhere).
+#[derive(Debug, Copy, Clone)]
+pub struct NoiseDensity<N>
+{
+    pub noise: N,
+    pub density: Float,
+    pub frequency: Float,
+    pub coverage: Float,
+}
+
+impl<N: Noise> Density for NoiseDensity<N>
+{
+    fn density(&self, p: &Point3) -> Float {
+        let f = self.frequency;
+        let n = self.noise.get(&[p.x * f, p.y * f, p.z * f]);
+        self.density * Float::max(n + self.coverage, 0.0)
+    }
+}
+
+// Light passing through a volume, after marching it
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Scattering
+{
+    // Scattered towards the eye along the way
+    pub light: Colour,
+    // Fraction of whatever is behind that gets through
+    pub transmittance: Float,
+}
+
+impl Scattering
+{
+    pub fn apply(&self, colour: &Colour) -> Colour {
+        colour.scale(self.transmittance) + self.light
+    }
+}
+
+// The light falling on a medium: a directional light such as the sun, and
+// light from all around (the sky), taken to be the same in every direction
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Lighting
+{
+    // Unit vector towards the light
+    pub direction: Vector3,
+    pub colour: Colour,
+    pub ambient: Colour,
+}
+
+// Steps towards the light to see how much of the medium is in the way
+const SHADOW_STEPS: u32 = 4;
+
+// A participating medium lit by single scattering from a directional light,
+// plus the ambient light scattered evenly. `albedo` is the fraction of what
+// the medium takes out that it scatters rather than absorbs, per channel.
+// It's marched `steps` times over no more than `max_distance`, and looks
+// `shadow_distance` towards the light for its own shadow.
+#[derive(Debug, Copy, Clone)]
+pub struct Medium<D>
+{
+    pub density: D,
+    pub albedo: Colour,
+    // Henyey-Greenstein g
+    pub anisotropy: Float,
+    pub steps: u32,
+    pub max_distance: Float,
+    pub shadow_distance: Float,
+}
+
+impl<D: Density> Medium<D>
+{
+    pub fn new(density: D) -> Self {
+        Medium {
+            density,
+            albedo: Vector3::splat(0.9),
+            anisotropy: 0.3,
+            steps: 48,
+            max_distance: 40.0,
+            shadow_distance: 10.0,
+        }
+    }
+
+    // Fraction of light getting from p to the light through the medium
+    fn shadow(&self, p: &Point3, light: &Vector3) -> Float {
+        let step = self.shadow_distance / SHADOW_STEPS as Float;
+        let depth: Float = (0..SHADOW_STEPS)
+            .map(|i| self.density.density(&(*p + light.scale((i as Float + 0.5) * step))) * step)
+            .sum();
+        (-depth).exp()
+    }
+
+    // March from `origin` for `t` (capped at `max_distance`) along `ray`.
+    // `visible` says whether a point can see the directional light past the
+    // scene's surfaces.
+    pub fn integrate(&self, origin: &Point3, ray: &Vector3, t: Float, lighting: &Lighting, visible: impl Fn(&Point3) -> bool) -> Scattering {
+        let end = Float::min(t, self.max_distance);
+        let step = end / self.steps as Float;
+        let light = &lighting.direction;
+        let phase = henyey_greenstein(ray.dot_product(light), self.anisotropy);
+
+        // Sample at the same random offset within every step, a different
+        // one per ray, trading banding for noise
+        let jitter = (ray.dot_product(&Vector3::new(12.9898, 78.233, 37.719)).sin() * 43758.5).rem_euclid(1.0);
+
+        let mut light_in = Vector3::zero();
+        let mut transmittance = 1.0;
+        for i in 0..self.steps {
+            let p = *origin + ray.scale((i as Float + jitter) * step);
+            let density = self.density.density(&p);
+            if density <= 0.0 {
+                continue;
+            }
+
+            // Scattered in from this step, exactly for a constant density
+            // across it, dimmed by everything before it
+            let step_transmittance = (-density * step).exp();
+            let mut lit = lighting.ambient;
+            if visible(&p) {
+                lit += lighting.colour.scale(phase * self.shadow(&p, light));
+            }
+            light_in += (self.albedo * lit).scale(transmittance * (1.0 - step_transmittance));
+            transmittance *= step_transmittance;
+        }
+
+        Scattering { light: light_in, transmittance }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::Sphere;
+    use crate::noise::Value;
+
+    fn near(a: Float, b: Float, tolerance: Float) -> bool {
+        Float::abs(a - b) < tolerance
+    }
+
+    #[test]
+    fn phase_function() {
+        for g in [-0.7, 0.0, 0.3, 0.9] {
+            // Integrates to 1 over the sphere
+            let n = 20000;
+            let total: Float = (0..n)
+                .map(|i| henyey_greenstein(-1.0 + (i as Float + 0.5) * 2.0 / n as Float, g) * 2.0 * PI * 2.0 / n as Float)
+                .sum();
+            assert!(near(1.0, total, 1e-3), "{} {}", g, total);
+        }
+        assert!(near(1.0 / (4.0 * PI), henyey_greenstein(0.3, 0.0), 1e-6));
+        assert!(henyey_greenstein(1.0, 0.5) > henyey_greenstein(-1.0, 0.5));
+        assert!(henyey_greenstein(1.0, -0.5) < henyey_greenstein(-1.0, -0.5));
+    }
+
+    #[test]
+    fn exponential_fog() {
+        let colour = Vector3::new(0.5, 0.5, 0.5);
+        let fog = Fog::Exponential { density: 0.1, colour };
+        let o = Point3::origin();
+        let ray = Vector3::new(0.0, 0.0, 1.0);
+        assert!(near((-1.0 as Float).exp(), fog.transmittance(&o, &ray, 10.0), 1e-6));
+        let red = Vector3::new(1.0, 0.0, 0.0);
+        assert_eq!(red, fog.apply(&red, &o, &ray, 0.0));
+        assert_eq!(colour, fog.apply(&red, &o, &ray, Float::INFINITY));
+        let clear = Fog::Exponential { density: 0.0, colour };
+        assert_eq!(red, clear.apply(&red, &o, &ray, Float::INFINITY));
+    }
+
+    #[test]
+    fn height_fog() {
+        let fog = Fog::Height { density: 0.2, falloff: 0.5, base: -1.0, colour: Fog::default_colour() };
+        let Fog::Height { density, falloff, base, .. } = fog else { unreachable!() };
+        let o = Point3::new(0.0, 1.0, 0.0);
+
+        // Against numerical integration, up, down and level
+        for ray in [Vector3::new(0.0, 0.3, 1.0), Vector3::new(0.0, -0.2, 1.0), Vector3::new(1.0, 0.0, 0.0), Vector3::new(1.0, 1e-8, 0.0)] {
+            let ray = ray.normalized();
+            let t = 15.0;
+            let n = 10000;
+            let depth: Float = (0..n)
+                .map(|i| {
+                    let p = o + ray.scale((i as Float + 0.5) * t / n as Float);
+                    density * (-falloff * (p.y - base)).exp() * t / n as Float
+                })
+                .sum();
+            let tolerance = if cfg!(feature = "f32") { 1e-3 } else { 1e-5 };
+            assert!(near((-depth).exp(), fog.transmittance(&o, &ray, t), tolerance), "{:?}", ray);
+        }
+
+        // Looking up out of the scene, some light still gets through; looking
+        // down, none does
+        let up = fog.transmittance(&o, &Vector3::new(0.0, 1.0, 0.0), Float::INFINITY);
+        assert!(up > 0.5 && up < 1.0);
+        assert_eq!(0.0, fog.transmittance(&o, &Vector3::new(0.0, -1.0, 0.0), Float::INFINITY));
+        assert_eq!(0.0, fog.transmittance(&o, &Vector3::new(1.0, 0.0, 0.0), Float::INFINITY));
+    }
+
+    #[test]
+    fn parse_fog() {
+        assert_eq!(Fog::Exponential { density: 0.5, colour: Fog::default_colour() }, Fog::parse("exp=0.5").unwrap());
+        assert_eq!(
+            Fog::Height { density: 0.5, falloff: 0.25, base: 0.0, colour: Fog::default_colour() },
+            Fog::parse("height=0.5,0.25").unwrap()
+        );
+        assert_eq!(
+            Fog::Height { density: 0.5, falloff: 0.25, base: -5.0, colour: Fog::default_colour() },
+            Fog::parse("height=0.5,0.25,-5").unwrap()
+        );
+        assert!(Fog::parse("height=0.5").is_err());
+        assert!(Fog::parse("exp").is_err());
+        assert!(Fog::parse("soup=1").is_err());
+    }
+
+    #[test]
+    fn densities() {
+        let ball = Shaped { field: Sphere { radius: 2.0 }, inside: Uniform(0.5), softness: 1.0 };
+        assert_eq!(0.5, ball.density(&Point3::origin()));
+        assert!(near(0.25, ball.density(&Point3::new(1.5, 0.0, 0.0)), 1e-6));
+        assert_eq!(0.0, ball.density(&Point3::new(2.5, 0.0, 0.0)));
+
+        let patchy = NoiseDensity { noise: Value::new(1), density: 2.0, frequency: 0.5, coverage: 0.0 };
+        let samples: Vec<Float> = (0..500).map(|i| patchy.density(&Point3::new(i as Float * 0.37, 0.0, i as Float * -0.21))).collect();
+        assert!(samples.iter().all(|d| *d >= 0.0 && *d <= 2.0));
+        assert!(samples.contains(&0.0));
+        assert!(samples.iter().any(|d| *d > 0.5));
+        let thick = NoiseDensity { coverage: 1.0, ..patchy };
+        assert!(thick.density(&Point3::new(0.3, 0.0, 0.3)) > patchy.density(&Point3::new(0.3, 0.0, 0.3)));
+    }
+
+    fn sun(direction: Vector3, ambient: Float) -> Lighting {
+        Lighting { direction, colour: Vector3::splat(1.0), ambient: Vector3::splat(ambient) }
+    }
+
+    #[test]
+    fn beer_lambert() {
+        let medium = Medium { steps: 10, ..Medium::new(Uniform(0.2)) };
+        let o = Point3::origin();
+        let ray = Vector3::new(0.0, 0.0, 1.0);
+        let light = sun(Vector3::new(0.0, 1.0, 0.0), 0.0);
+        let r = medium.integrate(&o, &ray, 5.0, &light, |_| false);
+        assert!(near((-1.0 as Float).exp(), r.transmittance, 1e-6));
+        // In the dark, nothing is scattered in
+        assert_eq!(Vector3::zero(), r.light);
+        // Ambient light is scattered in evenly, so a thick enough medium
+        // takes on its colour
+        let r = medium.integrate(&o, &ray, 5.0, &sun(light.direction, 0.5), |_| false);
+        assert!(near(0.9 * 0.5 * (1.0 - r.transmittance), r.light.x, 1e-6));
+        // Rays are cut off at the maximum distance
+        let r = medium.integrate(&o, &ray, Float::INFINITY, &light, |_| false);
+        assert!(near((-8.0 as Float).exp(), r.transmittance, 1e-6));
+    }
+
+    #[test]
+    fn single_scattering() {
+        // In a uniform medium, the light scattered in is
+        // albedo * phase * shadow * (1 - e^(-sigma t))
+        let sigma = 0.1;
+        let medium = Medium { steps: 200, ..Medium::new(Uniform(sigma)) };
+        let o = Point3::origin();
+        let ray = Vector3::new(0.0, 0.0, 1.0);
+        let light = sun(Vector3::new(0.0, 0.6, 0.8), 0.0);
+        let t = 20.0;
+        let r = medium.integrate(&o, &ray, t, &light, |_| true);
+
+        let phase = henyey_greenstein(0.8, medium.anisotropy);
+        let shadow = (-sigma * medium.shadow_distance).exp();
+        let expected = 0.9 * phase * shadow * (1.0 - (-sigma * t).exp());
+        assert!(near(expected, r.light.x, 1e-6), "{} {}", expected, r.light.x);
+        assert_eq!(r.light.x, r.light.z);
+
+        // Light shafts: with half the ray in shadow, about half the light
+        let shaft = medium.integrate(&o, &ray, t, &light, |p| p.z > 10.0);
+        let far_half = expected - 0.9 * phase * shadow * (1.0 - (-sigma * 10.0).exp());
+        assert!(near(far_half, shaft.light.x, 2e-3), "{} {}", far_half, shaft.light.x);
+        assert_eq!(r.transmittance, shaft.transmittance);
+    }
+
+    #[test]
+    fn self_shadowing() {
+        // A thick ball between the sample and the light shades it
+        let cloud = Shaped { field: Sphere { radius: 1.0 }, inside: Uniform(5.0), softness: 0.0 };
+        let medium = Medium::new(&cloud);
+        let light = Vector3::new(0.0, 1.0, 0.0);
+        assert!(medium.shadow(&Point3::new(0.0, -3.0, 0.0), &light) < 0.001);
+        assert_eq!(1.0, medium.shadow(&Point3::new(3.0, -3.0, 0.0), &light));
+    }
+}
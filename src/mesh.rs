@@ -0,0 +1,506 @@
+// Triangle meshes from distance fields, for tools that can't ray-trace.
+//
+// `marching_cubes` samples a field at the corners of a grid of cubes and puts
+// a vertex wherever the sign changes along a cube edge, interpolated between
+// the two distances. Rather than the usual 256-entry table typed in by hand,
+// the triangles for each pattern of inside corners are worked out from the
+// faces: each face of a cube joins up the edges the surface crosses, the same
+// way on both cubes sharing it, so the pieces chain into closed loops and the
+// mesh has no cracks. Where a face has two inside corners diagonally opposite,
+// they're kept apart.
+
+use std::collections::HashMap;
+
+use anyhow::{bail, Result};
+
+use crate::contour::{dual_contour, surface_nets};
+use crate::normal::{calc_normal, NormalMethod};
+use crate::point::{Point3, Vector3};
+use crate::real::Float;
+use crate::DistanceField;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Mesh
+{
+    pub vertices: Vec<Point3>,
+    // Unit normals, one per vertex
+    pub normals: Vec<Vector3>,
+    // Indices into `vertices`, counter-clockwise seen from outside
+    pub triangles: Vec<[u32; 3]>,
+}
+
+impl Mesh
+{
+    pub fn new() -> Self {
+        Mesh::default()
+    }
+
+    fn corners(&self, triangle: &[u32; 3]) -> [Point3; 3] {
+        triangle.map(|i| self.vertices[i as usize])
+    }
+
+    // Unit normal of the triangle's plane, the way it winds; zero if it's
+    // collapsed to a line
+    pub fn face_normal(&self, triangle: &[u32; 3]) -> Vector3 {
+        let [a, b, c] = self.corners(triangle);
+        let n = (b - a).cross_product(&(c - a));
+        if n.mag() > 0.0 {
+            n.normalized()
+        } else {
+            Vector3::zero()
+        }
+    }
+
+    pub fn area(&self) -> Float {
+        self.triangles.iter().map(|t| {
+            let [a, b, c] = self.corners(t);
+            (b - a).cross_product(&(c - a)).mag() / 2.0
+        }).sum()
+    }
+
+    // Volume enclosed, if the mesh is closed. Negative if it's inside out.
+    pub fn volume(&self) -> Float {
+        self.triangles.iter().map(|t| {
+            let [a, b, c] = self.corners(t);
+            a.to_vector().dot_product(&b.to_vector().cross_product(&c.to_vector())) / 6.0
+        }).sum()
+    }
+
+    // Whether every edge is shared by exactly two triangles, going along it
+    // opposite ways, so the mesh is watertight and agrees everywhere on which
+    // way is out
+    pub fn is_closed(&self) -> bool {
+        let mut edges = HashMap::new();
+        for t in &self.triangles {
+            for k in 0..3 {
+                *edges.entry((t[k], t[(k + 1) % 3])).or_insert(0) += 1;
+            }
+        }
+        edges.iter().all(|(&(a, b), &n)| n == 1 && edges.get(&(b, a)) == Some(&1))
+    }
+
+    // Merge vertices closer together than `tolerance`, dropping the triangles
+    // that collapse and any vertices left unused. A merged vertex keeps the
+    // position and normal of the first of them.
+    pub fn weld(&mut self, tolerance: Float) {
+        let cell = |p: &Point3| [p.x, p.y, p.z].map(|v| (v / tolerance).floor() as i64);
+        let mut buckets: HashMap<[i64; 3], Vec<u32>> = HashMap::new();
+        let mut remap = Vec::with_capacity(self.vertices.len());
+
+        for (i, p) in self.vertices.iter().enumerate() {
+            let [x, y, z] = cell(p);
+            // Anything within tolerance is in this cell or a neighbour
+            let mut found = None;
+            'search: for dx in -1..=1 {
+                for dy in -1..=1 {
+                    for dz in -1..=1 {
+                        for &j in buckets.get(&[x + dx, y + dy, z + dz]).into_iter().flatten() {
+                            if (self.vertices[j as usize] - *p).mag() <= tolerance {
+                                found = Some(j);
+                                break 'search;
+                            }
+                        }
+                    }
+                }
+            }
+            remap.push(found.unwrap_or_else(|| {
+                buckets.entry([x, y, z]).or_default().push(i as u32);
+                i as u32
+            }));
+        }
+
+        let triangles = self.triangles.iter()
+            .map(|t| t.map(|i| remap[i as usize]))
+            .filter(|[a, b, c]| a != b && b != c && c != a);
+
+        let mut welded = Mesh::new();
+        let mut index = HashMap::new();
+        for t in triangles {
+            let t = t.map(|i| *index.entry(i).or_insert_with(|| {
+                welded.vertices.push(self.vertices[i as usize]);
+                welded.normals.push(self.normals[i as usize]);
+                (welded.vertices.len() - 1) as u32
+            }));
+            welded.triangles.push(t);
+        }
+        *self = welded;
+    }
+}
+
+// The box a field is sampled in, cut into cubes `resolution` to a side along
+// its longest axis. Surfaces reaching the sides of the box are left open there.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Grid
+{
+    pub min: Point3,
+    pub max: Point3,
+    pub resolution: u32,
+}
+
+impl Grid
+{
+    pub fn new(min: &Point3, max: &Point3, resolution: u32) -> Self {
+        Grid { min: *min, max: *max, resolution }
+    }
+
+    // Edge length of every cube
+    pub fn cell_size(&self) -> Float {
+        let size = self.max - self.min;
+        size.x.max(size.y).max(size.z) / self.resolution.max(1) as Float
+    }
+
+    // Cubes along each axis, covering at least the box
+    pub fn cells(&self) -> [usize; 3] {
+        let size = self.max - self.min;
+        [size.x, size.y, size.z].map(|s| ((s / self.cell_size()).ceil() as usize).max(1))
+    }
+
+    pub fn point(&self, index: [usize; 3]) -> Point3 {
+        let [x, y, z] = index.map(|i| i as Float * self.cell_size());
+        self.min + Vector3::new(x, y, z)
+    }
+}
+
+// Which way to turn a field into a mesh
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Mesher
+{
+    MarchingCubes,
+    SurfaceNets,
+    // With the tolerance cells merge to
+    DualContour(Float),
+}
+
+impl Mesher
+{
+    // "cubes", "nets" or "dual[=tolerance]"
+    pub fn parse(s: &str) -> Result<Self> {
+        Ok(match s.split_once('=') {
+            None if s == "cubes" => Mesher::MarchingCubes,
+            None if s == "nets" => Mesher::SurfaceNets,
+            None if s == "dual" => Mesher::DualContour(0.0),
+            Some(("dual", tolerance)) => Mesher::DualContour(tolerance.parse()?),
+            _ => bail!("Mesher must be cubes, nets or dual[=tolerance], got {}", s),
+        })
+    }
+
+    pub fn mesh<F: DistanceField>(&self, field: &F, grid: &Grid) -> Mesh {
+        match *self {
+            Mesher::MarchingCubes => marching_cubes(field, grid),
+            Mesher::SurfaceNets => surface_nets(field, grid),
+            Mesher::DualContour(tolerance) => dual_contour(field, grid, tolerance),
+        }
+    }
+}
+
+// Cube corners are numbered by bits: 1 for +x, 2 for +y and 4 for +z
+pub(crate) fn corner_offset(corner: usize) -> [usize; 3] {
+    [corner & 1, (corner >> 1) & 1, (corner >> 2) & 1]
+}
+
+// The other two axes, in right-handed order
+pub(crate) fn other_axes(axis: usize) -> (usize, usize) {
+    ((axis + 1) % 3, (axis + 2) % 3)
+}
+
+// Edge 4 * axis + k runs along `axis` from the corner with the other two
+// axes' bits taken from k
+pub(crate) fn edge_corners(edge: usize) -> (usize, usize) {
+    let axis = edge / 4;
+    let (b, c) = other_axes(axis);
+    let low = ((edge & 1) << b) | (((edge >> 1) & 1) << c);
+    (low, low | (1 << axis))
+}
+
+fn edge_between(a: usize, b: usize) -> usize {
+    let axis = (a ^ b).trailing_zeros() as usize;
+    let (p, q) = other_axes(axis);
+    let low = a & b;
+    4 * axis + ((low >> p) & 1) + 2 * ((low >> q) & 1)
+}
+
+fn corner_position(corner: usize) -> Vector3 {
+    let [x, y, z] = corner_offset(corner).map(|v| v as Float);
+    Vector3::new(x, y, z)
+}
+
+fn edge_midpoint(edge: usize) -> Vector3 {
+    let (a, b) = edge_corners(edge);
+    (corner_position(a) + corner_position(b)).scale(0.5)
+}
+
+// The loops the surface makes round a cube with the corners set in `inside`,
+// each a list of the edges it crosses
+pub(crate) fn loops(inside: u8) -> Vec<Vec<u8>> {
+    let is_inside = |corner: usize| inside & (1 << corner) != 0;
+    // Where the surface leaves each crossed edge, going round with the
+    // outside on its right seen from outside, so triangles come out
+    // counter-clockwise
+    let mut next = [None; 12];
+
+    for axis in 0..3 {
+        for side in 0..2 {
+            let (b, c) = other_axes(axis);
+            let base = side << axis;
+            let ring = [base, base | (1 << b), base | (1 << b) | (1 << c), base | (1 << c)];
+            // Out of the cube
+            let normal = corner_position(1 << axis).scale(if side == 0 { -1.0 } else { 1.0 });
+
+            let crossed: Vec<usize> = (0..4)
+                .filter(|&k| is_inside(ring[k]) != is_inside(ring[(k + 1) % 4]))
+                .map(|k| edge_between(ring[k], ring[(k + 1) % 4]))
+                .collect();
+            // Pairs of crossed edges to join, each with the direction from
+            // inside to outside across it
+            let segments = match crossed.len() {
+                2 => {
+                    let centre = |want: bool| {
+                        let corners: Vec<_> = ring.iter().filter(|&&c| is_inside(c) == want).collect();
+                        corners.iter().map(|&&c| corner_position(c)).sum::<Vector3>().scale(1.0 / corners.len() as Float)
+                    };
+                    vec![(crossed[0], crossed[1], centre(false) - centre(true))]
+                }
+                4 => (0..4).filter(|&k| is_inside(ring[k])).map(|k| {
+                    let before = edge_between(ring[(k + 3) % 4], ring[k]);
+                    let after = edge_between(ring[k], ring[(k + 1) % 4]);
+                    let middle = (edge_midpoint(before) + edge_midpoint(after)).scale(0.5);
+                    (before, after, middle - corner_position(ring[k]))
+                }).collect(),
+                _ => vec![],
+            };
+
+            for (a, b, outward) in segments {
+                let forward = outward.cross_product(&normal);
+                if (edge_midpoint(b) - edge_midpoint(a)).dot_product(&forward) > 0.0 {
+                    next[a] = Some(b);
+                } else {
+                    next[b] = Some(a);
+                }
+            }
+        }
+    }
+
+    // Follow each loop round once
+    let mut loops = vec![];
+    let mut visited = [false; 12];
+    for start in 0..12 {
+        if visited[start] || next[start].is_none() {
+            continue;
+        }
+        let mut ring = vec![];
+        let mut edge = start;
+        while !visited[edge] {
+            visited[edge] = true;
+            ring.push(edge as u8);
+            edge = next[edge].expect("Open loop in a cube");
+        }
+        loops.push(ring);
+    }
+    loops
+}
+
+// Triangles, as cube edges, for the corners set in `inside`: each loop as a fan
+fn triangulate(inside: u8) -> Vec<[u8; 3]> {
+    loops(inside).iter()
+        .flat_map(|ring| (1..ring.len() - 1).map(move |i| [ring[0], ring[i], ring[i + 1]]))
+        .collect()
+}
+
+// Mesh the surface of `field` inside `grid`, welding vertices that land on
+// top of each other and giving each vertex the field's gradient as its normal
+pub fn marching_cubes<F: DistanceField>(field: &F, grid: &Grid) -> Mesh {
+    let table: Vec<Vec<[u8; 3]>> = (0..=255).map(triangulate).collect();
+    let [nx, ny, nz] = grid.cells();
+    let points = [nx + 1, ny + 1, nz + 1];
+    let index = |[x, y, z]: [usize; 3]| x + points[0] * (y + points[1] * z);
+
+    let mut samples = vec![0.0; points[0] * points[1] * points[2]];
+    for z in 0..points[2] {
+        for y in 0..points[1] {
+            for x in 0..points[0] {
+                samples[index([x, y, z])] = field.distance(&grid.point([x, y, z]));
+            }
+        }
+    }
+
+    let mut mesh = Mesh::new();
+    // Vertices made so far, by grid point at the low end of the edge, and axis
+    let mut made: HashMap<usize, u32> = HashMap::new();
+
+    for z in 0..nz {
+        for y in 0..ny {
+            for x in 0..nx {
+                let corner = |c: usize| {
+                    let [dx, dy, dz] = corner_offset(c);
+                    [x + dx, y + dy, z + dz]
+                };
+                let inside = (0..8).filter(|&c| samples[index(corner(c))] < 0.0).fold(0u8, |m, c| m | (1 << c));
+
+                for triangle in &table[inside as usize] {
+                    let triangle = triangle.map(|edge| {
+                        let (a, b) = edge_corners(edge as usize);
+                        let (a, b) = (corner(a), corner(b));
+                        let key = 3 * index(a) + edge as usize / 4;
+                        *made.entry(key).or_insert_with(|| {
+                            let (da, db) = (samples[index(a)], samples[index(b)]);
+                            let (pa, pb) = (grid.point(a), grid.point(b));
+                            let p = pa + (pb - pa).scale(da / (da - db));
+                            mesh.vertices.push(p);
+                            mesh.normals.push(calc_normal(field, &p, 1.0, NormalMethod::AutoDiff));
+                            (mesh.vertices.len() - 1) as u32
+                        })
+                    });
+                    mesh.triangles.push(triangle);
+                }
+            }
+        }
+    }
+
+    // Vertices on a grid point are shared by all the edges meeting there
+    mesh.weld(grid.cell_size() * 1e-4);
+    mesh
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::{Cuboid, Sphere};
+
+    const PI: Float = std::f64::consts::PI as Float;
+
+    fn near(a: Float, b: Float, tolerance: Float) -> bool {
+        Float::abs(a - b) < tolerance
+    }
+
+    fn cube() -> Grid {
+        Grid::new(&Point3::new(-1.5, -1.5, -1.5), &Point3::new(1.5, 1.5, 1.5), 24)
+    }
+
+    #[test]
+    fn every_case_closes() {
+        for inside in 0..=255u8 {
+            let triangles = triangulate(inside);
+            assert_eq!(inside == 0 || inside == 255, triangles.is_empty(), "{}", inside);
+            // The edges crossed are the vertices used
+            for edge in 0..12 {
+                let (a, b) = edge_corners(edge);
+                let crossed = (inside >> a & 1) != (inside >> b & 1);
+                assert_eq!(crossed, triangles.iter().flatten().any(|&e| e as usize == edge), "{} {}", inside, edge);
+            }
+        }
+        // One corner in gives one triangle, half the cube a quad
+        assert_eq!(1, triangulate(0b0000_0001).len());
+        assert_eq!(2, triangulate(0b0000_1111).len());
+    }
+
+    #[test]
+    fn grid() {
+        let grid = Grid::new(&Point3::new(0.0, 0.0, 0.0), &Point3::new(4.0, 2.0, 1.0), 8);
+        assert_eq!(0.5, grid.cell_size());
+        assert_eq!([8, 4, 2], grid.cells());
+        assert_eq!(Point3::new(0.5, 1.0, 1.5), grid.point([1, 2, 3]));
+    }
+
+    #[test]
+    fn sphere() {
+        let mesh = marching_cubes(&Sphere { radius: 1.0 }, &cube());
+        assert!(mesh.is_closed());
+        // A sphere is topologically a sphere
+        let edges = mesh.triangles.len() * 3 / 2;
+        assert_eq!(2, mesh.vertices.len() as i64 - edges as i64 + mesh.triangles.len() as i64);
+
+        for (p, n) in mesh.vertices.iter().zip(&mesh.normals) {
+            assert!(near(1.0, p.to_vector().mag(), 0.01), "{:?}", p);
+            assert!(near(1.0, n.dot_product(&p.to_vector().normalized()), 1e-3), "{:?} {:?}", p, n);
+        }
+        assert!(near(4.0 * PI, mesh.area(), 0.1), "{}", mesh.area());
+        assert!(near(4.0 / 3.0 * PI, mesh.volume(), 0.05), "{}", mesh.volume());
+    }
+
+    #[test]
+    fn triangles_face_out() {
+        let mesh = marching_cubes(&Cuboid { dimensions: Vector3::new(1.0, 0.5, 0.7) }, &cube());
+        assert!(mesh.is_closed());
+        assert!(mesh.volume() > 0.0);
+        for t in &mesh.triangles {
+            let [a, b, c] = mesh.corners(t);
+            let facing = (b - a).cross_product(&(c - a));
+            let normal: Vector3 = t.iter().map(|&i| mesh.normals[i as usize]).sum();
+            assert!(facing.dot_product(&normal) > 0.0);
+        }
+    }
+
+    #[test]
+    fn finer_is_closer() {
+        let ball = Sphere { radius: 1.0 };
+        let error = |resolution| {
+            let mesh = marching_cubes(&ball, &Grid { resolution, ..cube() });
+            (4.0 / 3.0 * PI - mesh.volume()).abs()
+        };
+        assert!(error(32) < error(8));
+        assert!(error(8) < 0.5);
+    }
+
+    #[test]
+    fn open_at_the_sides() {
+        // Only the top half of the ball fits
+        let grid = Grid::new(&Point3::new(-1.5, 0.0, -1.5), &Point3::new(1.5, 1.5, 1.5), 24);
+        let mesh = marching_cubes(&Sphere { radius: 1.0 }, &grid);
+        assert!(!mesh.is_closed());
+        assert!(near(2.0 * PI, mesh.area(), 0.1));
+        assert!(mesh.vertices.iter().all(|p| p.y >= 0.0));
+    }
+
+    #[test]
+    fn grid_points_on_the_surface() {
+        // The surface passes exactly through grid points, which all the edges
+        // meeting there put a vertex on
+        let grid = Grid::new(&Point3::new(-1.0, -1.0, -1.0), &Point3::new(1.0, 1.0, 1.0), 4);
+        let slab = Cuboid { dimensions: Vector3::new(2.0, 0.4, 2.0) };
+        let mesh = marching_cubes(&slab, &grid);
+        let mut positions: Vec<_> = mesh.vertices.iter().map(|p| [p.x, p.y, p.z]).collect();
+        positions.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        positions.dedup();
+        assert_eq!(mesh.vertices.len(), positions.len());
+        assert!(mesh.triangles.iter().all(|[a, b, c]| a != b && b != c && c != a));
+    }
+
+    #[test]
+    fn face_normal() {
+        let mesh = marching_cubes(&Sphere { radius: 1.0 }, &cube());
+        for t in &mesh.triangles {
+            let centre: Vector3 = t.iter().map(|&i| mesh.vertices[i as usize].to_vector()).sum();
+            assert!(near(1.0, mesh.face_normal(t).dot_product(&centre.normalized()), 0.05));
+        }
+        let flat = Mesh { vertices: vec![Point3::origin(); 3], normals: vec![Vector3::zero(); 3], triangles: vec![[0, 1, 2]] };
+        assert_eq!(Vector3::zero(), flat.face_normal(&[0, 1, 2]));
+    }
+
+    #[test]
+    fn parse_mesher() {
+        assert_eq!(Mesher::MarchingCubes, Mesher::parse("cubes").unwrap());
+        assert_eq!(Mesher::SurfaceNets, Mesher::parse("nets").unwrap());
+        assert_eq!(Mesher::DualContour(0.0), Mesher::parse("dual").unwrap());
+        assert_eq!(Mesher::DualContour(0.01), Mesher::parse("dual=0.01").unwrap());
+        assert!(Mesher::parse("dual=fine").is_err());
+        assert!(Mesher::parse("tetrahedra").is_err());
+    }
+
+    #[test]
+    fn weld() {
+        let mut mesh = Mesh {
+            vertices: vec![
+                Point3::new(0.0, 0.0, 0.0), Point3::new(1.0, 0.0, 0.0), Point3::new(0.0, 1.0, 0.0),
+                Point3::new(1.0, 0.0, 1e-6), Point3::new(0.0, 1.0, 0.0), Point3::new(1.0, 1.0, 0.0),
+                Point3::new(5.0, 5.0, 5.0),
+            ],
+            normals: vec![Vector3::new(0.0, 0.0, 1.0); 7],
+            triangles: vec![[0, 1, 2], [3, 5, 4], [1, 3, 2]],
+        };
+        mesh.weld(1e-3);
+        // The far vertex is unused, and the last triangle collapses
+        assert_eq!(4, mesh.vertices.len());
+        assert_eq!(vec![[0, 1, 2], [1, 3, 2]], mesh.triangles);
+        assert_eq!(Point3::new(1.0, 1.0, 0.0), mesh.vertices[3]);
+    }
+}
@@ -0,0 +1,87 @@
+// Scene building blocks. Each node is a DistanceField, and composite nodes
+// hold their children by value, so a whole scene is one nested type that
+// evaluates without any dynamic dispatch and works with every scalar type.
+
+use crate::point::{Point3, Vector3};
+use crate::real::{Float, Real};
+use crate::{cuboid, smooth_union, sphere, union, DistanceField};
+
+// Centred on the origin
+#[derive(Debug, Copy, Clone)]
+pub struct Sphere
+{
+    pub radius: Float,
+}
+
+impl DistanceField for Sphere
+{
+    fn distance<T: Real>(&self, p: &Point3<T>) -> T {
+        sphere(self.radius, p)
+    }
+}
+
+// Centred on the origin, `dimensions` being the half extents. Slightly
+// rounded, like the boxes in the demo scene.
+#[derive(Debug, Copy, Clone)]
+pub struct Cuboid
+{
+    pub dimensions: Vector3,
+}
+
+impl DistanceField for Cuboid
+{
+    fn distance<T: Real>(&self, p: &Point3<T>) -> T {
+        cuboid(&self.dimensions, p)
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct Union<A, B>(pub A, pub B);
+
+impl<A: DistanceField, B: DistanceField> DistanceField for Union<A, B>
+{
+    fn distance<T: Real>(&self, p: &Point3<T>) -> T {
+        union(self.0.distance(p), self.1.distance(p))
+    }
+
+    fn lipschitz(&self) -> Float {
+        Float::max(self.0.lipschitz(), self.1.lipschitz())
+    }
+}
+
+// Blends the two over a distance of about `k`
+#[derive(Debug, Copy, Clone)]
+pub struct SmoothUnion<A, B>(pub A, pub B, pub Float);
+
+impl<A: DistanceField, B: DistanceField> DistanceField for SmoothUnion<A, B>
+{
+    fn distance<T: Real>(&self, p: &Point3<T>) -> T {
+        smooth_union(self.0.distance(p), self.1.distance(p), self.2)
+    }
+
+    fn lipschitz(&self) -> Float {
+        Float::max(self.0.lipschitz(), self.1.lipschitz())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_scene_functions() {
+        let p = Point3::new(0.3, -1.2, 2.0);
+        let a = Sphere { radius: 1.0 };
+        let b = Cuboid { dimensions: Vector3::new(0.5, 0.5, 0.5) };
+
+        assert_eq!(sphere(1.0, &p), a.distance(&p));
+        assert_eq!(cuboid(&Vector3::new(0.5, 0.5, 0.5), &p), b.distance(&p));
+        assert_eq!(union(sphere(1.0, &p), cuboid(&b.dimensions, &p)), a.union(b).distance(&p));
+        assert_eq!(
+            smooth_union(sphere(1.0, &p), cuboid(&b.dimensions, &p), 0.5),
+            a.smooth_union(b, 0.5).distance(&p)
+        );
+        // By reference as well as by value
+        assert_eq!(a.union(b).distance(&p), a.union(&b).distance(&p));
+    }
+}
@@ -0,0 +1,794 @@
+// Procedural noise in 2, 3 or 4 dimensions (the 4th usually being time).
+// Every noise is seeded and a pure function of its seed and input, so the
+// same scene renders the same way every time.
+//
+// Noises give their gradient along with their value, worked out
+// analytically. That gives exact normals on displaced surfaces, through
+// `Real::apply3`, and curl noise without finite differences. Plain distances
+// and colours only need the value, which `get` works out on its own.
+
+use std::array::from_fn;
+use std::num::NonZeroU32;
+
+use crate::deform::Displacement;
+use crate::point::{Point3, Vector3};
+use crate::real::{Float, Real};
+
+pub trait Noise
+{
+    // The value and gradient at p. D is 2, 3 or 4.
+    fn sample<const D: usize>(&self, p: &[Float; D]) -> (Float, [Float; D]);
+
+    // Just the value, the same as sample gives, without the work of the
+    // gradient
+    fn get<const D: usize>(&self, p: &[Float; D]) -> Float;
+
+    // Bound on the length of the gradient in D dimensions. Marching
+    // displaced surfaces and terrain relies on it, so it has to hold
+    // everywhere, not just wherever it was measured.
+    fn lipschitz<const D: usize>(&self) -> Float;
+}
+
+impl<N: Noise> Noise for &N
+{
+    fn sample<const D: usize>(&self, p: &[Float; D]) -> (Float, [Float; D]) {
+        (*self).sample(p)
+    }
+
+    fn get<const D: usize>(&self, p: &[Float; D]) -> Float {
+        (*self).get(p)
+    }
+
+    fn lipschitz<const D: usize>(&self) -> Float {
+        (*self).lipschitz::<D>()
+    }
+}
+
+// Hashes of lattice points. The finaliser is Chris Wellons' lowbias32.
+fn mix(mut h: u32) -> u32 {
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x7feb352d);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x846ca68b);
+    h ^ (h >> 16)
+}
+
+fn hash<const D: usize>(seed: u32, cell: &[i32; D]) -> u32 {
+    cell.iter().fold(mix(seed ^ 0x9e3779b9), |h, &c| mix(h ^ c as u32))
+}
+
+// In [0, 1)
+fn unit(h: u32) -> Float {
+    (h as f64 / 4294967296.0) as Float
+}
+
+fn dot<const D: usize>(a: &[Float; D], b: &[Float; D]) -> Float {
+    a.iter().zip(b).map(|(a, b)| a * b).sum()
+}
+
+// The cell p is in, and where in it
+fn lattice<const D: usize>(p: &[Float; D]) -> ([i32; D], [Float; D]) {
+    let cell = p.map(Float::floor);
+    (cell.map(|c| c as i32), from_fn(|k| p[k] - cell[k]))
+}
+
+// 6t^5 - 15t^4 + 10t^3 and its derivative: smooth to the second derivative
+// at cell boundaries
+fn fade(t: Float) -> (Float, Float) {
+    let f = t * t * t * (t * (t * 6.0 - 15.0) + 10.0);
+    let df = 30.0 * t * t * (t - 1.0) * (t - 1.0);
+    (f, df)
+}
+
+// Blend values at the 2^D corners of the cell. `corner` gives the value at a
+// corner (as bits, one per axis) for the offset of p from it, and the
+// value's gradient with respect to that offset.
+fn interpolate<const D: usize>(
+    t: &[Float; D],
+    corner: impl Fn(usize, &[Float; D]) -> (Float, [Float; D]),
+) -> (Float, [Float; D]) {
+    let fades = t.map(fade);
+    let mut value = 0.0;
+    let mut gradient = [0.0; D];
+
+    for bits in 0..1 << D {
+        let high = |k: usize| bits >> k & 1 == 1;
+        let offset = from_fn(|k| if high(k) { t[k] - 1.0 } else { t[k] });
+        let (n, dn) = corner(bits, &offset);
+
+        let weights: [Float; D] = from_fn(|k| if high(k) { fades[k].0 } else { 1.0 - fades[k].0 });
+        let w: Float = weights.iter().product();
+        value += w * n;
+        for j in 0..D {
+            let others: Float = (0..D).filter(|&k| k != j).map(|k| weights[k]).product();
+            let dw = if high(j) { fades[j].1 } else { -fades[j].1 };
+            gradient[j] += w * dn[j] + others * dw * n;
+        }
+    }
+    (value, gradient)
+}
+
+// The value alone, summed the same way
+fn interpolate_value<const D: usize>(t: &[Float; D], corner: impl Fn(usize, &[Float; D]) -> Float) -> Float {
+    let fades = t.map(|t| fade(t).0);
+    let mut value = 0.0;
+    for bits in 0..1 << D {
+        let high = |k: usize| bits >> k & 1 == 1;
+        let offset = from_fn(|k| if high(k) { t[k] - 1.0 } else { t[k] });
+        let w: Float = (0..D).map(|k| if high(k) { fades[k] } else { 1.0 - fades[k] }).product();
+        value += w * corner(bits, &offset);
+    }
+    value
+}
+
+fn corner_cell<const D: usize>(cell: &[i32; D], bits: usize) -> [i32; D] {
+    from_fn(|k| cell[k] + (bits >> k & 1) as i32)
+}
+
+// A gradient for a lattice point: the diagonals in 2D, and in higher
+// dimensions the middles of the hypercube's edges, as in improved Perlin
+// noise. None is much longer than the others, so no direction stands out.
+fn gradient<const D: usize>(h: u32) -> [Float; D] {
+    let zero = if D > 2 { (h >> D) as usize % D } else { D };
+    from_fn(|k| match k {
+        _ if k == zero => 0.0,
+        _ if h >> k & 1 == 0 => 1.0,
+        _ => -1.0,
+    })
+}
+
+// Length of every gradient above: components of ±1, one of them zero past 2D
+fn gradient_norm<const D: usize>() -> Float {
+    Float::sqrt(if D > 2 { D - 1 } else { D } as Float)
+}
+
+// The entry of a table for 2, 3 and 4 dimensions. Any other dimension fails
+// to compile wherever it's used.
+fn scale_for<const D: usize>(scales: &[Float; 3]) -> Float {
+    const { assert!(D >= 2 && D <= 4, "Gradient noise comes in 2, 3 and 4 dimensions") };
+    scales[D - 2]
+}
+
+// Random values at lattice points, smoothly interpolated. Blobby and a bit
+// blocky, but cheap. In [-1, 1].
+#[derive(Debug, Default, Copy, Clone)]
+pub struct Value
+{
+    pub seed: u32,
+}
+
+impl Value
+{
+    pub fn new(seed: u32) -> Self {
+        Value { seed }
+    }
+
+    fn corner<const D: usize>(&self, cell: &[i32; D], bits: usize) -> Float {
+        unit(hash(self.seed, &corner_cell(cell, bits))) * 2.0 - 1.0
+    }
+}
+
+impl Noise for Value
+{
+    fn sample<const D: usize>(&self, p: &[Float; D]) -> (Float, [Float; D]) {
+        let (cell, t) = lattice(p);
+        interpolate(&t, |bits, _| (self.corner(&cell, bits), [0.0; D]))
+    }
+
+    fn get<const D: usize>(&self, p: &[Float; D]) -> Float {
+        let (cell, t) = lattice(p);
+        interpolate_value(&t, |bits, _| self.corner(&cell, bits))
+    }
+
+    // Along each axis, the fade's slope (at most 15/8) times the difference
+    // between corners (at most 2)
+    fn lipschitz<const D: usize>(&self) -> Float {
+        15.0 / 8.0 * 2.0 * Float::sqrt(D as Float)
+    }
+}
+
+// Perlin's gradient noise: zero on the lattice, with random slopes there.
+// Roughly in [-1, 1].
+#[derive(Debug, Default, Copy, Clone)]
+pub struct Perlin
+{
+    pub seed: u32,
+}
+
+impl Perlin
+{
+    pub fn new(seed: u32) -> Self {
+        Perlin { seed }
+    }
+
+    fn gradient<const D: usize>(&self, cell: &[i32; D], bits: usize) -> [Float; D] {
+        gradient::<D>(hash(self.seed, &corner_cell(cell, bits)))
+    }
+}
+
+impl Noise for Perlin
+{
+    fn sample<const D: usize>(&self, p: &[Float; D]) -> (Float, [Float; D]) {
+        let (cell, t) = lattice(p);
+        let (v, dv) = interpolate(&t, |bits, offset| {
+            let g = self.gradient(&cell, bits);
+            (dot(&g, offset), g)
+        });
+        let scale = scale_for::<D>(&PERLIN_SCALE);
+        (v * scale, dv.map(|d| d * scale))
+    }
+
+    fn get<const D: usize>(&self, p: &[Float; D]) -> Float {
+        let (cell, t) = lattice(p);
+        interpolate_value(&t, |bits, offset| dot(&self.gradient(&cell, bits), offset)) * scale_for::<D>(&PERLIN_SCALE)
+    }
+
+    // Along axis j the gradient is
+    //
+    //   sum of w_c g_c[j]  +  fade'(t_j) (B - A)
+    //
+    // where w_c are the corner weights and A and B the interpolated corner
+    // values over the faces at either end of the cell along j. The first is
+    // an average of gradients, so together those terms are no longer than
+    // the longest gradient. A corner's value g . x is at most the sum of
+    // |x_k|, with |x_j| = t_j on face A and 1 - t_j on face B, and along each
+    // other axis the weights average |x_k| to (1 - f) t + f (1 - t) <= 1/2
+    // (the fade f is below t for t < 1/2). So |A| + |B| <= D and, with the
+    // fade's slope at most 15/8, the second terms are at most 15/8 D each.
+    fn lipschitz<const D: usize>(&self) -> Float {
+        let n = D as Float;
+        scale_for::<D>(&PERLIN_SCALE) * (gradient_norm::<D>() + 15.0 / 8.0 * n * n.sqrt())
+    }
+}
+
+// Bring the largest values near 1
+const PERLIN_SCALE: [Float; 3] = [1.0, 1.0, 0.95];
+
+// Perlin's simplex noise: gradient noise on a lattice of simplices, so each
+// point only sums D + 1 corners, and without the axis-aligned look of the
+// hypercube lattice. Roughly in [-1, 1].
+#[derive(Debug, Default, Copy, Clone)]
+pub struct Simplex
+{
+    pub seed: u32,
+}
+
+impl Simplex
+{
+    pub fn new(seed: u32) -> Self {
+        Simplex { seed }
+    }
+}
+
+// Radius of each corner's kernel squared: 0.5 keeps the kernel inside the
+// simplices that share the corner, so the noise is continuous. (The 0.6
+// often used in 3D and 4D isn't.)
+const SIMPLEX_RADIUS: Float = 0.5;
+
+// Scales that bring the largest values near 1, for 2, 3 and 4 dimensions
+const SIMPLEX_SCALE: [Float; 3] = [64.0, 72.0, 58.0];
+
+impl Simplex
+{
+    // Calls `f` with the offset x of p from each corner of the simplex p is
+    // in, the corner's gradient g and a = r² - |x|², for the corners whose
+    // kernels reach p
+    fn corners<const D: usize>(&self, p: &[Float; D], mut f: impl FnMut(&[Float; D], &[Float; D], Float)) {
+        // Skew onto the hypercube lattice to find the cell, then back
+        let n = D as Float;
+        let skew = ((n + 1.0).sqrt() - 1.0) / n;
+        let unskew = (1.0 - 1.0 / (n + 1.0).sqrt()) / n;
+        let s = p.iter().sum::<Float>() * skew;
+        let cell = p.map(|v| (v + s).floor());
+        let t = cell.iter().sum::<Float>() * unskew;
+        let x0: [Float; D] = from_fn(|k| p[k] - (cell[k] - t));
+        let cell = cell.map(|c| c as i32);
+
+        // The simplex containing p runs from the cell's corner along the
+        // axes in order of how far p is along each
+        let mut order: [usize; D] = from_fn(|k| k);
+        order.sort_by(|a, b| x0[*b].total_cmp(&x0[*a]));
+
+        let mut offset = [0; D];
+        for k in 0..=D {
+            if k > 0 {
+                offset[order[k - 1]] += 1;
+            }
+            let x: [Float; D] = from_fn(|j| x0[j] - offset[j] as Float + k as Float * unskew);
+            let a = SIMPLEX_RADIUS - dot(&x, &x);
+            if a > 0.0 {
+                let corner: [i32; D] = from_fn(|j| cell[j] + offset[j]);
+                f(&x, &gradient::<D>(hash(self.seed, &corner)), a);
+            }
+        }
+    }
+}
+
+impl Noise for Simplex
+{
+    fn sample<const D: usize>(&self, p: &[Float; D]) -> (Float, [Float; D]) {
+        let mut value = 0.0;
+        let mut gradient = [0.0; D];
+        self.corners(p, |x, g, a| {
+            let gx = dot(g, x);
+            let a3 = a * a * a;
+            value += a3 * a * gx;
+            for j in 0..D {
+                gradient[j] += a3 * a * g[j] - 8.0 * a3 * gx * x[j];
+            }
+        });
+        let scale = scale_for::<D>(&SIMPLEX_SCALE);
+        (value * scale, gradient.map(|d| d * scale))
+    }
+
+    fn get<const D: usize>(&self, p: &[Float; D]) -> Float {
+        let mut value = 0.0;
+        self.corners(p, |x, g, a| value += a * a * a * a * dot(g, x));
+        value * scale_for::<D>(&SIMPLEX_SCALE)
+    }
+
+    // A corner's term is a^4 (g . x) with a = r² - |x|², and its gradient,
+    // split along and across x, is a^4 g across and a³ (a - 8|x|²) g along.
+    // So it's no longer than |g| a³ max(a, |a - 8|x|²|), which for r² = 1/2
+    // peaks at |g| / 16 at the corner itself, and beyond |x|² = 1/6 falls
+    // away. Corners of a simplex are at least sqrt(D / (D + 1)) apart, so
+    // only the nearest can be within half that; the other D are at least
+    // |x|² = D / (4 (D + 1)) >= 1/6 away.
+    fn lipschitz<const D: usize>(&self) -> Float {
+        let n = D as Float;
+        let x2 = n / (4.0 * (n + 1.0));
+        let a = SIMPLEX_RADIUS - x2;
+        let far = a * a * a * Float::max(a, Float::abs(a - 8.0 * x2));
+        scale_for::<D>(&SIMPLEX_SCALE) * gradient_norm::<D>() * (SIMPLEX_RADIUS.powi(4) + n * far)
+    }
+}
+
+// Worley's cellular noise: the distance to the nearest of a set of points
+// scattered one per lattice cell. In [0, sqrt(D)], though rarely above 1.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct Worley
+{
+    pub seed: u32,
+}
+
+impl Worley
+{
+    pub fn new(seed: u32) -> Self {
+        Worley { seed }
+    }
+
+    // The point scattered in a cell
+    fn feature<const D: usize>(&self, cell: &[i32; D]) -> [Float; D] {
+        let h = hash(self.seed, cell);
+        from_fn(|k| cell[k] as Float + unit(mix(h.wrapping_add(k as u32 + 1))))
+    }
+
+    // Squared distance to the nearest feature point, and p's offset from it
+    fn nearest<const D: usize>(&self, p: &[Float; D]) -> (Float, [Float; D]) {
+        let (cell, _) = lattice(p);
+        let mut nearest = (Float::MAX, [0.0; D]);
+        for n in 0..3usize.pow(D as u32) {
+            let mut m = n;
+            let neighbour: [i32; D] = from_fn(|k| {
+                let o = (m % 3) as i32 - 1;
+                m /= 3;
+                cell[k] + o
+            });
+            let f = self.feature(&neighbour);
+            let d: [Float; D] = from_fn(|k| p[k] - f[k]);
+            let r2 = dot(&d, &d);
+            if r2 < nearest.0 {
+                nearest = (r2, d);
+            }
+        }
+        nearest
+    }
+}
+
+impl Noise for Worley
+{
+    fn sample<const D: usize>(&self, p: &[Float; D]) -> (Float, [Float; D]) {
+        let (r2, d) = self.nearest(p);
+        let r = r2.sqrt();
+        let gradient = if r > 0.0 { d.map(|d| d / r) } else { [0.0; D] };
+        (r, gradient)
+    }
+
+    fn get<const D: usize>(&self, p: &[Float; D]) -> Float {
+        self.nearest(p).0.sqrt()
+    }
+
+    // A distance
+    fn lipschitz<const D: usize>(&self) -> Float {
+        1.0
+    }
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum FractalKind
+{
+    // Fractional Brownian motion, the plain sum. In [-1, 1].
+    Fbm,
+    // Sums of |noise|, creased where the noise crosses zero. In [0, 1].
+    Turbulence,
+    // (1 - |noise|)², sharp ridges where the noise crosses zero. In [0, 1].
+    Ridged,
+}
+
+// Octaves of a noise summed together, each `lacunarity` times the frequency
+// and `gain` times the amplitude of the last. Normalised by the total
+// amplitude so the range doesn't depend on the number of octaves, which
+// can't be zero.
+#[derive(Debug, Copy, Clone)]
+pub struct Fractal<N>
+{
+    pub noise: N,
+    pub kind: FractalKind,
+    pub octaves: NonZeroU32,
+    pub lacunarity: Float,
+    pub gain: Float,
+}
+
+impl<N: Noise> Fractal<N>
+{
+    fn new(noise: N, kind: FractalKind) -> Self {
+        Fractal {
+            noise,
+            kind,
+            octaves: NonZeroU32::new(5).unwrap(),
+            lacunarity: 2.0,
+            gain: 0.5,
+        }
+    }
+
+    pub fn fbm(noise: N) -> Self {
+        Fractal::new(noise, FractalKind::Fbm)
+    }
+
+    pub fn turbulence(noise: N) -> Self {
+        Fractal::new(noise, FractalKind::Turbulence)
+    }
+
+    pub fn ridged(noise: N) -> Self {
+        Fractal::new(noise, FractalKind::Ridged)
+    }
+
+    // Amplitude and frequency of each octave
+    fn octave_scales(&self) -> impl Iterator<Item = (Float, Float)> + '_ {
+        (0..self.octaves.get() as i32).map(|i| (self.gain.powi(i), self.lacunarity.powi(i)))
+    }
+
+    pub(crate) fn total_amplitude(&self) -> Float {
+        self.octave_scales().map(|(a, _)| a).sum()
+    }
+}
+
+impl<N: Noise> Fractal<N>
+{
+    // An octave's noise value shaped for the kind of fractal, and the slope
+    // of that shaping
+    fn shape(&self, n: Float) -> (Float, Float) {
+        let sign = if n < 0.0 { -1.0 } else { 1.0 };
+        match self.kind {
+            FractalKind::Fbm => (n, 1.0),
+            FractalKind::Turbulence => (n.abs(), sign),
+            FractalKind::Ridged => {
+                let r = 1.0 - n.abs();
+                (r * r, -2.0 * r * sign)
+            }
+        }
+    }
+
+    // Shift each octave so they don't all line up at the origin
+    fn octave_point<const D: usize>(i: usize, frequency: Float, p: &[Float; D]) -> [Float; D] {
+        let shift = i as Float * 17.31;
+        p.map(|v| v * frequency + shift)
+    }
+}
+
+impl<N: Noise> Noise for Fractal<N>
+{
+    fn sample<const D: usize>(&self, p: &[Float; D]) -> (Float, [Float; D]) {
+        let mut value = 0.0;
+        let mut gradient = [0.0; D];
+        for (i, (amplitude, frequency)) in self.octave_scales().enumerate() {
+            let (n, dn) = self.noise.sample(&Self::octave_point(i, frequency, p));
+            let (v, dv) = self.shape(n);
+            value += amplitude * v;
+            for (g, dn) in gradient.iter_mut().zip(dn) {
+                *g += amplitude * dv * frequency * dn;
+            }
+        }
+
+        let total = self.total_amplitude();
+        (value / total, gradient.map(|g| g / total))
+    }
+
+    fn get<const D: usize>(&self, p: &[Float; D]) -> Float {
+        let mut value = 0.0;
+        for (i, (amplitude, frequency)) in self.octave_scales().enumerate() {
+            let n = self.noise.get(&Self::octave_point(i, frequency, p));
+            value += amplitude * self.shape(n).0;
+        }
+        value / self.total_amplitude()
+    }
+
+    fn lipschitz<const D: usize>(&self) -> Float {
+        let slope = if self.kind == FractalKind::Ridged { 2.0 } else { 1.0 };
+        let sum: Float = self.octave_scales().map(|(a, f)| a * f).sum();
+        self.noise.lipschitz::<D>() * slope * sum / self.total_amplitude()
+    }
+}
+
+// Curl noise: the curl of a noise potential, a swirling flow field with no
+// sources or sinks, for advecting particles or warping domains. Components
+// are in roughly [-L, L], L being the noise's Lipschitz bound.
+//
+// In 2D the potential is the noise itself, and the flow runs along its
+// contours.
+pub fn curl2<N: Noise>(noise: &N, p: &[Float; 2]) -> [Float; 2] {
+    let (_, d) = noise.sample(p);
+    [d[1], -d[0]]
+}
+
+// Offsets to three unrelated potentials, one per component
+const POTENTIALS: [Float; 3] = [0.0, 31.41, -47.13];
+
+// In 3D the potential has three components, each the noise somewhere else
+pub fn curl3<N: Noise>(noise: &N, p: &[Float; 3]) -> [Float; 3] {
+    let d = POTENTIALS.map(|o| noise.sample(&p.map(|v| v + o)).1);
+    [d[2][1] - d[1][2], d[0][2] - d[2][0], d[1][0] - d[0][1]]
+}
+
+// A 3D flow that changes over time, the 4th coordinate. Divergence free at
+// every moment.
+pub fn curl4<N: Noise>(noise: &N, p: &[Float; 4]) -> [Float; 3] {
+    let d = POTENTIALS.map(|o| noise.sample(&[p[0] + o, p[1] + o, p[2] + o, p[3]]).1);
+    [d[2][1] - d[1][2], d[0][2] - d[2][0], d[1][0] - d[0][1]]
+}
+
+// Noise as a displacement for `DistanceField::displace`:
+// amplitude * noise(frequency * p)
+#[derive(Debug, Copy, Clone)]
+pub struct NoiseDisplacement<N>
+{
+    pub noise: N,
+    pub amplitude: Float,
+    pub frequency: Float,
+}
+
+impl<N: Noise> NoiseDisplacement<N>
+{
+    pub fn new(noise: N, amplitude: Float, frequency: Float) -> Self {
+        NoiseDisplacement { noise, amplitude, frequency }
+    }
+}
+
+impl<N: Noise> Displacement for NoiseDisplacement<N>
+{
+    fn displacement<T: Real>(&self, p: &Point3<T>) -> T {
+        let (a, f) = (self.amplitude, self.frequency);
+        T::apply3(
+            p,
+            |p| self.noise.get(&[p.x * f, p.y * f, p.z * f]) * a,
+            |p| {
+                let (v, d) = self.noise.sample(&[p.x * f, p.y * f, p.z * f]);
+                (v * a, Vector3::new(d[0], d[1], d[2]) * (a * f))
+            },
+        )
+    }
+
+    fn lipschitz(&self) -> Float {
+        Float::abs(self.amplitude * self.frequency) * self.noise.lipschitz::<3>()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::march::{cast_field, HitStatus, MarchSettings};
+    use crate::node::Sphere;
+    use crate::testing::{assert_packet_and_gradient, scatter};
+    use crate::DistanceField;
+
+    fn near(a: Float, b: Float) -> bool {
+        Float::abs(a - b) < 1e-4 * (1.0 + a.abs())
+    }
+
+    // Points spread over a few hundred cells, negative coordinates included
+    fn points<const D: usize>(count: usize) -> impl Iterator<Item = [Float; D]> {
+        scatter(count, 10.0)
+    }
+
+    // The analytic gradient against finite differences. Ridged, turbulent
+    // and cellular noise have creases, where only a one sided difference
+    // agrees.
+    fn gradients_match<N: Noise, const D: usize>(noise: &N, name: &str, creased: bool) {
+        let (h, tolerance) = if cfg!(feature = "f32") { (1e-3, 1e-1) } else { (1e-6, 1e-3) };
+        for p in points::<D>(300) {
+            let (v, d) = noise.sample(&p);
+            for k in 0..D {
+                let at = |o: Float| {
+                    let mut q = p;
+                    q[k] += o;
+                    noise.get(&q)
+                };
+                let differences = [(at(h) - at(-h)) / (2.0 * h), (at(h) - v) / h, (v - at(-h)) / h];
+                let differences = if creased { &differences[..] } else { &differences[..1] };
+                assert!(
+                    differences.iter().any(|n| Float::abs(n - d[k]) < tolerance * (1.0 + d[k].abs())),
+                    "{} {}D at {:?}: {} vs {:?}",
+                    name, D, p, d[k], differences
+                );
+            }
+        }
+    }
+
+    fn check_gradients<N: Noise>(noise: &N, name: &str, creased: bool) {
+        gradients_match::<N, 2>(noise, name, creased);
+        gradients_match::<N, 3>(noise, name, creased);
+        gradients_match::<N, 4>(noise, name, creased);
+    }
+
+    #[test]
+    fn gradients() {
+        check_gradients(&Value::new(1), "value", false);
+        check_gradients(&Perlin::new(2), "perlin", false);
+        check_gradients(&Simplex::new(3), "simplex", false);
+        check_gradients(&Worley::new(4), "worley", true);
+        fn octaves<N: Noise>(f: Fractal<N>) -> Fractal<N> {
+            Fractal { octaves: NonZeroU32::new(3).unwrap(), ..f }
+        }
+        check_gradients(&octaves(Fractal::fbm(Simplex::new(5))), "fbm", false);
+        check_gradients(&octaves(Fractal::ridged(Simplex::new(6))), "ridged", true);
+        check_gradients(&octaves(Fractal::turbulence(Perlin::new(7))), "turbulence", true);
+    }
+
+    fn values_match<N: Noise, const D: usize>(noise: &N, name: &str) {
+        for p in points::<D>(300) {
+            assert_eq!(noise.sample(&p).0, noise.get(&p), "{} {}D at {:?}", name, D, p);
+        }
+    }
+
+    #[test]
+    fn values_without_gradients() {
+        fn check<N: Noise>(noise: &N, name: &str) {
+            values_match::<N, 2>(noise, name);
+            values_match::<N, 3>(noise, name);
+            values_match::<N, 4>(noise, name);
+        }
+        check(&Value::new(1), "value");
+        check(&Perlin::new(2), "perlin");
+        check(&Simplex::new(3), "simplex");
+        check(&Worley::new(4), "worley");
+        check(&Fractal::ridged(Simplex::new(5)), "ridged");
+        check(&Fractal::turbulence(Perlin::new(6)), "turbulence");
+    }
+
+    // Smallest and largest value, and the steepest gradient
+    fn extremes<N: Noise, const D: usize>(noise: &N) -> (Float, Float, Float) {
+        points::<D>(5000).fold((Float::MAX, Float::MIN, 0.0), |(lo, hi, g), p| {
+            let (v, d) = noise.sample(&p);
+            (lo.min(v), hi.max(v), Float::max(g, dot(&d, &d).sqrt()))
+        })
+    }
+
+    fn check_range<N: Noise>(noise: &N, name: &str, lo: Float, hi: Float) {
+        for (d, (min, max, steepest)) in [(2, extremes::<N, 2>(noise)), (3, extremes::<N, 3>(noise)), (4, extremes::<N, 4>(noise))] {
+            assert!(min >= lo && max <= hi, "{} {}D: [{}, {}]", name, d, min, max);
+            // Nowhere near the ends would suggest a bad scale
+            assert!(max - min > (hi - lo) * 0.5, "{} {}D", name, d);
+            let bound = [noise.lipschitz::<2>(), noise.lipschitz::<3>(), noise.lipschitz::<4>()][d - 2];
+            assert!(steepest <= bound, "{} {}D: gradient up to {}", name, d, steepest);
+        }
+    }
+
+    #[test]
+    fn ranges() {
+        check_range(&Value::new(1), "value", -1.0, 1.0);
+        check_range(&Perlin::new(1), "perlin", -1.0, 1.0);
+        check_range(&Simplex::new(1), "simplex", -1.0, 1.0);
+        check_range(&Fractal::fbm(Perlin::new(1)), "fbm", -1.0, 1.0);
+        check_range(&Fractal::turbulence(Simplex::new(1)), "turbulence", 0.0, 1.0);
+        check_range(&Fractal::ridged(Simplex::new(1)), "ridged", 0.0, 1.0);
+
+        // The bounds as derived
+        let (r2, r3) = (Float::sqrt(2.0), Float::sqrt(3.0));
+        assert!(near(15.0 / 4.0 * r2, Value::new(1).lipschitz::<2>()));
+        assert!(near(15.0 / 4.0 * 2.0, Value::new(1).lipschitz::<4>()));
+        assert!(near(r2 + 15.0 / 8.0 * 2.0 * r2, Perlin::new(1).lipschitz::<2>()));
+        assert!(near(r2 + 15.0 / 8.0 * 3.0 * r3, Perlin::new(1).lipschitz::<3>()));
+        assert!(near(0.95 * (r3 + 15.0), Perlin::new(1).lipschitz::<4>()));
+        // Corners 1/6, 3/16 and 1/5 away (squared) for 2, 3 and 4D
+        let far = |x2: Float| (0.5 - x2).powi(3) * (9.0 * x2 - 0.5);
+        assert!(near(64.0 * r2 * (1.0 / 16.0 + 2.0 * far(1.0 / 6.0)), Simplex::new(1).lipschitz::<2>()));
+        assert!(near(72.0 * r2 * (1.0 / 16.0 + 3.0 * far(3.0 / 16.0)), Simplex::new(1).lipschitz::<3>()));
+        assert!(near(58.0 * r3 * (1.0 / 16.0 + 4.0 * far(1.0 / 5.0)), Simplex::new(1).lipschitz::<4>()));
+
+        let (min, max, _) = extremes::<_, 3>(&Worley::new(1));
+        assert!(min >= 0.0 && max <= Float::sqrt(3.0));
+    }
+
+    #[test]
+    fn seeded() {
+        let p = [1.3, -2.7, 0.4];
+        for seed in [0, 1, 12345] {
+            assert_eq!(Perlin::new(seed).get(&p), Perlin::new(seed).get(&p));
+            assert_eq!(Worley::new(seed).sample(&p), Worley::new(seed).sample(&p));
+        }
+        assert_ne!(Perlin::new(1).get(&p), Perlin::new(2).get(&p));
+        assert_ne!(Simplex::new(1).get(&p), Simplex::new(2).get(&p));
+        assert_ne!(Value::new(1).get(&p), Value::new(2).get(&p));
+        assert_ne!(Worley::new(1).get(&p), Worley::new(2).get(&p));
+
+        // Values as they are now, so a change to the hashing shows up
+        assert!(Float::abs(Perlin::new(7).get(&p) - PERLIN_7) < 1e-5);
+    }
+
+    const PERLIN_7: Float = 0.0868608;
+
+    #[test]
+    fn lattice_points() {
+        // Gradient noise is zero at the lattice, value noise is the corner's value
+        for p in [[0.0, 0.0, 0.0], [3.0, -2.0, 7.0], [-1.0, -1.0, -1.0]] {
+            assert_eq!(0.0, Perlin::new(3).get(&p));
+            let (cell, _) = lattice(&p);
+            assert_eq!(unit(hash(3, &cell)) * 2.0 - 1.0, Value::new(3).get(&p));
+        }
+        // Cellular noise is zero on a feature point
+        let w = Worley::new(3);
+        let f = w.feature(&[4, -2]);
+        assert_eq!(0.0, w.get(&f));
+    }
+
+    #[test]
+    fn fractal_bound() {
+        // Five octaves at double the frequency and half the amplitude each:
+        // every octave's slope is the same, and they sum to 5 / 1.9375 times
+        let fbm = Fractal::fbm(Perlin::new(1));
+        assert!(near(Perlin::new(1).lipschitz::<3>() * 5.0 / 1.9375, fbm.lipschitz::<3>()));
+        assert!(near(2.0 * fbm.lipschitz::<3>(), Fractal::ridged(Perlin::new(1)).lipschitz::<3>()));
+    }
+
+    // Divergence by central differences
+    fn divergence<const D: usize, const C: usize>(p: &[Float; D], field: impl Fn(&[Float; D]) -> [Float; C]) -> Float {
+        let h = 1e-3;
+        (0..C)
+            .map(|k| {
+                let (mut q, mut r) = (*p, *p);
+                q[k] += h;
+                r[k] -= h;
+                (field(&q)[k] - field(&r)[k]) / (2.0 * h)
+            })
+            .sum()
+    }
+
+    #[test]
+    fn curl_is_divergence_free() {
+        let noise = Perlin::new(9);
+        for p in points::<4>(100) {
+            let p3 = [p[0], p[1], p[2]];
+            let p2 = [p[0], p[1]];
+            let scale = curl3(&noise, &p3).iter().map(|v| v.abs()).sum::<Float>();
+            assert!(divergence(&p3, |p| curl3(&noise, p)).abs() < 1e-2 * (1.0 + scale));
+            assert!(divergence(&p, |p| curl4(&noise, p)).abs() < 1e-2 * (1.0 + scale));
+            assert!(divergence(&p2, |p| curl2(&noise, p)).abs() < 1e-2 * (1.0 + scale));
+        }
+        // But not zero everywhere
+        assert!(points::<3>(100).any(|p| curl3(&noise, &p)[0].abs() > 0.5));
+    }
+
+    #[test]
+    fn displacement() {
+        let bumps = NoiseDisplacement::new(Fractal::fbm(Simplex::new(2)), 0.1, 3.0);
+        let shape = Sphere { radius: 1.0 }.displace(bumps);
+        assert!(near(1.0 + 0.3 * Fractal::fbm(Simplex::new(2)).lipschitz::<3>(), shape.lipschitz()));
+
+        assert_packet_and_gradient(&shape);
+
+        let settings = MarchSettings { t_min: 0.0, max_steps: 500, ..Default::default() };
+        let r = cast_field(&shape, &Point3::new(0.0, 0.0, -5.0), &Vector3::new(0.0, 0.0, 1.0), &settings);
+        assert_eq!(HitStatus::Hit, r.status);
+        assert!(Float::abs(shape.distance(&r.position)) < 1e-3);
+    }
+}
@@ -0,0 +1,138 @@
+use anyhow::{bail, Result};
+
+use crate::dual::Dual;
+use crate::real::Float;
+use crate::point::{Point3, Vector3};
+use crate::DistanceField;
+
+// Finite difference offset per unit of distance from the eye, so that
+// differences are taken over roughly the same on-screen size everywhere
+const EPSILON: Float = 0.0005;
+
+#[derive(Debug, Default, PartialEq, Copy, Clone)]
+pub enum NormalMethod
+{
+    // Exact gradient from a single evaluation on dual numbers
+    #[default]
+    AutoDiff,
+    // Six evaluations, second order accurate
+    Central,
+    // Four evaluations, one of them the distance itself
+    Forward,
+    // Four evaluations at the corners of a tetrahedron (after iq)
+    Tetrahedral,
+}
+
+impl NormalMethod
+{
+    pub fn parse(s: &str) -> Result<Self> {
+        Ok(match s {
+            "autodiff" => NormalMethod::AutoDiff,
+            "central" => NormalMethod::Central,
+            "forward" => NormalMethod::Forward,
+            "tetrahedral" => NormalMethod::Tetrahedral,
+            _ => bail!("Unknown normal method {} (autodiff, central, forward, tetrahedral)", s),
+        })
+    }
+}
+
+// The surface normal at `position`, found at distance `t` along a ray
+pub fn calc_normal<F: DistanceField>(field: &F, position: &Point3, t: Float, method: NormalMethod) -> Vector3 {
+    let eps = EPSILON * Float::max(t, 1.0);
+    let d = |x: Float, y: Float, z: Float| field.distance(&(*position + Vector3::new(x, y, z)));
+
+    let gradient = match method {
+        NormalMethod::AutoDiff => field.distance(&Dual::position(position)).grad,
+        NormalMethod::Central => Vector3::new(
+            d(eps, 0.0, 0.0) - d(-eps, 0.0, 0.0),
+            d(0.0, eps, 0.0) - d(0.0, -eps, 0.0),
+            d(0.0, 0.0, eps) - d(0.0, 0.0, -eps),
+        ),
+        NormalMethod::Forward => {
+            let centre = d(0.0, 0.0, 0.0);
+            Vector3::new(
+                d(eps, 0.0, 0.0) - centre,
+                d(0.0, eps, 0.0) - centre,
+                d(0.0, 0.0, eps) - centre,
+            )
+        }
+        NormalMethod::Tetrahedral => {
+            let h = 0.5773 * eps;
+            [
+                Vector3::new(h, -h, -h),
+                Vector3::new(-h, -h, h),
+                Vector3::new(-h, h, -h),
+                Vector3::new(h, h, h),
+            ]
+            .iter()
+            .fold(Vector3::zero(), |acc, k| acc + *k * d(k.x, k.y, k.z))
+        }
+    };
+
+    gradient.normalized()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::real::Real;
+
+    const METHODS: [NormalMethod; 4] = [
+        NormalMethod::AutoDiff,
+        NormalMethod::Central,
+        NormalMethod::Forward,
+        NormalMethod::Tetrahedral,
+    ];
+
+    struct Sphere;
+
+    impl DistanceField for Sphere
+    {
+        fn distance<T: Real>(&self, p: &Point3<T>) -> T {
+            (*p - Point3::new(1.0, 2.0, 3.0).lift()).mag() - T::from_float(2.0)
+        }
+    }
+
+    // Roughly the angle between two unit vectors, without the precision loss
+    // of acos near 1
+    fn angle_between(u: &Vector3, v: &Vector3) -> Float {
+        (*u - *v).mag()
+    }
+
+    #[test]
+    fn sphere_normals() {
+        let centre = Point3::new(1.0, 2.0, 3.0);
+        for dir in [
+            Vector3::new(1.0, 0.0, 0.0),
+            Vector3::new(0.0, -1.0, 0.0),
+            Vector3::new(1.0, 1.0, -1.0).normalized(),
+            Vector3::new(-0.3, 0.2, 0.9).normalized(),
+        ] {
+            let surface = centre + dir.scale(2.0);
+            let analytic = dir;
+
+            for method in METHODS {
+                let n = calc_normal(&Sphere, &surface, 10.0, method);
+                let tolerance = if method == NormalMethod::AutoDiff { 1e4 * Float::EPSILON } else { 1e-2 };
+                assert!(angle_between(&n, &analytic) < tolerance, "{:?} {:?} {:?}", method, n, analytic);
+            }
+        }
+    }
+
+    #[test]
+    fn central_beats_forward() {
+        let dir = Vector3::new(-0.3, 0.2, 0.9).normalized();
+        let surface = Point3::new(1.0, 2.0, 3.0) + dir.scale(2.0);
+
+        let error = |method| angle_between(&calc_normal(&Sphere, &surface, 100.0, method), &dir);
+        assert!(error(NormalMethod::Central) < error(NormalMethod::Forward));
+    }
+
+    #[test]
+    fn parse() {
+        for (name, method) in ["autodiff", "central", "forward", "tetrahedral"].iter().zip(METHODS) {
+            assert_eq!(method, NormalMethod::parse(name).unwrap());
+        }
+        assert!(NormalMethod::parse("sideways").is_err());
+    }
+}
@@ -0,0 +1,62 @@
+// Helpers for the vector types, which are all Copy: the owned operator is
+// written out once and these fill in the borrowed and assigning versions.
+
+// `&a op &b`, `a op &b` and `&a op b` in terms of `a op b`
+macro_rules! forward_ref_binop {
+    ($imp:ident, $method:ident, $lhs:ty, $rhs:ty) => {
+        impl<'a, 'b, T: Real> $imp<&'b $rhs> for &'a $lhs
+        {
+            type Output = <$lhs as $imp<$rhs>>::Output;
+
+            fn $method(self, other: &'b $rhs) -> Self::Output {
+                $imp::$method(*self, *other)
+            }
+        }
+
+        impl<'b, T: Real> $imp<&'b $rhs> for $lhs
+        {
+            type Output = <$lhs as $imp<$rhs>>::Output;
+
+            fn $method(self, other: &'b $rhs) -> Self::Output {
+                $imp::$method(self, *other)
+            }
+        }
+
+        impl<'a, T: Real> $imp<$rhs> for &'a $lhs
+        {
+            type Output = <$lhs as $imp<$rhs>>::Output;
+
+            fn $method(self, other: $rhs) -> Self::Output {
+                $imp::$method(*self, other)
+            }
+        }
+    };
+}
+
+// `a op= b` in terms of `a op b`
+macro_rules! assign_op {
+    ($imp:ident, $method:ident, $op:ident, $op_method:ident, $lhs:ty, $rhs:ty) => {
+        impl<T: Real> $imp<$rhs> for $lhs
+        {
+            fn $method(&mut self, other: $rhs) {
+                *self = $op::$op_method(*self, other);
+            }
+        }
+    };
+}
+
+// `s * v` for each float type, since `T * Vector<T>` can't be written generically
+macro_rules! scalar_mul {
+    ($vec:ident, $($t:ty),*) => {
+        $(
+            impl Mul<$vec<$t>> for $t
+            {
+                type Output = $vec<$t>;
+
+                fn mul(self, other: $vec<$t>) -> $vec<$t> {
+                    other * self
+                }
+            }
+        )*
+    };
+}
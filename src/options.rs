@@ -0,0 +1,328 @@
+use anyhow::{anyhow, bail, Result};
+
+use crate::debug::{DebugMode, SlicePlane};
+use crate::environment::{Environment, Irradiance, Sky};
+use crate::media::{Fog, Medium, Uniform};
+use crate::material::SubsurfaceMode;
+use crate::mesh::{Grid, Mesher};
+use crate::export::{Format, MeshOutput};
+use crate::march::{ExhaustedPolicy, MarchSettings, Stepping};
+use crate::normal::NormalMethod;
+use crate::real::Float;
+use crate::point::{Point3, Vector3};
+
+pub struct Options
+{
+    pub width: u32,
+    pub height: u32,
+    pub output: String,
+    pub debug: Option<DebugMode>,
+    pub march: MarchSettings,
+    pub normals: NormalMethod,
+    // March primary rays in packets of this many lanes (4, 8 or 16)
+    pub packet: Option<usize>,
+    // Degrees to turn the camera about the vertical through the origin
+    pub orbit: Float,
+    // Seen by rays that miss, and lighting the scene along with the sun
+    pub environment: Environment,
+    // Worked out from `environment`, which `from_args` keeps up to date
+    pub irradiance: Irradiance,
+    pub fog: Option<Fog>,
+    // Fills the air, scattering sunlight into shafts
+    pub haze: Option<Medium<Uniform>>,
+    pub subsurface: SubsurfaceMode,
+    // Write a mesh of the scene instead of rendering it
+    pub mesh: Option<MeshOutput>,
+    pub mesher: Mesher,
+    // Where and how finely the scene is sampled for a mesh
+    pub mesh_grid: Grid,
+}
+
+impl Default for Options
+{
+    fn default() -> Self {
+        let environment = Environment::Sky(Sky::new(&crate::sun()));
+        Options {
+            width: 1920,
+            height: 1080,
+            output: "output.png".to_string(),
+            debug: None,
+            march: MarchSettings::default(),
+            normals: NormalMethod::default(),
+            packet: None,
+            orbit: 0.0,
+            irradiance: Irradiance::new(&environment),
+            environment,
+            fog: None,
+            haze: None,
+            subsurface: SubsurfaceMode::default(),
+            mesh: None,
+            mesher: Mesher::MarchingCubes,
+            // Round the objects in the demo scene, leaving out the floor
+            mesh_grid: Grid::new(&Point3::new(-4.5, -2.5, -1.5), &Point3::new(4.5, 2.5, 2.5), 128),
+        }
+    }
+}
+
+impl Options
+{
+    // Usage: sdf-rs [--size WxH] [--output file.png] [--debug mode] [--slice nx,ny,nz,d]
+    //              [--exhausted hit|miss|threshold[=k]] [--stepping sphere|relaxed[=w]|scaled=k]
+    //              [--max-steps n] [--epsilon e] [--t-max t]
+    //              [--normals autodiff|central|forward|tetrahedral] [--packet 4|8|16]
+    //              [--orbit degrees] [--sky solid=r,g,b|gradient|sky[=turbidity]|map=file.hdr]
+    //              [--fog exp=density|height=density,falloff[,base]] [--haze density[,anisotropy]]
+    //              [--subsurface off|approx|walk[=samples]]
+    //              [--mesh file.obj|stl|ply|glb] [--mesh-format obj|stl|stl-ascii|ply|glb]
+    //              [--mesher cubes|nets|dual[=tolerance]] [--mesh-resolution n]
+    //              [--mesh-bounds x0,y0,z0,x1,y1,z1]
+    pub fn from_args<I: Iterator<Item = String>>(mut args: I) -> Result<Self> {
+        let mut options = Options::default();
+        let mut slice = SlicePlane::default();
+        let mut mode: Option<String> = None;
+        let mut mesh: Option<String> = None;
+        let mut mesh_format: Option<Format> = None;
+
+        while let Some(arg) = args.next() {
+            let mut value = || args.next().ok_or_else(|| anyhow!("Missing value for {}", arg));
+            match arg.as_str() {
+                "--size" => {
+                    let v = value()?;
+                    let (w, h) = v.split_once('x').ok_or_else(|| anyhow!("Size must be WxH, got {}", v))?;
+                    options.width = w.parse()?;
+                    options.height = h.parse()?;
+                }
+                "--output" => options.output = value()?,
+                "--debug" => mode = Some(value()?),
+                "--slice" => slice = parse_plane(&value()?)?,
+                "--exhausted" => options.march.exhausted = ExhaustedPolicy::parse(&value()?)?,
+                "--stepping" => options.march.stepping = Stepping::parse(&value()?)?,
+                "--max-steps" => options.march.max_steps = value()?.parse()?,
+                "--epsilon" => options.march.epsilon = value()?.parse()?,
+                "--t-max" => options.march.t_max = value()?.parse()?,
+                "--normals" => options.normals = NormalMethod::parse(&value()?)?,
+                "--orbit" => options.orbit = value()?.parse()?,
+                "--sky" => {
+                    options.environment = Environment::parse(&value()?, &crate::sun())?;
+                    options.irradiance = Irradiance::new(&options.environment);
+                }
+                "--fog" => options.fog = Some(Fog::parse(&value()?)?),
+                "--haze" => options.haze = Some(parse_haze(&value()?)?),
+                "--subsurface" => options.subsurface = SubsurfaceMode::parse(&value()?)?,
+                "--mesh" => mesh = Some(value()?),
+                "--mesh-format" => mesh_format = Some(Format::parse(&value()?)?),
+                "--mesher" => options.mesher = Mesher::parse(&value()?)?,
+                "--mesh-resolution" => options.mesh_grid.resolution = value()?.parse()?,
+                "--mesh-bounds" => {
+                    let (min, max) = parse_bounds(&value()?)?;
+                    options.mesh_grid = Grid::new(&min, &max, options.mesh_grid.resolution);
+                }
+                "--packet" => {
+                    let v = value()?;
+                    match v.as_str() {
+                        "4" | "8" | "16" => options.packet = Some(v.parse()?),
+                        _ => bail!("Packet size must be 4, 8 or 16, got {}", v),
+                    }
+                }
+                _ => bail!("Unknown argument {}", arg),
+            }
+        }
+
+        options.debug = mode.map(|m| DebugMode::parse(&m, slice)).transpose()?;
+        if let Some(path) = mesh {
+            let format = match mesh_format {
+                Some(format) => format,
+                None => Format::from_path(&path)?,
+            };
+            options.mesh = Some(MeshOutput { path, format });
+        }
+        Ok(options)
+    }
+}
+
+fn parse_haze(s: &str) -> Result<Medium<Uniform>> {
+    let v = s
+        .split(',')
+        .map(|c| c.trim().parse::<Float>())
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let haze = match v[..] {
+        [density] => Medium::new(Uniform(density)),
+        [density, anisotropy] => Medium { anisotropy, ..Medium::new(Uniform(density)) },
+        _ => bail!("Haze must be density[,anisotropy], got {}", s),
+    };
+    let Uniform(density) = haze.density;
+    if density < 0.0 || !density.is_finite() {
+        bail!("Haze density must be at least zero and finite, got {}", s);
+    }
+    // At +-1 the phase function is all in one direction, and divides by zero
+    if haze.anisotropy.abs() >= 1.0 || haze.anisotropy.is_nan() {
+        bail!("Haze anisotropy must be between -1 and 1, got {}", s);
+    }
+    Ok(haze)
+}
+
+fn parse_bounds(s: &str) -> Result<(Point3, Point3)> {
+    let v = s
+        .split(',')
+        .map(|c| c.trim().parse::<Float>())
+        .collect::<Result<Vec<_>, _>>()?;
+
+    match v[..] {
+        [x0, y0, z0, x1, y1, z1] if x0 < x1 && y0 < y1 && z0 < z1 => {
+            Ok((Point3::new(x0, y0, z0), Point3::new(x1, y1, z1)))
+        }
+        _ => bail!("Mesh bounds must be x0,y0,z0,x1,y1,z1 with each max above its min, got {}", s),
+    }
+}
+
+fn parse_plane(s: &str) -> Result<SlicePlane> {
+    let v = s
+        .split(',')
+        .map(|c| c.trim().parse::<Float>())
+        .collect::<Result<Vec<_>, _>>()?;
+
+    if v.len() != 4 {
+        bail!("Slice plane must be nx,ny,nz,d, got {}", s);
+    }
+
+    let normal = Vector3::new(v[0], v[1], v[2]);
+    let mag = normal.mag();
+    if mag == 0.0 || !mag.is_finite() {
+        bail!("Slice plane normal must be non-zero and finite, got {}", s);
+    }
+
+    Ok(SlicePlane::new(&normal, v[3]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(s: &str) -> impl Iterator<Item = String> + '_ {
+        s.split_whitespace().map(String::from)
+    }
+
+    #[test]
+    fn defaults() {
+        let o = Options::from_args(args("")).unwrap();
+        assert_eq!((1920, 1080), (o.width, o.height));
+        assert_eq!("output.png", o.output);
+        assert!(o.debug.is_none());
+        assert_eq!(MarchSettings::default(), o.march);
+        assert_eq!(NormalMethod::AutoDiff, o.normals);
+        assert_eq!(None, o.packet);
+        assert_eq!(0.0, o.orbit);
+    }
+
+    #[test]
+    fn orbit() {
+        assert_eq!(-45.0, Options::from_args(args("--orbit -45")).unwrap().orbit);
+        assert!(Options::from_args(args("--orbit left")).is_err());
+    }
+
+    #[test]
+    fn packet() {
+        assert_eq!(Some(8), Options::from_args(args("--packet 8")).unwrap().packet);
+        assert!(Options::from_args(args("--packet 3")).is_err());
+    }
+
+    #[test]
+    fn march_settings() {
+        let o = Options::from_args(args("--exhausted miss --stepping relaxed=1.5 --max-steps 100 --epsilon 0.01 --t-max 50")).unwrap();
+        assert_eq!(ExhaustedPolicy::Miss, o.march.exhausted);
+        assert_eq!(Stepping::OverRelaxed(1.5), o.march.stepping);
+        assert_eq!(100, o.march.max_steps);
+        assert_eq!(0.01, o.march.epsilon);
+        assert_eq!(50.0, o.march.t_max);
+    }
+
+    #[test]
+    fn size_and_output() {
+        let o = Options::from_args(args("--size 64x32 --output foo.png")).unwrap();
+        assert_eq!((64, 32), (o.width, o.height));
+        assert_eq!("foo.png", o.output);
+    }
+
+    #[test]
+    fn debug_slice() {
+        let o = Options::from_args(args("--debug slice --slice 0,2,0,1")).unwrap();
+        match o.debug {
+            Some(DebugMode::Slice(plane)) => {
+                assert_eq!(Vector3::new(0.0, 1.0, 0.0), plane.normal);
+                assert_eq!(1.0, plane.offset);
+            }
+            _ => panic!("Expected slice mode"),
+        }
+    }
+
+    #[test]
+    fn sky() {
+        let o = Options::from_args(args("--sky solid=1,1,1")).unwrap();
+        assert!(matches!(o.environment, Environment::Solid(_)));
+        // The lighting follows along
+        assert!((o.irradiance.get(&Vector3::new(0.0, -1.0, 0.0)) - Vector3::splat(1.0)).mag() < 0.01);
+        assert!(matches!(Options::default().environment, Environment::Sky(_)));
+        assert!(Options::from_args(args("--sky")).is_err());
+        assert!(Options::from_args(args("--sky plaid")).is_err());
+    }
+
+    #[test]
+    fn media() {
+        let o = Options::from_args(args("--fog exp=0.02 --haze 0.05,0.6")).unwrap();
+        assert!(matches!(o.fog, Some(Fog::Exponential { .. })));
+        let haze = o.haze.unwrap();
+        assert_eq!(Uniform(0.05), haze.density);
+        assert_eq!(0.6, haze.anisotropy);
+        assert_eq!(Uniform(0.1), Options::from_args(args("--haze 0.1")).unwrap().haze.unwrap().density);
+        assert!(Options::default().fog.is_none() && Options::default().haze.is_none());
+        assert!(Options::from_args(args("--haze 0.1,0.2,0.3")).is_err());
+        assert!(Options::from_args(args("--haze -0.1")).is_err());
+        assert!(Options::from_args(args("--haze 0.1,1")).is_err());
+        assert!(Options::from_args(args("--haze 0.1,-1.5")).is_err());
+        assert!(Options::from_args(args("--haze 0.1,-0.99")).is_ok());
+        assert!(Options::from_args(args("--fog thick")).is_err());
+    }
+
+    #[test]
+    fn subsurface() {
+        assert_eq!(SubsurfaceMode::Approximate, Options::default().subsurface);
+        assert_eq!(SubsurfaceMode::RandomWalk(8), Options::from_args(args("--subsurface walk=8")).unwrap().subsurface);
+        assert!(Options::from_args(args("--subsurface lots")).is_err());
+    }
+
+    #[test]
+    fn mesh() {
+        assert!(Options::default().mesh.is_none());
+        let o = Options::from_args(args("--mesh scene.stl --mesher dual=0.01 --mesh-resolution 64")).unwrap();
+        assert_eq!(Some(MeshOutput { path: "scene.stl".to_string(), format: Format::Stl }), o.mesh);
+        assert_eq!(Mesher::DualContour(0.01), o.mesher);
+        assert_eq!(64, o.mesh_grid.resolution);
+
+        // An explicit format wins, whichever comes first
+        let o = Options::from_args(args("--mesh-format stl-ascii --mesh scene.stl --mesh-bounds -1,-2,-3,1,2,3")).unwrap();
+        assert_eq!(Format::StlAscii, o.mesh.unwrap().format);
+        assert_eq!(Point3::new(-1.0, -2.0, -3.0), o.mesh_grid.min);
+        assert_eq!(Point3::new(1.0, 2.0, 3.0), o.mesh_grid.max);
+        assert_eq!(128, o.mesh_grid.resolution);
+
+        assert!(Options::from_args(args("--mesh scene.gltf")).is_err());
+        assert!(Options::from_args(args("--mesh-format fbx")).is_err());
+        assert!(Options::from_args(args("--mesher voxels")).is_err());
+        assert!(Options::from_args(args("--mesh-bounds 1,1,1,0,2,2")).is_err());
+        assert!(Options::from_args(args("--mesh-bounds 0,0,0,1")).is_err());
+    }
+
+    #[test]
+    fn bad_args() {
+        assert!(Options::from_args(args("--debug wibble")).is_err());
+        assert!(Options::from_args(args("--size 10")).is_err());
+        assert!(Options::from_args(args("--slice 1,2")).is_err());
+        assert!(Options::from_args(args("--slice 0,0,0,1")).is_err());
+        assert!(Options::from_args(args("--output")).is_err());
+        assert!(Options::from_args(args("--exhausted sometimes")).is_err());
+        assert!(Options::from_args(args("--max-steps lots")).is_err());
+        assert!(Options::from_args(args("--normals guess")).is_err());
+        assert!(Options::from_args(args("--packet")).is_err());
+    }
+}
@@ -0,0 +1,268 @@
+use std::cmp::Ordering;
+use std::ops::{Add, Div, Index, IndexMut, Mul, Neg, Sub};
+
+use crate::real::{Float, Real};
+use crate::point::{Point3, Vector3};
+
+// N independent values evaluated in lock step, one per lane. A `Point3` of
+// packets is a structure-of-arrays batch of N points, so running a distance
+// function on one evaluates all N together, in loops the compiler can turn
+// into SIMD, and walks any composed scene once per batch rather than once per
+// point.
+#[derive(Debug, Copy, Clone)]
+pub struct Packet<const N: usize>(pub [Float; N]);
+
+pub type Packet4 = Packet<4>;
+pub type Packet8 = Packet<8>;
+pub type Packet16 = Packet<16>;
+
+impl<const N: usize> Packet<N>
+{
+    pub fn splat(v: Float) -> Self {
+        Packet([v; N])
+    }
+
+    fn map(self, f: impl Fn(Float) -> Float) -> Self {
+        Packet(self.0.map(f))
+    }
+
+    fn zip(self, other: Self, f: impl Fn(Float, Float) -> Float) -> Self {
+        let mut r = self.0;
+        for (r, o) in r.iter_mut().zip(other.0) {
+            *r = f(*r, o);
+        }
+        Packet(r)
+    }
+
+    // One lane per point, structure-of-arrays
+    pub fn gather(points: &[Point3; N]) -> Point3<Packet<N>> {
+        Point3 {
+            x: Packet(points.map(|p| p.x)),
+            y: Packet(points.map(|p| p.y)),
+            z: Packet(points.map(|p| p.z)),
+        }
+    }
+
+    pub fn gather_vectors(vectors: &[Vector3; N]) -> Vector3<Packet<N>> {
+        Vector3 {
+            x: Packet(vectors.map(|v| v.x)),
+            y: Packet(vectors.map(|v| v.y)),
+            z: Packet(vectors.map(|v| v.z)),
+        }
+    }
+}
+
+impl<const N: usize> Index<usize> for Packet<N>
+{
+    type Output = Float;
+
+    fn index(&self, lane: usize) -> &Float {
+        &self.0[lane]
+    }
+}
+
+impl<const N: usize> IndexMut<usize> for Packet<N>
+{
+    fn index_mut(&mut self, lane: usize) -> &mut Float {
+        &mut self.0[lane]
+    }
+}
+
+// A packet has no single truth value for an ordering, so generic code that
+// branches on `a < b` can't do the right thing when the lanes disagree. Rather
+// than quietly picking an answer, comparisons assert that every lane agrees;
+// code that should branch per lane has to use min, max and abs instead, which
+// work lane by lane. Equality is of the whole packet.
+impl<const N: usize> Packet<N>
+{
+    // The one answer every lane gives
+    fn uniform(self, other: Self, f: impl Fn(Float, Float) -> bool) -> bool {
+        let first = f(self.0[0], other.0[0]);
+        assert!(
+            self.0.iter().zip(&other.0).all(|(&a, &b)| f(a, b) == first),
+            "Packet lanes disagree in a comparison ({:?} against {:?}); branch per lane with min and max",
+            self.0,
+            other.0
+        );
+        first
+    }
+}
+
+impl<const N: usize> PartialEq for Packet<N>
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+impl<const N: usize> PartialOrd for Packet<N>
+{
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        let mut lanes = self.0.iter().zip(&other.0).map(|(a, b)| a.partial_cmp(b));
+        let first = lanes.next()?;
+        assert!(lanes.all(|o| o == first), "Packet lanes disagree in a comparison ({:?} against {:?})", self.0, other.0);
+        first
+    }
+
+    fn lt(&self, other: &Self) -> bool {
+        self.uniform(*other, |a, b| a < b)
+    }
+
+    fn le(&self, other: &Self) -> bool {
+        self.uniform(*other, |a, b| a <= b)
+    }
+
+    fn gt(&self, other: &Self) -> bool {
+        self.uniform(*other, |a, b| a > b)
+    }
+
+    fn ge(&self, other: &Self) -> bool {
+        self.uniform(*other, |a, b| a >= b)
+    }
+}
+
+macro_rules! lane_op {
+    ($imp:ident, $method:ident, $op:tt) => {
+        impl<const N: usize> $imp for Packet<N>
+        {
+            type Output = Packet<N>;
+
+            fn $method(self, other: Packet<N>) -> Packet<N> {
+                self.zip(other, |a, b| a $op b)
+            }
+        }
+    };
+}
+
+lane_op!(Add, add, +);
+lane_op!(Sub, sub, -);
+lane_op!(Mul, mul, *);
+lane_op!(Div, div, /);
+
+impl<const N: usize> Neg for Packet<N>
+{
+    type Output = Packet<N>;
+
+    fn neg(self) -> Packet<N> {
+        self.map(|v| -v)
+    }
+}
+
+impl<const N: usize> Real for Packet<N>
+{
+    #[allow(clippy::unnecessary_cast)]
+    fn from_f64(v: f64) -> Self {
+        Packet::splat(v as Float)
+    }
+
+    // Only meaningful for splats; a packet has no single value
+    #[allow(clippy::unnecessary_cast)]
+    fn to_f64(self) -> f64 {
+        assert!(
+            self.0.iter().all(|v| v.to_bits() == self.0[0].to_bits()),
+            "Packet {:?} has no single value",
+            self.0
+        );
+        self.0[0] as f64
+    }
+
+    fn sqrt(self) -> Self {
+        self.map(Float::sqrt)
+    }
+
+    fn abs(self) -> Self {
+        self.map(Float::abs)
+    }
+
+    fn max(self, other: Self) -> Self {
+        self.zip(other, Float::max)
+    }
+
+    fn min(self, other: Self) -> Self {
+        self.zip(other, Float::min)
+    }
+
+    fn floor(self) -> Self {
+        self.map(Float::floor)
+    }
+
+    fn sin(self) -> Self {
+        self.map(Float::sin)
+    }
+
+    fn cos(self) -> Self {
+        self.map(Float::cos)
+    }
+
+    fn atan2(self, other: Self) -> Self {
+        self.zip(other, Float::atan2)
+    }
+
+    fn apply3(p: &Point3<Self>, value: impl Fn(&Point3) -> Float, _: impl Fn(&Point3) -> (Float, Vector3)) -> Self {
+        Packet(std::array::from_fn(|i| value(&Point3::new(p.x[i], p.y[i], p.z[i]))))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{sdf, DistanceField, Scene};
+
+    #[test]
+    fn lanes() {
+        let a = Packet([1.0, -2.0, 3.0, 4.0]);
+        let b = Packet4::splat(2.0);
+
+        assert_eq!(Packet([3.0, 0.0, 5.0, 6.0]), a + b);
+        assert_eq!(Packet([2.0, -4.0, 6.0, 8.0]), a * b);
+        assert_eq!(Packet([1.0, 2.0, 3.0, 4.0]), a.abs());
+        assert_eq!(Packet([2.0, 2.0, 3.0, 4.0]), a.max(b));
+        assert_eq!(Packet([1.0, -2.0, 2.0, 2.0]), a.min(b));
+        assert_eq!(2.0, a.abs().sqrt()[3]);
+    }
+
+    #[test]
+    fn comparisons_need_every_lane() {
+        let a = Packet([1.0, 2.0]);
+        assert!(a < Packet::splat(3.0));
+        assert!(a >= Packet::splat(1.0));
+        assert_eq!(Some(Ordering::Greater), a.partial_cmp(&Packet::splat(0.0)));
+        assert_eq!(2.0, Packet4::splat(2.0).to_f64());
+    }
+
+    #[test]
+    #[should_panic(expected = "lanes disagree")]
+    fn mixed_comparison_panics() {
+        let _ = Packet([1.0, 2.0]) < Packet::splat(1.5);
+    }
+
+    #[test]
+    #[should_panic(expected = "lanes disagree")]
+    fn mixed_ordering_panics() {
+        let _ = Packet([1.0, 2.0]).partial_cmp(&Packet::splat(2.0));
+    }
+
+    #[test]
+    #[should_panic(expected = "no single value")]
+    fn mixed_value_panics() {
+        Packet([1.0, 2.0]).to_f64();
+    }
+
+    #[test]
+    fn matches_scalar_sdf() {
+        let points = [
+            Point3::new(0.0, 0.0, -10.0),
+            Point3::new(-3.0, -1.0, 0.5),
+            Point3::new(1.5, 1.2, 0.9),
+            Point3::new(0.0, -5.0, 0.0),
+            Point3::new(100.0, 3.0, -7.0),
+            Point3::new(0.25, 0.25, 0.25),
+            Point3::new(2.0, 2.0, 2.0),
+            Point3::new(-1.0, 4.0, 3.0),
+        ];
+        let d: Packet8 = Scene.distance(&Packet::gather(&points));
+        for (lane, p) in points.iter().enumerate() {
+            assert_eq!(sdf(p), d[lane]);
+        }
+    }
+}
@@ -0,0 +1,434 @@
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign};
+
+use crate::real::{cast, Float, Real};
+use crate::vector::Vec4;
+
+// A location in space. Points can be moved by vectors and subtracted to give
+// the vector between them, but not added together.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Point3<T = Float>
+{
+    pub x: T,
+    pub y: T,
+    pub z: T,
+}
+
+// A displacement or direction. Unaffected by translation.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Vector3<T = Float>
+{
+    pub x: T,
+    pub y: T,
+    pub z: T,
+}
+
+// A pair of components, mostly from swizzling a Vector3 (`p.xz()`)
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Vector2<T = Float>
+{
+    pub x: T,
+    pub y: T,
+}
+
+impl Point3
+{
+    // Convert to another scalar type, as a constant
+    pub fn lift<T: Real>(&self) -> Point3<T> {
+        self.cast()
+    }
+}
+
+impl<T: Real> Point3<T>
+{
+    pub fn new(x: T, y: T, z: T) -> Self {
+        Point3 { x, y, z }
+    }
+
+    pub fn origin() -> Self {
+        let zero = T::from_float(0.0);
+        Point3::new(zero, zero, zero)
+    }
+
+    // The displacement of this point from the origin
+    pub fn to_vector(self) -> Vector3<T> {
+        Vector3::new(self.x, self.y, self.z)
+    }
+
+    pub fn value(&self) -> Point3 {
+        self.cast()
+    }
+
+    pub fn cast<U: Real>(&self) -> Point3<U> {
+        Point3::new(cast(self.x), cast(self.y), cast(self.z))
+    }
+}
+
+impl Vector3
+{
+    // Convert to another scalar type, as a constant
+    pub fn lift<T: Real>(&self) -> Vector3<T> {
+        self.cast()
+    }
+}
+
+impl<T: Real> Vector3<T>
+{
+    pub fn new(x: T, y: T, z: T) -> Self {
+        Vector3 { x, y, z }
+    }
+
+    pub fn zero() -> Self {
+        let zero = T::from_float(0.0);
+        Vector3::new(zero, zero, zero)
+    }
+
+    pub fn dot_product(&self, other: &Vector3<T>) -> T {
+        self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    pub fn cross_product(&self, other: &Vector3<T>) -> Vector3<T> {
+        Vector3::new(
+            (self.y * other.z) - (self.z * other.y),
+            (self.z * other.x) - (self.x * other.z),
+            (self.x * other.y) - (self.y * other.x),
+        )
+    }
+
+    pub fn abs(&self) -> Vector3<T> {
+        Vector3::new(self.x.abs(), self.y.abs(), self.z.abs())
+    }
+
+    pub fn splat(v: T) -> Self {
+        Vector3::new(v, v, v)
+    }
+
+    pub fn scale(&self, other: T) -> Vector3<T> {
+        Vector3::new(self.x * other, self.y * other, self.z * other)
+    }
+
+    pub fn mag(&self) -> T {
+        self.dot_product(self).sqrt()
+    }
+
+    pub fn normalized(&self) -> Vector3<T> {
+        self.scale(T::from_float(1.0) / self.mag())
+    }
+
+    pub fn value(&self) -> Vector3 {
+        self.cast()
+    }
+
+    pub fn cast<U: Real>(&self) -> Vector3<U> {
+        Vector3::new(cast(self.x), cast(self.y), cast(self.z))
+    }
+}
+
+impl<T: Real> Vector2<T>
+{
+    pub fn new(x: T, y: T) -> Self {
+        Vector2 { x, y }
+    }
+
+    pub fn splat(v: T) -> Self {
+        Vector2::new(v, v)
+    }
+
+    pub fn dot_product(&self, other: &Vector2<T>) -> T {
+        self.x * other.x + self.y * other.y
+    }
+
+    pub fn mag(&self) -> T {
+        self.dot_product(self).sqrt()
+    }
+
+    pub fn yx(&self) -> Vector2<T> {
+        Vector2::new(self.y, self.x)
+    }
+}
+
+macro_rules! swizzle {
+    ($($name:ident: $out:ident($($c:ident),*)),* $(,)?) => {
+        impl<T: Real> Vector3<T>
+        {
+            $(
+                pub fn $name(&self) -> $out<T> {
+                    $out::new($(self.$c),*)
+                }
+            )*
+        }
+    };
+}
+
+swizzle!(
+    xy: Vector2(x, y),
+    xz: Vector2(x, z),
+    yx: Vector2(y, x),
+    yz: Vector2(y, z),
+    zx: Vector2(z, x),
+    zy: Vector2(z, y),
+    xzy: Vector3(x, z, y),
+    yxz: Vector3(y, x, z),
+    yzx: Vector3(y, z, x),
+    zxy: Vector3(z, x, y),
+    zyx: Vector3(z, y, x),
+);
+
+impl<T: Real> From<T> for Vector3<T>
+{
+    fn from(v: T) -> Vector3<T> {
+        Vector3::splat(v)
+    }
+}
+
+impl<T: Real> From<T> for Vector2<T>
+{
+    fn from(v: T) -> Vector2<T> {
+        Vector2::splat(v)
+    }
+}
+
+impl<T: Real> From<Point3<T>> for Vec4<T>
+{
+    fn from(p: Point3<T>) -> Vec4<T> {
+        Vec4::position(p.x, p.y, p.z)
+    }
+}
+
+impl<T: Real> From<Vector3<T>> for Vec4<T>
+{
+    fn from(v: Vector3<T>) -> Vec4<T> {
+        Vec4::direction(v.x, v.y, v.z)
+    }
+}
+
+impl<T: Real> Sub for Point3<T>
+{
+    type Output = Vector3<T>;
+
+    fn sub(self, other: Point3<T>) -> Vector3<T> {
+        Vector3::new(self.x - other.x, self.y - other.y, self.z - other.z)
+    }
+}
+
+impl<T: Real> Add<Vector3<T>> for Point3<T>
+{
+    type Output = Point3<T>;
+
+    fn add(self, other: Vector3<T>) -> Point3<T> {
+        Point3::new(self.x + other.x, self.y + other.y, self.z + other.z)
+    }
+}
+
+impl<T: Real> Sub<Vector3<T>> for Point3<T>
+{
+    type Output = Point3<T>;
+
+    fn sub(self, other: Vector3<T>) -> Point3<T> {
+        Point3::new(self.x - other.x, self.y - other.y, self.z - other.z)
+    }
+}
+
+forward_ref_binop!(Sub, sub, Point3<T>, Point3<T>);
+forward_ref_binop!(Add, add, Point3<T>, Vector3<T>);
+forward_ref_binop!(Sub, sub, Point3<T>, Vector3<T>);
+assign_op!(AddAssign, add_assign, Add, add, Point3<T>, Vector3<T>);
+assign_op!(SubAssign, sub_assign, Sub, sub, Point3<T>, Vector3<T>);
+
+// Component-wise arithmetic for Vector2 and Vector3
+macro_rules! vector_ops {
+    ($vec:ident, $($c:ident),*) => {
+        impl<T: Real> Add for $vec<T>
+        {
+            type Output = $vec<T>;
+
+            fn add(self, other: $vec<T>) -> $vec<T> {
+                $vec { $($c: self.$c + other.$c),* }
+            }
+        }
+
+        impl<T: Real> Sub for $vec<T>
+        {
+            type Output = $vec<T>;
+
+            fn sub(self, other: $vec<T>) -> $vec<T> {
+                $vec { $($c: self.$c - other.$c),* }
+            }
+        }
+
+        impl<T: Real> Mul for $vec<T>
+        {
+            type Output = $vec<T>;
+
+            fn mul(self, other: $vec<T>) -> $vec<T> {
+                $vec { $($c: self.$c * other.$c),* }
+            }
+        }
+
+        impl<T: Real> Div for $vec<T>
+        {
+            type Output = $vec<T>;
+
+            fn div(self, other: $vec<T>) -> $vec<T> {
+                $vec { $($c: self.$c / other.$c),* }
+            }
+        }
+
+        impl<T: Real> Mul<T> for $vec<T>
+        {
+            type Output = $vec<T>;
+
+            fn mul(self, other: T) -> $vec<T> {
+                $vec { $($c: self.$c * other),* }
+            }
+        }
+
+        impl<T: Real> Div<T> for $vec<T>
+        {
+            type Output = $vec<T>;
+
+            fn div(self, other: T) -> $vec<T> {
+                $vec { $($c: self.$c / other),* }
+            }
+        }
+
+        impl<T: Real> Neg for $vec<T>
+        {
+            type Output = $vec<T>;
+
+            fn neg(self) -> $vec<T> {
+                $vec { $($c: -self.$c),* }
+            }
+        }
+
+        impl<'a, T: Real> Neg for &'a $vec<T>
+        {
+            type Output = $vec<T>;
+
+            fn neg(self) -> $vec<T> {
+                -*self
+            }
+        }
+
+        impl<T: Real> Sum for $vec<T>
+        {
+            fn sum<I: Iterator<Item = $vec<T>>>(iter: I) -> $vec<T> {
+                iter.fold($vec::splat(T::from_float(0.0)), |a, b| a + b)
+            }
+        }
+
+        impl<'a, T: Real> Sum<&'a $vec<T>> for $vec<T>
+        {
+            fn sum<I: Iterator<Item = &'a $vec<T>>>(iter: I) -> $vec<T> {
+                iter.copied().sum()
+            }
+        }
+
+        forward_ref_binop!(Add, add, $vec<T>, $vec<T>);
+        forward_ref_binop!(Sub, sub, $vec<T>, $vec<T>);
+        forward_ref_binop!(Mul, mul, $vec<T>, $vec<T>);
+        forward_ref_binop!(Div, div, $vec<T>, $vec<T>);
+        forward_ref_binop!(Mul, mul, $vec<T>, T);
+        forward_ref_binop!(Div, div, $vec<T>, T);
+        assign_op!(AddAssign, add_assign, Add, add, $vec<T>, $vec<T>);
+        assign_op!(SubAssign, sub_assign, Sub, sub, $vec<T>, $vec<T>);
+        assign_op!(MulAssign, mul_assign, Mul, mul, $vec<T>, $vec<T>);
+        assign_op!(DivAssign, div_assign, Div, div, $vec<T>, $vec<T>);
+        assign_op!(MulAssign, mul_assign, Mul, mul, $vec<T>, T);
+        assign_op!(DivAssign, div_assign, Div, div, $vec<T>, T);
+        scalar_mul!($vec, f32, f64);
+    };
+}
+
+vector_ops!(Vector2, x, y);
+vector_ops!(Vector3, x, y, z);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn point_arithmetic() {
+        let p = Point3::new(1.0, 2.0, 3.0);
+        let q = Point3::new(-1.0, -2.0, -3.0);
+        let v = Vector3::new(1.0, 1.0, 1.0);
+
+        assert_eq!(Vector3::new(2.0, 4.0, 6.0), p - q);
+        assert_eq!(Point3::new(2.0, 3.0, 4.0), p + v);
+        assert_eq!(Point3::new(0.0, 1.0, 2.0), p - v);
+        assert_eq!(p, q + (p - q));
+    }
+
+    #[test]
+    fn vector_arithmetic() {
+        let u = Vector3::new(1.0, 2.0, 3.0);
+        let v = Vector3::new(-1.0, -2.0, -3.0);
+
+        assert_eq!(Vector3::zero(), u + v);
+        assert_eq!(Vector3::new(2.0, 4.0, 6.0), u - v);
+        assert_eq!(v, -u);
+        assert_eq!(-14.0, u.dot_product(&v));
+        assert_eq!(Float::sqrt(14.0), u.mag());
+        assert_eq!(Vector3::new(1.0, 2.0, 3.0), v.abs());
+        assert_eq!(Vector3::new(0.0, 0.0, 1.0), Vector3::new(1.0, 0.0, 0.0).cross_product(&Vector3::new(0.0, 1.0, 0.0)));
+    }
+
+    #[test]
+    #[allow(clippy::op_ref)]
+    fn operators() {
+        let u = Vector3::new(1.0, 2.0, 3.0);
+        let v = Vector3::new(2.0, 2.0, 2.0);
+
+        assert_eq!(&u + &v, u + v);
+        assert_eq!(u + &v, &u - -v);
+        assert_eq!(Vector3::new(2.0, 4.0, 6.0), u * 2.0);
+        assert_eq!(Vector3::new(2.0, 4.0, 6.0), 2.0 * u);
+        assert_eq!(u * 2.0, &u * v);
+        assert_eq!(Vector3::new(0.5, 1.0, 1.5), u / 2.0);
+        assert_eq!(u / 2.0, &u / &v);
+
+        let mut w = u;
+        w += v;
+        w -= u;
+        w *= 3.0;
+        w /= v;
+        assert_eq!(Vector3::splat(3.0), w);
+
+        let mut p = Point3::origin();
+        p += u;
+        p -= v;
+        assert_eq!(Point3::new(-1.0, 0.0, 1.0), p);
+        assert_eq!(&p - &Point3::origin(), p.to_vector());
+        assert_eq!(&p + &v, p + v);
+    }
+
+    #[test]
+    fn sum() {
+        let vs = [Vector3::new(1.0, 0.0, 0.0), Vector3::new(0.0, 1.0, 0.0), Vector3::new(0.0, 0.0, 1.0)];
+        assert_eq!(Vector3::splat(1.0), vs.iter().sum());
+        assert_eq!(Vector3::splat(2.0), vs.iter().map(|v| v * 2.0).sum());
+        assert_eq!(Vector2::new(3.0, 3.0), [Vector2::splat(1.0); 3].into_iter().sum());
+    }
+
+    #[test]
+    fn swizzles() {
+        let v = Vector3::new(1.0, 2.0, 3.0);
+        assert_eq!(Vector2::new(1.0, 3.0), v.xz());
+        assert_eq!(Vector2::new(3.0, 2.0), v.zy());
+        assert_eq!(Vector2::new(2.0, 1.0), v.xy().yx());
+        assert_eq!(Vector3::new(3.0, 1.0, 2.0), v.zxy());
+        assert_eq!(Float::sqrt(10.0), v.xz().mag());
+    }
+
+    #[test]
+    fn to_homogeneous() {
+        assert_eq!(Vec4::position(1.0, 2.0, 3.0), Point3::new(1.0, 2.0, 3.0).into());
+        assert_eq!(Vec4::direction(1.0, 2.0, 3.0), Vector3::new(1.0, 2.0, 3.0).into());
+    }
+
+    #[test]
+    fn origin() {
+        let p = Point3::new(1.0, 2.0, 3.0);
+        assert_eq!(p.to_vector(), p - Point3::origin());
+    }
+}
@@ -0,0 +1,249 @@
+use std::ops::Mul;
+
+use crate::matrix::{EulerOrder, Mat4};
+use crate::point::Vector3;
+use crate::real::Float;
+use crate::vector::Vec4;
+
+// A rotation as a unit quaternion, `w` being the scalar part. Same
+// conventions as `Mat4`: rotations are anticlockwise looking down the axis,
+// and `a * b` rotates by `b` and then by `a`.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Quat
+{
+    pub x: Float,
+    pub y: Float,
+    pub z: Float,
+    pub w: Float,
+}
+
+impl Default for Quat
+{
+    fn default() -> Self {
+        Quat::identity()
+    }
+}
+
+impl Quat
+{
+    pub fn new(x: Float, y: Float, z: Float, w: Float) -> Self {
+        Quat { x, y, z, w }
+    }
+
+    pub fn identity() -> Self {
+        Quat::new(0.0, 0.0, 0.0, 1.0)
+    }
+
+    // The axis needn't be unit length. Angle in radians.
+    pub fn from_axis_angle(axis: &Vector3, angle: Float) -> Self {
+        let n = axis.normalized();
+        let (s, c) = (angle / 2.0).sin_cos();
+        Quat::new(n.x * s, n.y * s, n.z * s, c)
+    }
+
+    // As `Mat4::euler`
+    pub fn from_euler(angles: &Vector3, order: EulerOrder) -> Self {
+        let x = Quat::from_axis_angle(&Vector3::new(1.0, 0.0, 0.0), angles.x);
+        let y = Quat::from_axis_angle(&Vector3::new(0.0, 1.0, 0.0), angles.y);
+        let z = Quat::from_axis_angle(&Vector3::new(0.0, 0.0, 1.0), angles.z);
+        match order {
+            EulerOrder::XYZ => z * y * x,
+            EulerOrder::XZY => y * z * x,
+            EulerOrder::YXZ => z * x * y,
+            EulerOrder::YZX => x * z * y,
+            EulerOrder::ZXY => y * x * z,
+            EulerOrder::ZYX => x * y * z,
+        }
+    }
+
+    // The rotation part of a matrix with no scale or shear
+    pub fn from_mat4(m: &Mat4) -> Self {
+        let [x, y, z, w] = m.to_quaternion();
+        Quat::new(x, y, z, w)
+    }
+
+    pub fn to_mat4(&self) -> Mat4 {
+        Mat4::from_quaternion([self.x, self.y, self.z, self.w])
+    }
+
+    pub fn dot(&self, other: &Quat) -> Float {
+        self.x * other.x + self.y * other.y + self.z * other.z + self.w * other.w
+    }
+
+    pub fn norm(&self) -> Float {
+        self.dot(self).sqrt()
+    }
+
+    pub fn normalized(&self) -> Quat {
+        let n = self.norm();
+        Quat::new(self.x / n, self.y / n, self.z / n, self.w / n)
+    }
+
+    // The inverse, for a unit quaternion
+    pub fn conjugate(&self) -> Quat {
+        Quat::new(-self.x, -self.y, -self.z, self.w)
+    }
+
+    pub fn rotate(&self, v: &Vector3) -> Vector3 {
+        // v + 2w(u x v) + 2u x (u x v), with u the vector part
+        let u = Vector3::new(self.x, self.y, self.z);
+        let uv = u.cross_product(v);
+        *v + uv * (2.0 * self.w) + u.cross_product(&uv) * 2.0
+    }
+
+    // Rotates directions; positions are rotated about the origin. Keeps w.
+    pub fn rotate_vec4(&self, v: &Vec4) -> Vec4 {
+        let r = self.rotate(&Vector3::new(v.x, v.y, v.z));
+        Vec4 { x: r.x, y: r.y, z: r.z, w: v.w }
+    }
+
+    // Normalised linear interpolation. Cheaper than slerp but doesn't turn
+    // at a constant rate.
+    pub fn nlerp(&self, other: &Quat, t: Float) -> Quat {
+        let other = self.nearest(other);
+        Quat::new(
+            self.x + (other.x - self.x) * t,
+            self.y + (other.y - self.y) * t,
+            self.z + (other.z - self.z) * t,
+            self.w + (other.w - self.w) * t,
+        )
+        .normalized()
+    }
+
+    // Spherical linear interpolation, turning at a constant rate along the
+    // shorter way round
+    pub fn slerp(&self, other: &Quat, t: Float) -> Quat {
+        let other = self.nearest(other);
+        let d = self.dot(&other).min(1.0);
+
+        // Nearly the same rotation: sin(theta) is too small to divide by
+        if d > 0.9995 {
+            return self.nlerp(&other, t);
+        }
+
+        let theta = d.acos();
+        let a = ((1.0 - t) * theta).sin() / theta.sin();
+        let b = (t * theta).sin() / theta.sin();
+        Quat::new(
+            self.x * a + other.x * b,
+            self.y * a + other.y * b,
+            self.z * a + other.z * b,
+            self.w * a + other.w * b,
+        )
+    }
+
+    // q and -q are the same rotation; pick whichever is closer to self so
+    // interpolation takes the short way round
+    fn nearest(&self, other: &Quat) -> Quat {
+        if self.dot(other) < 0.0 {
+            Quat::new(-other.x, -other.y, -other.z, -other.w)
+        } else {
+            *other
+        }
+    }
+}
+
+// Hamilton product: rotate by `other`, then by `self`
+impl Mul for Quat
+{
+    type Output = Quat;
+
+    fn mul(self, other: Quat) -> Quat {
+        Quat::new(
+            self.w * other.x + self.x * other.w + self.y * other.z - self.z * other.y,
+            self.w * other.y - self.x * other.z + self.y * other.w + self.z * other.x,
+            self.w * other.z + self.x * other.y - self.y * other.x + self.z * other.w,
+            self.w * other.w - self.x * other.x - self.y * other.y - self.z * other.z,
+        )
+    }
+}
+
+impl From<Quat> for Mat4
+{
+    fn from(q: Quat) -> Mat4 {
+        q.to_mat4()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QUARTER: Float = std::f64::consts::FRAC_PI_2 as Float;
+
+    fn near(a: &Vector3, b: &Vector3) -> bool {
+        (*a - *b).mag() < 1e-5
+    }
+
+    fn near_quat(a: &Quat, b: &Quat) -> bool {
+        // Either sign is the same rotation
+        Float::abs(Float::abs(a.dot(b)) - 1.0) < 1e-5
+    }
+
+    fn near_mat(a: &Mat4, b: &Mat4) -> bool {
+        (0..16).all(|i| Float::abs(a[i] - b[i]) < 1e-5)
+    }
+
+    #[test]
+    fn rotation() {
+        let q = Quat::from_axis_angle(&Vector3::new(0.0, 0.0, 2.0), QUARTER);
+        assert!(near(&Vector3::new(0.0, 1.0, 0.0), &q.rotate(&Vector3::new(1.0, 0.0, 0.0))));
+        assert!(near(&Vector3::new(1.0, 0.0, 0.0), &q.conjugate().rotate(&Vector3::new(0.0, 1.0, 0.0))));
+        assert_eq!(Vector3::new(1.0, 2.0, 3.0), Quat::identity().rotate(&Vector3::new(1.0, 2.0, 3.0)));
+
+        let v = q.rotate_vec4(&Vec4::position(1.0, 0.0, 0.0));
+        assert_eq!(1.0, v.w);
+        assert!(Float::abs(v.y - 1.0) < 1e-6);
+    }
+
+    #[test]
+    fn composition() {
+        let a = Quat::from_axis_angle(&Vector3::new(1.0, 0.0, 0.0), 0.4);
+        let b = Quat::from_axis_angle(&Vector3::new(0.3, 1.0, -0.2), 1.3);
+        let v = Vector3::new(0.5, -1.0, 2.0);
+        assert!(near(&a.rotate(&b.rotate(&v)), &(a * b).rotate(&v)));
+        assert!(near_mat(&(a * b).to_mat4(), &b.to_mat4().then(&a.to_mat4())));
+    }
+
+    #[test]
+    fn matrices() {
+        let axis = Vector3::new(1.0, -2.0, 0.5);
+        let q = Quat::from_axis_angle(&axis, 2.2);
+        let m: Mat4 = Mat4::rotation(&Vec4::direction(axis.x, axis.y, axis.z), 2.2);
+        assert!(near_mat(&m, &q.into()));
+        assert!(near_quat(&q, &Quat::from_mat4(&m)));
+
+        let angles = Vector3::new(0.1, 0.7, -0.4);
+        let euler = Vec4::direction(angles.x, angles.y, angles.z);
+        for order in [EulerOrder::XYZ, EulerOrder::XZY, EulerOrder::YXZ, EulerOrder::YZX, EulerOrder::ZXY, EulerOrder::ZYX] {
+            assert!(near_mat(&Mat4::euler(&euler, order), &Quat::from_euler(&angles, order).to_mat4()), "{:?}", order);
+        }
+    }
+
+    #[test]
+    fn normalisation() {
+        let q = Quat::new(1.0, 2.0, 2.0, 4.0);
+        assert_eq!(5.0, q.norm());
+        assert!(Float::abs(q.normalized().norm() - 1.0) < 1e-6);
+    }
+
+    #[test]
+    fn interpolation() {
+        let y = Vector3::new(0.0, 1.0, 0.0);
+        let a = Quat::identity();
+        let b = Quat::from_axis_angle(&y, 2.0);
+
+        assert!(near_quat(&a, &a.slerp(&b, 0.0)));
+        assert!(near_quat(&b, &a.slerp(&b, 1.0)));
+        assert!(near_quat(&Quat::from_axis_angle(&y, 0.5), &a.slerp(&b, 0.25)));
+        assert!(near_quat(&Quat::from_axis_angle(&y, 1.0), &a.nlerp(&b, 0.5)));
+
+        // The same rotation with the opposite sign still goes the short way
+        let flipped = Quat::new(-b.x, -b.y, -b.z, -b.w);
+        assert!(near_quat(&Quat::from_axis_angle(&y, 1.0), &a.slerp(&flipped, 0.5)));
+
+        // Nearly identical rotations fall back to nlerp rather than dividing by ~0
+        let c = Quat::from_axis_angle(&y, 1e-6);
+        assert!(a.slerp(&c, 0.5).norm().is_finite());
+    }
+}
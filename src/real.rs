@@ -0,0 +1,191 @@
+use std::fmt::Debug;
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+use crate::point::{Point3, Vector3};
+
+// The scalar used for rendering. f64 unless built with the `f32` feature,
+// which trades precision for speed.
+#[cfg(not(feature = "f32"))]
+pub type Float = f64;
+#[cfg(feature = "f32")]
+pub type Float = f32;
+
+// The arithmetic a distance function needs from its scalar type. Implemented
+// for f32, f64 and dual numbers, so the same SDF code can compute plain
+// distances at either precision or distances with gradients.
+pub trait Real:
+    Copy
+    + Debug
+    + PartialEq
+    + PartialOrd
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + Neg<Output = Self>
+{
+    fn from_f64(v: f64) -> Self;
+    fn to_f64(self) -> f64;
+    fn sqrt(self) -> Self;
+    fn abs(self) -> Self;
+    fn max(self, other: Self) -> Self;
+    fn min(self, other: Self) -> Self;
+    fn floor(self) -> Self;
+    fn sin(self) -> Self;
+    fn cos(self) -> Self;
+    // Angle of (x, y) = (other, self) from the x axis, in (-pi, pi]
+    fn atan2(self, other: Self) -> Self;
+
+    // Evaluate a function that only works on plain floats, such as noise,
+    // at p. `value` gives just its value, which is all plain floats need and
+    // which packets call once per lane; `sample` gives its gradient along
+    // with it, so dual numbers can carry on differentiating through it.
+    fn apply3(p: &Point3<Self>, value: impl Fn(&Point3) -> Float, sample: impl Fn(&Point3) -> (Float, Vector3)) -> Self;
+
+    #[allow(clippy::unnecessary_cast)]
+    fn from_float(v: Float) -> Self {
+        Self::from_f64(v as f64)
+    }
+
+    // The plain value, dropping any derivatives
+    #[allow(clippy::unnecessary_cast)]
+    fn value(self) -> Float {
+        self.to_f64() as Float
+    }
+
+    // The inner loops of Vec4 and Mat4, as hooks so f32 and f64 can swap in
+    // SIMD versions with the `simd` feature. Matrices are laid out as in
+    // `Mat4`, with `(x, y)` at `x + y * 4`.
+    fn add4(a: [Self; 4], b: [Self; 4]) -> [Self; 4] {
+        [a[0] + b[0], a[1] + b[1], a[2] + b[2], a[3] + b[3]]
+    }
+
+    fn sub4(a: [Self; 4], b: [Self; 4]) -> [Self; 4] {
+        [a[0] - b[0], a[1] - b[1], a[2] - b[2], a[3] - b[3]]
+    }
+
+    fn mul4(a: [Self; 4], b: [Self; 4]) -> [Self; 4] {
+        [a[0] * b[0], a[1] * b[1], a[2] * b[2], a[3] * b[3]]
+    }
+
+    fn mat_vec(m: &[Self; 16], v: [Self; 4]) -> [Self; 4] {
+        [0, 1, 2, 3].map(|y| m[y * 4] * v[0] + m[1 + y * 4] * v[1] + m[2 + y * 4] * v[2] + m[3 + y * 4] * v[3])
+    }
+
+    fn mat_mat(a: &[Self; 16], b: &[Self; 16]) -> [Self; 16] {
+        let mut r = *a;
+        for (i, r) in r.iter_mut().enumerate() {
+            let (x, y) = (i % 4, i / 4);
+            *r = a[y * 4] * b[x] + a[1 + y * 4] * b[x + 4] + a[2 + y * 4] * b[x + 8] + a[3 + y * 4] * b[x + 12];
+        }
+        r
+    }
+}
+
+macro_rules! impl_real {
+    ($t:ty, $simd:ident) => {
+        impl Real for $t
+        {
+            #[allow(clippy::unnecessary_cast)]
+            fn from_f64(v: f64) -> Self {
+                v as $t
+            }
+
+            #[allow(clippy::unnecessary_cast)]
+            fn to_f64(self) -> f64 {
+                self as f64
+            }
+
+            fn sqrt(self) -> Self {
+                <$t>::sqrt(self)
+            }
+
+            fn abs(self) -> Self {
+                <$t>::abs(self)
+            }
+
+            fn max(self, other: Self) -> Self {
+                <$t>::max(self, other)
+            }
+
+            fn min(self, other: Self) -> Self {
+                <$t>::min(self, other)
+            }
+
+            fn floor(self) -> Self {
+                <$t>::floor(self)
+            }
+
+            fn sin(self) -> Self {
+                <$t>::sin(self)
+            }
+
+            fn cos(self) -> Self {
+                <$t>::cos(self)
+            }
+
+            fn atan2(self, other: Self) -> Self {
+                <$t>::atan2(self, other)
+            }
+
+            fn apply3(p: &Point3<Self>, value: impl Fn(&Point3) -> Float, _: impl Fn(&Point3) -> (Float, Vector3)) -> Self {
+                Self::from_float(value(&p.value()))
+            }
+
+            #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+            #[inline]
+            fn add4(a: [Self; 4], b: [Self; 4]) -> [Self; 4] {
+                crate::simd::$simd::add(a, b)
+            }
+
+            #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+            #[inline]
+            fn sub4(a: [Self; 4], b: [Self; 4]) -> [Self; 4] {
+                crate::simd::$simd::sub(a, b)
+            }
+
+            #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+            #[inline]
+            fn mul4(a: [Self; 4], b: [Self; 4]) -> [Self; 4] {
+                crate::simd::$simd::mul(a, b)
+            }
+
+            #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+            #[inline]
+            fn mat_vec(m: &[Self; 16], v: [Self; 4]) -> [Self; 4] {
+                crate::simd::$simd::mat_vec(m, v)
+            }
+
+            #[cfg(all(feature = "simd", target_arch = "x86_64"))]
+            #[inline]
+            fn mat_mat(a: &[Self; 16], b: &[Self; 16]) -> [Self; 16] {
+                crate::simd::$simd::mat_mat(a, b)
+            }
+        }
+    };
+}
+
+impl_real!(f32, f32x4);
+impl_real!(f64, f64x4);
+
+// Convert between scalar types, through f64 so nothing is lost on the way
+pub fn cast<T: Real, U: Real>(v: T) -> U {
+    U::from_f64(v.to_f64())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conversions() {
+        let v: f32 = cast(2.5f64);
+        assert_eq!(2.5f32, v);
+        assert_eq!(2.5 as Float, v.value());
+        let precise: f64 = cast(0.1f64);
+        assert_eq!(0.1f64, precise);
+        assert_eq!(3.0f64, Real::sqrt(9.0f64));
+        assert_eq!(3.0f32, Real::max(3.0f32, -4.0));
+        assert_eq!(4.0f32, Real::abs(-4.0f32));
+    }
+}
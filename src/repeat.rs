@@ -0,0 +1,299 @@
+// Domain repetition: one child copied across space by folding the query
+// point into a single cell, so a grid of a thousand spheres costs about as
+// much as one.
+//
+// Folding assumes the nearest copy is in the cell the point falls in. That
+// holds for children that are symmetric and fit inside their cell; anything
+// else (off-centre, asymmetric, or spilling over the cell edge) needs
+// `with_neighbours`, which also checks the next cell along each axis, up to
+// 2x2x2 evaluations for a 3D grid or 2 around a circle.
+
+use crate::point::{Point3, Vector3};
+use crate::real::{Float, Real};
+use crate::DistanceField;
+
+const TAU: Float = std::f64::consts::TAU as Float;
+
+// Copies on a rectangular lattice, `period` apart. A zero period leaves that
+// axis alone.
+#[derive(Debug, Copy, Clone)]
+pub struct Repeat<N>
+{
+    node: N,
+    period: Vector3,
+    // Copies either side of the original, per axis
+    limit: Option<Vector3>,
+    mirrored: bool,
+    neighbours: bool,
+}
+
+impl<N: DistanceField> Repeat<N>
+{
+    pub fn new(node: N, period: &Vector3) -> Self {
+        Repeat {
+            node,
+            period: *period,
+            limit: None,
+            mirrored: false,
+            neighbours: false,
+        }
+    }
+
+    // Only `count[i]` copies either side of the original along axis i, so
+    // 2 * count + 1 in all
+    pub fn limited(self, count: [u32; 3]) -> Self {
+        Repeat {
+            limit: Some(Vector3::new(count[0] as Float, count[1] as Float, count[2] as Float)),
+            ..self
+        }
+    }
+
+    // Flip every other cell, so the copies meet their neighbours face to
+    // face. Makes any child tile without seams.
+    pub fn mirrored(self) -> Self {
+        Repeat { mirrored: true, ..self }
+    }
+
+    pub fn with_neighbours(self) -> Self {
+        Repeat { neighbours: true, ..self }
+    }
+
+    // The cells to look in along one axis, and the point's coordinate in each
+    fn axis<T: Real>(&self, p: T, period: Float, limit: Option<Float>) -> ([T; 2], usize) {
+        if period == 0.0 {
+            return ([p, p], 1);
+        }
+
+        let s = T::from_float(period);
+        let one = T::from_float(1.0);
+        let half = T::from_float(0.5);
+        let cells = if self.neighbours {
+            // The two cells whose centres either side of p
+            let c = (p / s).floor();
+            ([c, c + one], 2)
+        } else {
+            ([(p / s + half).floor(); 2], 1)
+        };
+
+        let clamp = |c: T| match limit {
+            Some(n) => c.max(T::from_float(-n)).min(T::from_float(n)),
+            None => c,
+        };
+        let local = |c: T| {
+            let c = clamp(c);
+            let q = p - c * s;
+            if self.mirrored {
+                // (-1)^c, without branching on the cell
+                let odd = c - (c * half).floor() * T::from_float(2.0);
+                q * (one - odd * T::from_float(2.0))
+            } else {
+                q
+            }
+        };
+        ([local(cells.0[0]), local(cells.0[1])], cells.1)
+    }
+}
+
+impl<N: DistanceField> DistanceField for Repeat<N>
+{
+    fn distance<T: Real>(&self, p: &Point3<T>) -> T {
+        let limit = |i: usize| self.limit.map(|l| [l.x, l.y, l.z][i]);
+        let (xs, nx) = self.axis(p.x, self.period.x, limit(0));
+        let (ys, ny) = self.axis(p.y, self.period.y, limit(1));
+        let (zs, nz) = self.axis(p.z, self.period.z, limit(2));
+
+        let mut d: Option<T> = None;
+        for x in &xs[..nx] {
+            for y in &ys[..ny] {
+                for z in &zs[..nz] {
+                    let dc = self.node.distance(&Point3::new(*x, *y, *z));
+                    d = Some(d.map_or(dc, |d| d.min(dc)));
+                }
+            }
+        }
+        d.unwrap()
+    }
+
+    fn lipschitz(&self) -> Float {
+        self.node.lipschitz()
+    }
+}
+
+// `count` copies evenly spaced around the y axis. The child should be
+// modelled at its place in the first sector, centred on the +x axis; use a
+// Transform to repeat around some other axis.
+#[derive(Debug, Copy, Clone)]
+pub struct RepeatPolar<N>
+{
+    node: N,
+    count: u32,
+    neighbours: bool,
+}
+
+impl<N: DistanceField> RepeatPolar<N>
+{
+    pub fn new(node: N, count: u32) -> Self {
+        RepeatPolar {
+            node,
+            count: count.max(1),
+            neighbours: false,
+        }
+    }
+
+    pub fn with_neighbours(self) -> Self {
+        RepeatPolar { neighbours: true, ..self }
+    }
+
+    // p rotated back by whole sectors, about y
+    fn sector<T: Real>(p: &Point3<T>, k: T, angle: Float) -> Point3<T> {
+        let a = k * T::from_float(angle);
+        let (s, c) = (a.sin(), a.cos());
+        Point3::new(p.x * c + p.z * s, p.y, p.z * c - p.x * s)
+    }
+}
+
+impl<N: DistanceField> DistanceField for RepeatPolar<N>
+{
+    fn distance<T: Real>(&self, p: &Point3<T>) -> T {
+        let angle = TAU / self.count as Float;
+        let sectors = p.z.atan2(p.x) / T::from_float(angle);
+
+        if self.neighbours {
+            let k = sectors.floor();
+            let a = self.node.distance(&Self::sector(p, k, angle));
+            let b = self.node.distance(&Self::sector(p, k + T::from_float(1.0), angle));
+            a.min(b)
+        } else {
+            let k = (sectors + T::from_float(0.5)).floor();
+            self.node.distance(&Self::sector(p, k, angle))
+        }
+    }
+
+    fn lipschitz(&self) -> Float {
+        self.node.lipschitz()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::{Cuboid, Sphere};
+    use crate::transform::Transform;
+
+    fn near(a: Float, b: Float) -> bool {
+        Float::abs(a - b) < 1e-5
+    }
+
+    fn sphere() -> Sphere {
+        Sphere { radius: 0.5 }
+    }
+
+    #[test]
+    fn infinite() {
+        let grid = sphere().repeat(&Vector3::new(2.0, 0.0, 3.0));
+        assert!(near(-0.5, grid.distance(&Point3::new(0.0, 0.0, 0.0))));
+        assert!(near(-0.5, grid.distance(&Point3::new(20.0, 0.0, -30.0))));
+        assert!(near(0.5, grid.distance(&Point3::new(1.0, 0.0, 0.0))));
+        // y isn't repeated
+        assert!(near(9.5, grid.distance(&Point3::new(2.0, 10.0, 3.0))));
+    }
+
+    #[test]
+    fn limited() {
+        let row = sphere().repeat(&Vector3::new(2.0, 0.0, 0.0)).limited([2, 0, 0]);
+        assert!(near(-0.5, row.distance(&Point3::new(4.0, 0.0, 0.0))));
+        assert!(near(-0.5, row.distance(&Point3::new(-4.0, 0.0, 0.0))));
+        // Past the end of the row, the last copy is the nearest
+        assert!(near(1.5, row.distance(&Point3::new(6.0, 0.0, 0.0))));
+        assert!(near(15.5, row.distance(&Point3::new(-20.0, 0.0, 0.0))));
+    }
+
+    #[test]
+    fn neighbours() {
+        // A sphere off to one side of its cell: the nearest copy is often in
+        // the next cell over
+        let offset = Transform::translate(sphere(), &Vector3::new(0.8, 0.0, 0.0));
+        let plain = (&offset).repeat(&Vector3::new(2.0, 0.0, 0.0));
+        let checked = (&offset).repeat(&Vector3::new(2.0, 0.0, 0.0)).with_neighbours();
+
+        // Just past x = 1, in the cell centred on 2, close to the copy at 0.8
+        let p = Point3::new(1.1, 0.0, 0.0);
+        assert!(near(-0.2, checked.distance(&p)));
+        assert!(plain.distance(&p) > checked.distance(&p));
+
+        // The neighbour check agrees with a brute force union
+        for i in 0..100 {
+            let p = Point3::new(i as Float * 0.173 - 8.0, 0.3, 0.0);
+            let brute = (-6..=6)
+                .map(|c| offset.distance(&(p - Vector3::new(2.0 * c as Float, 0.0, 0.0))))
+                .fold(Float::MAX, Float::min);
+            assert!(near(brute, checked.distance(&p)), "{:?}", p);
+        }
+    }
+
+    #[test]
+    fn limited_neighbours() {
+        let offset = Transform::translate(sphere(), &Vector3::new(0.8, 0.0, 0.0));
+        let row = (&offset).repeat(&Vector3::new(2.0, 0.0, 0.0)).limited([1, 0, 0]).with_neighbours();
+        for i in 0..100 {
+            let p = Point3::new(i as Float * 0.173 - 8.0, 0.3, 0.0);
+            let brute = (-1..=1)
+                .map(|c| offset.distance(&(p - Vector3::new(2.0 * c as Float, 0.0, 0.0))))
+                .fold(Float::MAX, Float::min);
+            assert!(near(brute, row.distance(&p)), "{:?}", p);
+        }
+    }
+
+    #[test]
+    fn mirrored() {
+        let offset = Transform::translate(sphere(), &Vector3::new(0.5, 0.0, 0.0));
+        let tiles = offset.repeat(&Vector3::new(2.0, 0.0, 0.0)).mirrored();
+        // Cell 0 has its sphere at +0.5, cell 1 (centred on 2) is flipped so
+        // its sphere is at 1.5, and cell -1 (centred on -2) at -2.5
+        assert!(near(-0.5, tiles.distance(&Point3::new(0.5, 0.0, 0.0))));
+        assert!(near(-0.5, tiles.distance(&Point3::new(1.5, 0.0, 0.0))));
+        assert!(near(-0.5, tiles.distance(&Point3::new(-2.5, 0.0, 0.0))));
+        assert!(near(0.5, tiles.distance(&Point3::new(-0.5, 0.0, 0.0))));
+        assert!(near(-0.5, tiles.distance(&Point3::new(4.5, 0.0, 0.0))));
+        assert!(near(0.5, tiles.distance(&Point3::new(2.5, 0.0, 0.0))));
+    }
+
+    #[test]
+    fn polar() {
+        // Six pillars in a ring of radius 3
+        let pillar = Transform::translate(Cuboid { dimensions: Vector3::new(0.2, 2.0, 0.2) }, &Vector3::new(3.0, 0.0, 0.0));
+        let ring = (&pillar).repeat_polar(6);
+        for i in 0..6 {
+            let a = i as Float * TAU / 6.0;
+            let p = Point3::new(3.0 * a.cos(), 1.0, 3.0 * a.sin());
+            assert!(near(pillar.distance(&Point3::new(3.0, 1.0, 0.0)), ring.distance(&p)), "{}", i);
+        }
+        // In between the pillars is empty
+        let a = TAU / 12.0;
+        assert!(ring.distance(&Point3::new(3.0 * a.cos(), 1.0, 3.0 * a.sin())) > 1.0);
+
+        // Neighbour checking agrees with a brute force union
+        let checked = (&pillar).repeat_polar(6).with_neighbours();
+        for i in 0..60 {
+            let a = i as Float * 0.21;
+            let p = Point3::new(2.0 * a.cos(), 0.5, 2.0 * a.sin());
+            let brute = (0..6)
+                .map(|k| pillar.distance(&RepeatPolar::<Sphere>::sector(&p, k as Float, TAU / 6.0)))
+                .fold(Float::MAX, Float::min);
+            assert!(near(brute, checked.distance(&p)));
+        }
+    }
+
+    #[test]
+    fn gradients() {
+        // Normals still come out of the folded space correctly
+        use crate::normal::{calc_normal, NormalMethod};
+        let grid = sphere().repeat(&Vector3::new(2.0, 2.0, 2.0));
+        let n = calc_normal(&grid, &Point3::new(4.0, 2.5, -6.0), 1.0, NormalMethod::AutoDiff);
+        assert!((n - Vector3::new(0.0, 1.0, 0.0)).mag() < 1e-6);
+
+        let ring = Transform::translate(sphere(), &Vector3::new(3.0, 0.0, 0.0)).repeat_polar(4);
+        let n = calc_normal(&ring, &Point3::new(0.0, 0.0, 3.5), 1.0, NormalMethod::AutoDiff);
+        assert!((n - Vector3::new(0.0, 0.0, 1.0)).mag() < 1e-5);
+    }
+}
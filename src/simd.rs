@@ -0,0 +1,222 @@
+// SSE versions of the Vec4 and Mat4 inner loops, for the `simd` feature.
+// SSE and SSE2 are part of the x86_64 baseline, so no runtime detection is
+// needed. Sums are accumulated in the same order as the scalar code and there
+// is no fused multiply-add, so results are bit for bit the same.
+//
+// Matrices are the 16 element arrays from `Mat4`, where `(x, y)` is at
+// `x + y * 4`.
+
+use std::arch::x86_64::*;
+
+pub mod f32x4
+{
+    use super::*;
+
+    #[inline]
+    pub fn add(a: [f32; 4], b: [f32; 4]) -> [f32; 4] {
+        unsafe { store(_mm_add_ps(load(&a), load(&b))) }
+    }
+
+    #[inline]
+    pub fn sub(a: [f32; 4], b: [f32; 4]) -> [f32; 4] {
+        unsafe { store(_mm_sub_ps(load(&a), load(&b))) }
+    }
+
+    #[inline]
+    pub fn mul(a: [f32; 4], b: [f32; 4]) -> [f32; 4] {
+        unsafe { store(_mm_mul_ps(load(&a), load(&b))) }
+    }
+
+    // r[y] = sum over x of m(x, y) * v[x]
+    #[inline]
+    pub fn mat_vec(m: &[f32; 16], v: [f32; 4]) -> [f32; 4] {
+        unsafe {
+            // Rows are contiguous, so load them whole and transpose to get
+            // the columns, rather than gathering each column element by
+            // element
+            let [r0, r1, r2, r3] = [0, 4, 8, 12].map(|k| _mm_loadu_ps(m[k..].as_ptr()));
+            let (t0, t1) = (_mm_unpacklo_ps(r0, r1), _mm_unpacklo_ps(r2, r3));
+            let (t2, t3) = (_mm_unpackhi_ps(r0, r1), _mm_unpackhi_ps(r2, r3));
+            let columns = [_mm_movelh_ps(t0, t1), _mm_movehl_ps(t1, t0), _mm_movelh_ps(t2, t3), _mm_movehl_ps(t3, t2)];
+
+            let mut r = _mm_mul_ps(columns[0], _mm_set1_ps(v[0]));
+            for (column, &vx) in columns.iter().zip(&v).skip(1) {
+                r = _mm_add_ps(r, _mm_mul_ps(*column, _mm_set1_ps(vx)));
+            }
+            store(r)
+        }
+    }
+
+    // r(x, y) = sum over k of a(k, y) * b(x, k), the usual row times column
+    // product. Each row of r is a sum of rows of `b` scaled by single
+    // elements of `a`.
+    #[inline]
+    pub fn mat_mat(a: &[f32; 16], b: &[f32; 16]) -> [f32; 16] {
+        let mut r = [0.0; 16];
+        unsafe {
+            let rows = [0, 4, 8, 12].map(|k| _mm_loadu_ps(b[k..].as_ptr()));
+            for y in 0..4 {
+                let mut acc = _mm_mul_ps(rows[0], _mm_set1_ps(a[y * 4]));
+                for (k, row) in rows.iter().enumerate().skip(1) {
+                    acc = _mm_add_ps(acc, _mm_mul_ps(*row, _mm_set1_ps(a[k + y * 4])));
+                }
+                _mm_storeu_ps(r[y * 4..].as_mut_ptr(), acc);
+            }
+        }
+        r
+    }
+
+    #[inline]
+    unsafe fn load(a: &[f32; 4]) -> __m128 {
+        _mm_loadu_ps(a.as_ptr())
+    }
+
+    #[inline]
+    unsafe fn store(v: __m128) -> [f32; 4] {
+        let mut r = [0.0; 4];
+        _mm_storeu_ps(r.as_mut_ptr(), v);
+        r
+    }
+}
+
+// f64 only fits two to a register, so everything is done in halves
+pub mod f64x4
+{
+    use super::*;
+
+    #[inline]
+    pub fn add(a: [f64; 4], b: [f64; 4]) -> [f64; 4] {
+        unsafe { store(pair(load(&a), load(&b), |a, b| _mm_add_pd(a, b))) }
+    }
+
+    #[inline]
+    pub fn sub(a: [f64; 4], b: [f64; 4]) -> [f64; 4] {
+        unsafe { store(pair(load(&a), load(&b), |a, b| _mm_sub_pd(a, b))) }
+    }
+
+    #[inline]
+    pub fn mul(a: [f64; 4], b: [f64; 4]) -> [f64; 4] {
+        unsafe { store(pair(load(&a), load(&b), |a, b| _mm_mul_pd(a, b))) }
+    }
+
+    // As for f32, load whole rows and transpose, here in 2x2 blocks: column
+    // x's top half comes from rows 0 and 1, its bottom half from rows 2
+    // and 3
+    #[inline]
+    pub fn mat_vec(m: &[f64; 16], v: [f64; 4]) -> [f64; 4] {
+        unsafe {
+            let [r0, r1, r2, r3] = [0, 4, 8, 12].map(|k| (_mm_loadu_pd(m[k..].as_ptr()), _mm_loadu_pd(m[k + 2..].as_ptr())));
+            let columns = [
+                (_mm_unpacklo_pd(r0.0, r1.0), _mm_unpacklo_pd(r2.0, r3.0)),
+                (_mm_unpackhi_pd(r0.0, r1.0), _mm_unpackhi_pd(r2.0, r3.0)),
+                (_mm_unpacklo_pd(r0.1, r1.1), _mm_unpacklo_pd(r2.1, r3.1)),
+                (_mm_unpackhi_pd(r0.1, r1.1), _mm_unpackhi_pd(r2.1, r3.1)),
+            ];
+            let scaled = |x: usize| pair(columns[x], splat(v[x]), |a, b| _mm_mul_pd(a, b));
+            let mut r = scaled(0);
+            for x in 1..4 {
+                r = pair(r, scaled(x), |a, b| _mm_add_pd(a, b));
+            }
+            store(r)
+        }
+    }
+
+    #[inline]
+    pub fn mat_mat(a: &[f64; 16], b: &[f64; 16]) -> [f64; 16] {
+        let mut r = [0.0; 16];
+        unsafe {
+            let rows = [0, 4, 8, 12].map(|k| (_mm_loadu_pd(b[k..].as_ptr()), _mm_loadu_pd(b[k + 2..].as_ptr())));
+            for y in 0..4 {
+                let mut acc = pair(rows[0], splat(a[y * 4]), |a, b| _mm_mul_pd(a, b));
+                for (k, row) in rows.iter().enumerate().skip(1) {
+                    let scaled = pair(*row, splat(a[k + y * 4]), |a, b| _mm_mul_pd(a, b));
+                    acc = pair(acc, scaled, |a, b| _mm_add_pd(a, b));
+                }
+                _mm_storeu_pd(r[y * 4..].as_mut_ptr(), acc.0);
+                _mm_storeu_pd(r[y * 4 + 2..].as_mut_ptr(), acc.1);
+            }
+        }
+        r
+    }
+
+    type Half = (__m128d, __m128d);
+
+    #[inline]
+    fn pair(a: Half, b: Half, f: impl Fn(__m128d, __m128d) -> __m128d) -> Half {
+        (f(a.0, b.0), f(a.1, b.1))
+    }
+
+    #[inline]
+    unsafe fn splat(v: f64) -> Half {
+        (_mm_set1_pd(v), _mm_set1_pd(v))
+    }
+
+    #[inline]
+    unsafe fn load(a: &[f64; 4]) -> Half {
+        (_mm_loadu_pd(a.as_ptr()), _mm_loadu_pd(a[2..].as_ptr()))
+    }
+
+    #[inline]
+    unsafe fn store(v: Half) -> [f64; 4] {
+        let mut r = [0.0; 4];
+        _mm_storeu_pd(r.as_mut_ptr(), v.0);
+        _mm_storeu_pd(r[2..].as_mut_ptr(), v.1);
+        r
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const A: [f64; 16] = [
+        0.1, 2.0, -3.5, 4.0, 1.0 / 3.0, 6.0, 7.25, -8.0, 9.0, 0.7, 11.0, 12.0, -13.0, 14.0, 0.3, 16.0,
+    ];
+
+    fn b() -> [f64; 16] {
+        A.map(|v| v * 1.7 - 0.2)
+    }
+
+    fn scalar_mat_vec(m: &[f64; 16], v: [f64; 4]) -> [f64; 4] {
+        [0, 1, 2, 3].map(|y| m[y * 4] * v[0] + m[1 + y * 4] * v[1] + m[2 + y * 4] * v[2] + m[3 + y * 4] * v[3])
+    }
+
+    fn scalar_mat_mat(a: &[f64; 16], b: &[f64; 16]) -> [f64; 16] {
+        let mut r = [0.0; 16];
+        for (i, r) in r.iter_mut().enumerate() {
+            let (x, y) = (i % 4, i / 4);
+            *r = a[y * 4] * b[x] + a[1 + y * 4] * b[x + 4] + a[2 + y * 4] * b[x + 8] + a[3 + y * 4] * b[x + 12];
+        }
+        r
+    }
+
+    #[test]
+    fn matches_scalar_f64() {
+        let v = [0.3, -1.1, 2.0 / 3.0, 1.0];
+        let u = [5.0, 0.25, -7.0, 0.0];
+        assert_eq!([0, 1, 2, 3].map(|i| v[i] + u[i]), f64x4::add(v, u));
+        assert_eq!([0, 1, 2, 3].map(|i| v[i] - u[i]), f64x4::sub(v, u));
+        assert_eq!([0, 1, 2, 3].map(|i| v[i] * u[i]), f64x4::mul(v, u));
+        assert_eq!(scalar_mat_vec(&A, v), f64x4::mat_vec(&A, v));
+        assert_eq!(scalar_mat_mat(&A, &b()), f64x4::mat_mat(&A, &b()));
+    }
+
+    #[test]
+    fn matches_scalar_f32() {
+        let a = A.map(|v| v as f32);
+        let b = b().map(|v| v as f32);
+        let v = [0.3f32, -1.1, 2.0 / 3.0, 1.0];
+        let expected_mv = [0, 1, 2, 3].map(|y| a[y * 4] * v[0] + a[1 + y * 4] * v[1] + a[2 + y * 4] * v[2] + a[3 + y * 4] * v[3]);
+        assert_eq!(expected_mv, f32x4::mat_vec(&a, v));
+
+        let mut expected_mm = [0.0f32; 16];
+        for (i, r) in expected_mm.iter_mut().enumerate() {
+            let (x, y) = (i % 4, i / 4);
+            *r = a[y * 4] * b[x] + a[1 + y * 4] * b[x + 4] + a[2 + y * 4] * b[x + 8] + a[3 + y * 4] * b[x + 12];
+        }
+        assert_eq!(expected_mm, f32x4::mat_mat(&a, &b));
+        assert_eq!(v.map(|x| x + x), f32x4::add(v, v));
+        assert_eq!(v.map(|x| x * x), f32x4::mul(v, v));
+        assert_eq!([0.0; 4], f32x4::sub(v, v));
+    }
+}
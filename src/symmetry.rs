@@ -0,0 +1,344 @@
+// Symmetry by folding space: every point is reflected into one region before
+// the child sees it, so modelling one half (or one octant, or one wedge of a
+// polyhedron) gives the whole symmetric object for the price of one.
+//
+// Reflections don't stretch distances, so folded fields stay exact as long
+// as the child stays inside the region that's kept. A child that pokes out
+// across a mirror is cut off there.
+
+use anyhow::{bail, Result};
+
+use crate::point::{Point3, Vector3};
+use crate::real::{Float, Real};
+use crate::DistanceField;
+
+// Mirrored across the plane through `offset * normal`, keeping the side the
+// normal points to
+#[derive(Debug, Copy, Clone)]
+pub struct Mirror<N>
+{
+    node: N,
+    normal: Vector3,
+    offset: Float,
+}
+
+impl<N: DistanceField> Mirror<N>
+{
+    // The normal needn't be unit length, but can't be zero
+    pub fn new(node: N, normal: &Vector3, offset: Float) -> Result<Self> {
+        let mag = normal.mag();
+        if mag == 0.0 || !mag.is_finite() {
+            bail!("Mirror normal must be non-zero and finite, got {:?}", normal);
+        }
+        Ok(Mirror {
+            node,
+            normal: normal.scale(1.0 / mag),
+            offset,
+        })
+    }
+}
+
+impl<N: DistanceField> DistanceField for Mirror<N>
+{
+    fn distance<T: Real>(&self, p: &Point3<T>) -> T {
+        self.node.distance(&reflect(p, &self.normal, self.offset))
+    }
+
+    fn lipschitz(&self) -> Float {
+        self.node.lipschitz()
+    }
+}
+
+// Points on the wrong side of the plane through `offset * normal` reflected
+// across it; the rest left alone
+fn reflect<T: Real>(p: &Point3<T>, normal: &Vector3, offset: Float) -> Point3<T> {
+    let n = normal.lift::<T>();
+    let d = p.to_vector().dot_product(&n) - T::from_float(offset);
+    *p - n * (d.min(T::from_float(0.0)) * T::from_float(2.0))
+}
+
+// Mirrored across any of the coordinate planes: `axes[i]` folds axis i with
+// abs, so only the positive side of it is modelled
+#[derive(Debug, Copy, Clone)]
+pub struct Symmetric<N>
+{
+    node: N,
+    axes: [bool; 3],
+}
+
+impl<N: DistanceField> Symmetric<N>
+{
+    pub fn new(node: N, axes: [bool; 3]) -> Self {
+        Symmetric { node, axes }
+    }
+
+    // Left to right, modelling x >= 0
+    pub fn x(node: N) -> Self {
+        Symmetric::new(node, [true, false, false])
+    }
+
+    pub fn y(node: N) -> Self {
+        Symmetric::new(node, [false, true, false])
+    }
+
+    pub fn z(node: N) -> Self {
+        Symmetric::new(node, [false, false, true])
+    }
+
+    // All three, modelling only the positive octant
+    pub fn octant(node: N) -> Self {
+        Symmetric::new(node, [true; 3])
+    }
+}
+
+impl<N: DistanceField> DistanceField for Symmetric<N>
+{
+    fn distance<T: Real>(&self, p: &Point3<T>) -> T {
+        let fold = |v: T, axis: bool| if axis { v.abs() } else { v };
+        self.node.distance(&Point3::new(
+            fold(p.x, self.axes[0]),
+            fold(p.y, self.axes[1]),
+            fold(p.z, self.axes[2]),
+        ))
+    }
+
+    fn lipschitz(&self) -> Float {
+        self.node.lipschitz()
+    }
+}
+
+// The symmetry groups of the regular polyhedra. The dodecahedron and
+// icosahedron are duals, so they share the icosahedral group, as the cube
+// shares the octahedron's.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Polyhedral
+{
+    Tetrahedral,
+    Octahedral,
+    Icosahedral,
+}
+
+impl Polyhedral
+{
+    // The group is generated by reflections in the x = 0 and y = 0 planes
+    // and one more, at pi / 3 to the first and pi / n to the second
+    fn n(&self) -> Float {
+        match self {
+            Polyhedral::Tetrahedral => 3.0,
+            Polyhedral::Octahedral => 4.0,
+            Polyhedral::Icosahedral => 5.0,
+        }
+    }
+
+    fn mirror(&self) -> Vector3 {
+        let c = (std::f64::consts::PI as Float / self.n()).cos();
+        Vector3::new(-0.5, -c, Float::sqrt(0.75 - c * c))
+    }
+
+    // Fold a point into the fundamental region, the wedge with x and y
+    // positive and on the inside of the third mirror (after Knighty's
+    // polyhedra folds)
+    pub fn fold<T: Real>(&self, p: &Point3<T>) -> Point3<T> {
+        let mirror = self.mirror();
+        let mut p = *p;
+        for _ in 0..self.n() as usize {
+            p = Point3::new(p.x.abs(), p.y.abs(), p.z);
+            p = reflect(&p, &mirror, 0.0);
+        }
+        p
+    }
+
+    // Unit directions to the corners of the fundamental region, where the
+    // mirrors meet, for placing children. For the icosahedral group: an
+    // icosahedron vertex, a dodecahedron vertex (the centre of an
+    // icosahedron face) and the middle of an edge. In the same order, the
+    // octahedral group gives an octahedron vertex, a cube vertex and an edge
+    // middle, and the tetrahedral group a vertex, the centre of a face and
+    // an edge middle.
+    pub fn corners(&self) -> [Vector3; 3] {
+        let m = self.mirror();
+        let (a, b) = (Vector3::new(1.0, 0.0, 0.0), Vector3::new(0.0, 1.0, 0.0));
+        // Each corner lies on two of the mirrors, so it's along their
+        // normals' cross product, turned to face into the region
+        let inward = |v: Vector3| {
+            let v = v.normalized();
+            if v.z < 0.0 { -v } else { v }
+        };
+        [inward(b.cross_product(&m)), inward(m.cross_product(&a)), inward(a.cross_product(&b))]
+    }
+}
+
+// Kaleidoscopic symmetry: the child, modelled in the fundamental region of a
+// polyhedral group, copied by every reflection and rotation in the group
+#[derive(Debug, Copy, Clone)]
+pub struct Kaleidoscope<N>
+{
+    pub node: N,
+    pub group: Polyhedral,
+}
+
+impl<N: DistanceField> DistanceField for Kaleidoscope<N>
+{
+    fn distance<T: Real>(&self, p: &Point3<T>) -> T {
+        self.node.distance(&self.group.fold(p))
+    }
+
+    fn lipschitz(&self) -> Float {
+        self.node.lipschitz()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::Sphere;
+    use crate::testing::{assert_packet_and_gradient, scatter_points};
+    use crate::transform::Transform;
+
+    fn near(a: Float, b: Float) -> bool {
+        Float::abs(a - b) < 1e-5
+    }
+
+    fn near_point(a: &Point3, b: &Point3) -> bool {
+        (*a - *b).mag() < 1e-5
+    }
+
+    fn ball(at: &Vector3) -> Transform<Sphere> {
+        Transform::translate(Sphere { radius: 0.25 }, at)
+    }
+
+    // Points in a cube of side 4
+    fn points() -> impl Iterator<Item = Point3> {
+        scatter_points(200, 2.0)
+    }
+
+    #[test]
+    fn mirror() {
+        // Across x = y, keeping x > y
+        let m = ball(&Vector3::new(1.0, 0.5, 0.0)).mirror(&Vector3::new(1.0, -1.0, 0.0), 0.0).unwrap();
+        assert!(near(-0.25, m.distance(&Point3::new(1.0, 0.5, 0.0))));
+        assert!(near(-0.25, m.distance(&Point3::new(0.5, 1.0, 0.0))));
+        assert!(m.distance(&Point3::new(-1.0, 0.5, 0.0)) > 1.0);
+
+        // Offset planes: a ball at x = 3 mirrored in x = 2 appears at x = 1
+        let m = ball(&Vector3::new(3.0, 0.0, 0.0)).mirror(&Vector3::new(2.0, 0.0, 0.0), 2.0).unwrap();
+        assert!(near(-0.25, m.distance(&Point3::new(1.0, 0.0, 0.0))));
+        assert!(near(-0.25, m.distance(&Point3::new(3.0, 0.0, 0.0))));
+        assert!(near(0.75, m.distance(&Point3::new(2.0, 0.0, 0.0))));
+
+        // No plane to mirror in
+        assert!(ball(&Vector3::zero()).mirror(&Vector3::zero(), 1.0).is_err());
+        assert!(ball(&Vector3::zero()).mirror(&Vector3::new(Float::NAN, 1.0, 0.0), 0.0).is_err());
+    }
+
+    #[test]
+    fn mirror_is_a_union() {
+        let at = Vector3::new(0.5, 1.0, -0.3);
+        let normal = Vector3::new(1.0, 0.5, 0.0).normalized();
+        let reflected = at - normal.scale(2.0 * at.dot_product(&normal));
+        let m = ball(&at).mirror(&normal, 0.0).unwrap();
+        let both = ball(&at).union(ball(&reflected));
+        for p in points() {
+            assert!(near(both.distance(&p), m.distance(&p)), "{:?}", p);
+        }
+    }
+
+    #[test]
+    fn axes() {
+        let at = Vector3::new(1.0, 0.5, 0.75);
+        let x = ball(&at).symmetric([true, false, false]);
+        assert!(near(-0.25, x.distance(&Point3::new(-1.0, 0.5, 0.75))));
+        assert!(x.distance(&Point3::new(1.0, -0.5, 0.75)) > 0.0);
+
+        let octant = Symmetric::octant(ball(&at));
+        for sx in [-1.0, 1.0] {
+            for sy in [-1.0, 1.0] {
+                for sz in [-1.0, 1.0] {
+                    let p = Point3::new(sx * at.x, sy * at.y, sz * at.z);
+                    assert!(near(-0.25, octant.distance(&p)));
+                }
+            }
+        }
+        assert_eq!(Symmetric::y(ball(&at)).distance(&Point3::new(0.0, -2.0, 0.0)), Symmetric::y(ball(&at)).distance(&Point3::new(0.0, 2.0, 0.0)));
+    }
+
+    #[test]
+    fn icosahedron() {
+        // The 12 vertices of an icosahedron, which has mirrors in the
+        // coordinate planes
+        let phi = (1.0 + Float::sqrt(5.0)) / 2.0;
+        let mut vertices = vec![];
+        for a in [-1.0, 1.0] {
+            for b in [-phi, phi] {
+                vertices.push(Vector3::new(a, 0.0, b));
+                vertices.push(Vector3::new(0.0, b, a));
+                vertices.push(Vector3::new(b, a, 0.0));
+            }
+        }
+
+        // All fold onto the same corner of the fundamental region
+        let group = Polyhedral::Icosahedral;
+        let corners = group.corners();
+        for v in &vertices {
+            let v = Point3::origin() + v.normalized();
+            let folded = group.fold(&v);
+            assert!(near_point(&(Point3::origin() + corners[0]), &folded), "{:?} -> {:?}", v, folded);
+        }
+
+        // So one ball at a corner gives one at every vertex
+        let k = ball(&corners[0].scale(2.0)).kaleidoscope(group);
+        for v in &vertices {
+            assert!(near(-0.25, k.distance(&(Point3::origin() + v.normalized().scale(2.0)))));
+        }
+
+        // The second corner is a dodecahedron vertex, the centre of a face
+        let face = (Vector3::new(1.0, 0.0, phi) + Vector3::new(0.0, phi, 1.0) + Vector3::new(phi, 1.0, 0.0)).normalized();
+        let dodeca = ball(&corners[1].scale(2.0)).kaleidoscope(group);
+        assert!(near(-0.25, dodeca.distance(&(Point3::origin() + face.scale(2.0)))));
+    }
+
+    #[test]
+    fn invariant_under_the_group() {
+        // Each group is generated by its three mirrors, so reflecting in any
+        // of them mustn't change the distance
+        for group in [Polyhedral::Tetrahedral, Polyhedral::Octahedral, Polyhedral::Icosahedral] {
+            let corners = group.corners();
+            let child = ball(&corners[0].scale(1.5)).union(ball(&(corners[1] + corners[2]).scale(0.8)));
+            let k = child.kaleidoscope(group);
+            let m = group.mirror();
+            for p in points() {
+                let d = k.distance(&p);
+                assert!(near(d, k.distance(&Point3::new(-p.x, p.y, p.z))), "{:?}", group);
+                assert!(near(d, k.distance(&Point3::new(p.x, -p.y, p.z))), "{:?}", group);
+                let r = p - m.scale(2.0 * p.to_vector().dot_product(&m));
+                assert!(near(d, k.distance(&r)), "{:?}", group);
+
+                // Folding lands inside all three mirrors
+                let f = group.fold(&p).to_vector();
+                assert!(f.x >= 0.0 && f.y >= 0.0 && f.dot_product(&m) >= -1e-5);
+                assert!(near(f.mag(), p.to_vector().mag()));
+            }
+        }
+    }
+
+    #[test]
+    fn corners() {
+        // The rotational symmetry about each corner: n-fold, 3-fold, 2-fold
+        for (group, n) in [(Polyhedral::Tetrahedral, 3), (Polyhedral::Octahedral, 4), (Polyhedral::Icosahedral, 5)] {
+            let k = ball(&Vector3::new(0.3, 0.2, 1.0)).kaleidoscope(group);
+            for (corner, n) in group.corners().iter().zip([n, 3, 2]) {
+                let q = crate::quat::Quat::from_axis_angle(corner, std::f64::consts::TAU as Float / n as Float);
+                for p in points().take(20) {
+                    let r = Point3::origin() + q.rotate(&p.to_vector());
+                    assert!(near(k.distance(&p), k.distance(&r)), "{:?} {}", group, n);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn packets_and_gradients() {
+        let shape = ball(&Vector3::new(0.3, 0.4, 1.0)).kaleidoscope(Polyhedral::Icosahedral).mirror(&Vector3::new(0.2, 1.0, 0.1), 0.5).unwrap();
+        assert_packet_and_gradient(&shape);
+    }
+}
@@ -0,0 +1,408 @@
+// Heightfield terrain: ground at y = h(x, z), from a heightmap image or
+// fractal noise.
+//
+// The vertical gap y - h isn't a distance, but it's off by at most
+// sqrt(1 + s²) where s bounds the slope of h, which is the Lipschitz bound
+// the generic marcher scales its steps by. `cast_terrain` in the march module
+// does better for terrain on its own, stepping by how fast the ray can close
+// the gap and dropping detail too fine to see at the distance it has reached.
+
+use std::num::NonZeroU32;
+
+use anyhow::{bail, Context, Result};
+use image::{ImageBuffer, Luma};
+
+use crate::noise::{Fractal, Noise};
+use crate::point::{Point3, Vector3};
+use crate::real::{Float, Real};
+use crate::DistanceField;
+
+pub trait Heights
+{
+    // Height at (x, z) and its gradient along x and z, leaving out features
+    // smaller than `detail` where that's cheaper
+    fn height(&self, x: Float, z: Float, detail: Float) -> (Float, [Float; 2]);
+
+    // Just the height, the same as `height` gives, where that's cheaper
+    fn get(&self, x: Float, z: Float, detail: Float) -> Float {
+        self.height(x, z, detail).0
+    }
+
+    // Bound on the gradient's length, at the same detail
+    fn slope(&self, detail: Float) -> Float;
+}
+
+impl<H: Heights> Heights for &H
+{
+    fn height(&self, x: Float, z: Float, detail: Float) -> (Float, [Float; 2]) {
+        (*self).height(x, z, detail)
+    }
+
+    fn get(&self, x: Float, z: Float, detail: Float) -> Float {
+        (*self).get(x, z, detail)
+    }
+
+    fn slope(&self, detail: Float) -> Float {
+        (*self).slope(detail)
+    }
+}
+
+pub type Gray16 = ImageBuffer<Luma<u16>, Vec<u16>>;
+
+// A greyscale image laid flat, centred on the origin and `size` across in x
+// and z, with black at height 0 and white at `height`. Bilinear between
+// pixels and flat beyond the edges. Detail is fixed by the resolution.
+#[derive(Debug, Clone)]
+pub struct Heightmap
+{
+    image: Gray16,
+    size: Float,
+    height: Float,
+    slope: Float,
+}
+
+impl Heightmap
+{
+    // The corners of the image sit on the corners of the map, so it needs at
+    // least two pixels each way.
+    pub fn new(image: Gray16, size: Float, height: Float) -> Result<Self> {
+        if image.width() < 2 || image.height() < 2 {
+            bail!("Heightmap must be at least 2x2 pixels, got {}x{}", image.width(), image.height());
+        }
+        if size <= 0.0 || !size.is_finite() {
+            bail!("Heightmap size must be positive, got {}", size);
+        }
+
+        let mut map = Heightmap { image, size, height, slope: 0.0 };
+
+        // Bilinear gradients along each axis are at most the steepest step
+        // between neighbouring pixels along it
+        let (w, h) = (map.image.width(), map.image.height());
+        let (mut sx, mut sz) = (0.0, 0.0);
+        for y in 0..h {
+            for x in 0..w {
+                let v = map.pixel(x as i64, y as i64);
+                if x + 1 < w {
+                    sx = Float::max(sx, Float::abs(map.pixel(x as i64 + 1, y as i64) - v));
+                }
+                if y + 1 < h {
+                    sz = Float::max(sz, Float::abs(map.pixel(x as i64, y as i64 + 1) - v));
+                }
+            }
+        }
+        let (px, pz) = map.spacing();
+        map.slope = Float::hypot(sx / px, sz / pz);
+        Ok(map)
+    }
+
+    pub fn open(path: &str, size: Float, height: Float) -> Result<Self> {
+        let image = image::open(path).with_context(|| format!("Can't load heightmap {}", path))?;
+        Heightmap::new(image.to_luma16(), size, height).with_context(|| format!("Can't use heightmap {}", path))
+    }
+
+    // Distance between pixels along x and z
+    fn spacing(&self) -> (Float, Float) {
+        let gaps = |n: u32| self.size / (n - 1) as Float;
+        (gaps(self.image.width()), gaps(self.image.height()))
+    }
+
+    // Height of a pixel, clamped to the image. Rows run along z.
+    fn pixel(&self, x: i64, y: i64) -> Float {
+        let x = x.clamp(0, self.image.width() as i64 - 1) as u32;
+        let y = y.clamp(0, self.image.height() as i64 - 1) as u32;
+        self.image.get_pixel(x, y)[0] as Float / u16::MAX as Float * self.height
+    }
+}
+
+impl Heights for Heightmap
+{
+    fn height(&self, x: Float, z: Float, _detail: Float) -> (Float, [Float; 2]) {
+        // Corner pixels sit on the corners of the map
+        let (px, pz) = self.spacing();
+        let u = x + self.size / 2.0;
+        let v = z + self.size / 2.0;
+        let (last_x, last_y) = ((self.image.width() - 1) as Float, (self.image.height() - 1) as Float);
+        let gx = (u / px).clamp(0.0, last_x);
+        let gy = (v / pz).clamp(0.0, last_y);
+
+        let (x0, y0) = (gx.floor().min(last_x - 1.0), gy.floor().min(last_y - 1.0));
+        let (fx, fy) = (gx - x0, gy - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        let h00 = self.pixel(x0, y0);
+        let h10 = self.pixel(x0 + 1, y0);
+        let h01 = self.pixel(x0, y0 + 1);
+        let h11 = self.pixel(x0 + 1, y0 + 1);
+
+        let bottom = h00 + (h10 - h00) * fx;
+        let top = h01 + (h11 - h01) * fx;
+        let h = bottom + (top - bottom) * fy;
+
+        // Flat outside the map
+        let inside = |g: Float, last: Float, spacing: Float| if g > 0.0 && g < last { 1.0 / spacing } else { 0.0 };
+        let dx = ((h10 - h00) * (1.0 - fy) + (h11 - h01) * fy) * inside(u / px, last_x, px);
+        let dz = (top - bottom) * inside(v / pz, last_y, pz);
+        (h, [dx, dz])
+    }
+
+    fn slope(&self, _detail: Float) -> Float {
+        self.slope
+    }
+}
+
+// Rolling hills or mountains: fractal noise scaled to `amplitude` either side
+// of zero (or 0 to `amplitude` for turbulent and ridged noise), with
+// features about 1 / `frequency` across. Octaves finer than the detail asked
+// for are skipped.
+#[derive(Debug, Copy, Clone)]
+pub struct NoiseHeights<N>
+{
+    pub fractal: Fractal<N>,
+    pub amplitude: Float,
+    pub frequency: Float,
+}
+
+impl<N: Noise> NoiseHeights<N>
+{
+    pub fn new(fractal: Fractal<N>, amplitude: Float, frequency: Float) -> Self {
+        NoiseHeights { fractal, amplitude, frequency }
+    }
+
+    // The octaves with wavelengths of at least `detail`, at least one
+    fn octaves(&self, detail: Float) -> NonZeroU32 {
+        if detail <= 0.0 {
+            return self.fractal.octaves;
+        }
+        let wavelength = 1.0 / (self.frequency * detail);
+        let kept = (wavelength.ln() / self.fractal.lacunarity.ln()).floor() as i64 + 1;
+        NonZeroU32::new(kept.clamp(1, self.fractal.octaves.get() as i64) as u32).unwrap_or(NonZeroU32::MIN)
+    }
+
+    // The fractal at that detail, and what to scale it by. Fractals normalise
+    // by the amplitude of their octaves, so dropping some would stretch the
+    // rest.
+    fn at_detail(&self, detail: Float) -> (Fractal<N>, Float)
+    where
+        N: Copy,
+    {
+        let fractal = Fractal { octaves: self.octaves(detail), ..self.fractal };
+        let scale = self.amplitude * fractal.total_amplitude() / self.fractal.total_amplitude();
+        (fractal, scale)
+    }
+}
+
+impl<N: Noise + Copy> Heights for NoiseHeights<N>
+{
+    fn height(&self, x: Float, z: Float, detail: Float) -> (Float, [Float; 2]) {
+        let (fractal, scale) = self.at_detail(detail);
+        let f = self.frequency;
+        let (h, d) = fractal.sample(&[x * f, z * f]);
+        (h * scale, d.map(|d| d * scale * f))
+    }
+
+    fn get(&self, x: Float, z: Float, detail: Float) -> Float {
+        let (fractal, scale) = self.at_detail(detail);
+        fractal.get(&[x * self.frequency, z * self.frequency]) * scale
+    }
+
+    // Each octave adds about as much slope as the first, so far off terrain
+    // is much gentler
+    fn slope(&self, detail: Float) -> Float {
+        let (fractal, scale) = self.at_detail(detail);
+        Float::abs(scale * self.frequency) * fractal.lipschitz::<2>()
+    }
+}
+
+// The ground beneath a heightfield
+#[derive(Debug, Copy, Clone)]
+pub struct Terrain<H>
+{
+    pub heights: H,
+    // Level the heights are measured up from
+    pub base: Float,
+    // Smallest feature worth drawing per unit distance from the eye, about
+    // the angle a pixel covers
+    pub detail: Float,
+}
+
+impl<H: Heights> Terrain<H>
+{
+    pub fn new(heights: H) -> Self {
+        Terrain { heights, base: 0.0, detail: 0.001 }
+    }
+
+    // Height with detail to suit being seen from `t` away
+    pub fn height(&self, x: Float, z: Float, t: Float) -> (Float, [Float; 2]) {
+        let (h, d) = self.heights.height(x, z, self.detail * t);
+        (self.base + h, d)
+    }
+
+    // Just the height, for the same
+    pub fn get(&self, x: Float, z: Float, t: Float) -> Float {
+        self.base + self.heights.get(x, z, self.detail * t)
+    }
+
+    // Slope bound for the same
+    pub fn slope(&self, t: Float) -> Float {
+        self.heights.slope(self.detail * t)
+    }
+}
+
+impl<H: Heights> DistanceField for Terrain<H>
+{
+    // In full detail, as there's no telling where it's seen from
+    fn distance<T: Real>(&self, p: &Point3<T>) -> T {
+        p.y - T::from_float(self.base) - T::apply3(
+            p,
+            |q| self.heights.get(q.x, q.z, 0.0),
+            |q| {
+                let (h, d) = self.heights.height(q.x, q.z, 0.0);
+                (h, Vector3::new(d[0], 0.0, d[1]))
+            },
+        )
+    }
+
+    fn lipschitz(&self) -> Float {
+        Float::hypot(1.0, self.heights.slope(0.0))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dual::Dual;
+    use crate::noise::Perlin;
+
+    fn near(a: Float, b: Float) -> bool {
+        Float::abs(a - b) < 1e-4
+    }
+
+    fn hills() -> NoiseHeights<Perlin> {
+        NoiseHeights::new(Fractal::fbm(Perlin::new(3)), 2.0, 0.1)
+    }
+
+    // A 3x2 map, 2 units between pixels along x and 4 along z
+    fn ramp() -> Heightmap {
+        let image = Gray16::from_fn(3, 2, |x, y| Luma([(x * 10000 + y * 30000) as u16]));
+        Heightmap::new(image, 4.0, u16::MAX as Float).unwrap()
+    }
+
+    #[test]
+    fn heightmap() {
+        let map = ramp();
+        // Corners of the map
+        assert!(near(0.0, map.height(-2.0, -2.0, 0.0).0));
+        assert!(near(20000.0, map.height(2.0, -2.0, 0.0).0));
+        assert!(near(50000.0, map.height(2.0, 2.0, 0.0).0));
+        // Bilinear in between, with matching gradients
+        let (h, d) = map.height(-1.0, 0.0, 0.0);
+        assert!(near(5000.0 + 15000.0, h));
+        assert!(near(5000.0, d[0]));
+        assert!(near(7500.0, d[1]));
+        // Flat past the edges
+        let (h, d) = map.height(10.0, -10.0, 0.0);
+        assert!(near(20000.0, h));
+        assert_eq!([0.0, 0.0], d);
+        // The steepest gradient is at least that anywhere in the map
+        assert!(near(Float::hypot(5000.0, 7500.0), map.slope(0.0)));
+    }
+
+    #[test]
+    fn heightmap_gradients() {
+        let image = Gray16::from_fn(16, 16, |x, y| Luma([((x * 7 + y * 13) % 11 * 5000) as u16]));
+        let map = Heightmap::new(image, 10.0, 2.0).unwrap();
+        let e = 1e-3;
+        for i in 0..200 {
+            let (x, z) = ((i as Float * 0.71).sin() * 4.9, (i as Float * 1.37).cos() * 4.9);
+            let (_, d) = map.height(x, z, 0.0);
+            let dx = (map.height(x + e, z, 0.0).0 - map.height(x - e, z, 0.0).0) / (2.0 * e);
+            let dz = (map.height(x, z + e, 0.0).0 - map.height(x, z - e, 0.0).0) / (2.0 * e);
+            // Allow for a pixel boundary between the samples
+            assert!(Float::abs(d[0] - dx) < 0.1 || Float::hypot(d[0], d[1]) <= map.slope(0.0) + 1e-4, "{} {}", x, z);
+            assert!(Float::abs(d[1] - dz) < 0.1 || Float::hypot(d[0], d[1]) <= map.slope(0.0) + 1e-4, "{} {}", x, z);
+            assert!(Float::hypot(dx, dz) <= map.slope(0.0) + 1e-2);
+        }
+    }
+
+    #[test]
+    fn open_heightmap() {
+        let path = std::env::temp_dir().join(format!("sdf-rs-heightmap-{}.png", std::process::id()));
+        let image = Gray16::from_fn(4, 4, |x, _| Luma([(x * 20000) as u16]));
+        image.save(&path).unwrap();
+        let map = Heightmap::open(path.to_str().unwrap(), 3.0, 6.0).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(near(0.0, map.height(-1.5, 0.0, 0.0).0));
+        assert!(near(6.0 * 60000.0 / 65535.0, map.height(1.5, 0.0, 0.0).0));
+        assert!(Heightmap::open("no/such/heightmap.png", 1.0, 1.0).is_err());
+    }
+
+    #[test]
+    fn degenerate_heightmaps() {
+        for (w, h) in [(0, 0), (1, 1), (1, 4), (4, 0)] {
+            assert!(Heightmap::new(Gray16::new(w, h), 1.0, 1.0).is_err(), "{}x{}", w, h);
+        }
+        assert!(Heightmap::new(Gray16::new(2, 2), 0.0, 1.0).is_err());
+        assert!(Heightmap::new(Gray16::new(2, 2), Float::NAN, 1.0).is_err());
+        assert!(Heightmap::new(Gray16::new(2, 2), 1.0, 1.0).is_ok());
+    }
+
+    #[test]
+    fn detail() {
+        let hills = hills();
+        // Features 10 units across, halving four times
+        assert_eq!(5, hills.octaves(0.0).get());
+        assert_eq!(5, hills.octaves(0.5).get());
+        assert_eq!(4, hills.octaves(1.0).get());
+        assert_eq!(2, hills.octaves(4.0).get());
+        assert_eq!(1, hills.octaves(100.0).get());
+
+        // Dropping octaves only loses their share of the height
+        let lost: Float = [2, 3, 4].iter().map(|i| 0.5_f64.powi(*i) as Float).sum::<Float>() / 1.9375 * 2.0;
+        for i in 0..100 {
+            let (x, z) = (i as Float * 1.3 - 60.0, i as Float * -0.7 + 20.0);
+            let full = hills.height(x, z, 0.0).0;
+            let coarse = hills.height(x, z, 4.0).0;
+            assert_eq!(coarse, hills.get(x, z, 4.0));
+            assert!(Float::abs(full - coarse) <= lost + 1e-4);
+            assert!(Float::abs(full) <= 2.0 + 1e-4);
+        }
+
+        // Fewer octaves, gentler slopes
+        assert!(hills.slope(4.0) < 0.5 * hills.slope(0.0));
+        for i in 0..100 {
+            let (_, d) = hills.height(i as Float * 0.37, i as Float * -2.1, 4.0);
+            assert!(Float::hypot(d[0], d[1]) <= hills.slope(4.0));
+        }
+    }
+
+    #[test]
+    fn distance_is_gap() {
+        let terrain = Terrain::new(hills());
+        let slope = hills().slope(0.0);
+        assert!(near(Float::hypot(1.0, slope), terrain.lipschitz()));
+        for i in 0..50 {
+            let p = Point3::new(i as Float * 0.9, 3.0 - i as Float * 0.1, i as Float * -1.1);
+            let (h, _) = hills().height(p.x, p.z, 0.0);
+            assert!(near(p.y - h, terrain.distance(&p)));
+        }
+    }
+
+    #[test]
+    fn base() {
+        let low = Terrain { base: -5.0, ..Terrain::new(hills()) };
+        let p = Point3::new(2.0, -4.0, 3.0);
+        let (h, _) = hills().height(p.x, p.z, 0.0);
+        assert!(near(h - 5.0, low.get(p.x, p.z, 0.0)));
+        assert!(near(p.y - (h - 5.0), low.distance(&p)));
+    }
+
+    #[test]
+    fn normals() {
+        let terrain = Terrain::new(hills());
+        let p = Point3::new(3.0, 0.0, -7.0);
+        let (_, d) = hills().height(p.x, p.z, 0.0);
+        let grad = terrain.distance(&Dual::position(&p)).grad;
+        assert!(near(-d[0], grad.x));
+        assert!(near(1.0, grad.y));
+        assert!(near(-d[1], grad.z));
+    }
+}
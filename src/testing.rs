@@ -0,0 +1,52 @@
+// Checks shared by the tests of several modules
+
+use crate::dual::Dual;
+use crate::material::Rng;
+use crate::packet::{Packet, Packet8};
+use crate::point::{Point3, Vector3};
+use crate::real::{Float, Real};
+use crate::DistanceField;
+
+// Pseudo-random coordinates in [-extent, extent], the same every run
+pub fn scatter<const D: usize>(count: usize, extent: Float) -> impl Iterator<Item = [Float; D]> {
+    let mut rng = Rng::new(D as u64);
+    (0..count).map(move |_| std::array::from_fn(|_| (rng.float() * 2.0 - 1.0) * extent))
+}
+
+// The same, as points in a cube
+pub fn scatter_points(count: usize, extent: Float) -> impl Iterator<Item = Point3> {
+    scatter(count, extent).map(|[x, y, z]| Point3::new(x, y, z))
+}
+
+// A field with its distances multiplied up, so it overestimates by exactly
+// its bound
+pub struct Steep<F>(pub F, pub Float);
+
+impl<F: DistanceField> DistanceField for Steep<F>
+{
+    fn distance<T: Real>(&self, p: &Point3<T>) -> T {
+        self.0.distance(p) * T::from_float(self.1)
+    }
+
+    fn lipschitz(&self) -> Float {
+        self.1 * self.0.lipschitz()
+    }
+}
+
+// A field gives the same distances a packet at a time as it does a point at a
+// time, and its dual number gradients match central differences, taken
+// finely enough for anything displaced by high octave noise
+pub fn assert_packet_and_gradient<F: DistanceField>(field: &F) {
+    let points: Vec<Point3> = scatter_points(8, 2.0).collect();
+    let d: Packet8 = field.distance(&Packet::gather(&points.clone().try_into().unwrap()));
+
+    let (h, tolerance) = if cfg!(feature = "f32") { (1e-3, 2e-2) } else { (1e-6, 1e-5) };
+    for (lane, p) in points.iter().enumerate() {
+        assert_eq!(field.distance(p), d[lane], "{:?}", p);
+
+        let exact = field.distance(&Dual::position(p)).grad;
+        let d = |o: Vector3| (field.distance(&(*p + o)) - field.distance(&(*p - o))) / (2.0 * h);
+        let approx = Vector3::new(d(Vector3::new(h, 0.0, 0.0)), d(Vector3::new(0.0, h, 0.0)), d(Vector3::new(0.0, 0.0, h)));
+        assert!((exact - approx).mag() < tolerance * (1.0 + exact.mag()), "{:?}: {:?} {:?}", p, exact, approx);
+    }
+}
@@ -0,0 +1,388 @@
+// Surface colour. Patterns and images are 2D, in texture coordinates (u, v)
+// that repeat every unit; a `Mapping` projects them onto a surface from the
+// hit position and normal. Solid textures, such as noise, colour a point in
+// space directly.
+
+use anyhow::{bail, Context, Result};
+
+use crate::noise::Noise;
+use crate::point::{Point3, Vector3};
+use crate::real::Float;
+
+// Linear RGB, each channel nominally in [0, 1]
+pub type Colour = Vector3;
+
+pub fn to_rgb(c: &Colour) -> image::Rgb<u8> {
+    let channel = |v: Float| (v.clamp(0.0, 1.0) * 255.0) as u8;
+    image::Rgb([channel(c.x), channel(c.y), channel(c.z)])
+}
+
+fn from_rgb(c: &image::Rgb<u8>) -> Colour {
+    Vector3::new(c[0] as Float, c[1] as Float, c[2] as Float).scale(1.0 / 255.0)
+}
+
+fn lerp(a: &Colour, b: &Colour, t: Float) -> Colour {
+    *a + (*b - *a).scale(t)
+}
+
+// The colour at a point on a surface
+pub trait Texture
+{
+    fn colour(&self, p: &Point3, normal: &Vector3) -> Colour;
+}
+
+// A plain colour
+impl Texture for Colour
+{
+    fn colour(&self, _: &Point3, _: &Vector3) -> Colour {
+        *self
+    }
+}
+
+// The colour at texture coordinates (u, v)
+pub trait Texture2
+{
+    fn sample(&self, u: Float, v: Float) -> Colour;
+}
+
+// Squares of `a` and `b`, half a unit across, so one of each per unit
+#[derive(Debug, Copy, Clone)]
+pub struct Checker
+{
+    pub a: Colour,
+    pub b: Colour,
+}
+
+impl Texture2 for Checker
+{
+    fn sample(&self, u: Float, v: Float) -> Colour {
+        let parity = ((u * 2.0).floor() + (v * 2.0).floor()).rem_euclid(2.0);
+        if parity < 1.0 { self.a } else { self.b }
+    }
+}
+
+// Bands across u, `a` for the first `ratio` of each unit and `b` for the rest
+#[derive(Debug, Copy, Clone)]
+pub struct Stripes
+{
+    pub a: Colour,
+    pub b: Colour,
+    pub ratio: Float,
+}
+
+impl Texture2 for Stripes
+{
+    fn sample(&self, u: Float, _: Float) -> Colour {
+        if u.rem_euclid(1.0) < self.ratio { self.a } else { self.b }
+    }
+}
+
+// Lines `width` wide (in units) along every whole u and v
+#[derive(Debug, Copy, Clone)]
+pub struct Grid
+{
+    pub line: Colour,
+    pub background: Colour,
+    pub width: Float,
+}
+
+impl Texture2 for Grid
+{
+    fn sample(&self, u: Float, v: Float) -> Colour {
+        // Distance to the nearest line in each direction
+        let near = |t: Float| Float::abs(t - t.round()) < self.width / 2.0;
+        if near(u) || near(v) { self.line } else { self.background }
+    }
+}
+
+// From `a` to `b` across each unit of u
+#[derive(Debug, Copy, Clone)]
+pub struct Gradient
+{
+    pub a: Colour,
+    pub b: Colour,
+}
+
+impl Texture2 for Gradient
+{
+    fn sample(&self, u: Float, _: Float) -> Colour {
+        lerp(&self.a, &self.b, u.rem_euclid(1.0))
+    }
+}
+
+// Noise remapped from [-1, 1] onto the colours `low` to `high`. A solid
+// texture as it is, or a 2D one through a mapping.
+#[derive(Debug, Copy, Clone)]
+pub struct NoiseTexture<N>
+{
+    pub noise: N,
+    pub low: Colour,
+    pub high: Colour,
+    pub frequency: Float,
+}
+
+impl<N: Noise> NoiseTexture<N>
+{
+    fn shade(&self, n: Float) -> Colour {
+        lerp(&self.low, &self.high, (n * 0.5 + 0.5).clamp(0.0, 1.0))
+    }
+}
+
+impl<N: Noise> Texture for NoiseTexture<N>
+{
+    fn colour(&self, p: &Point3, _: &Vector3) -> Colour {
+        let f = self.frequency;
+        self.shade(self.noise.get(&[p.x * f, p.y * f, p.z * f]))
+    }
+}
+
+impl<N: Noise> Texture2 for NoiseTexture<N>
+{
+    fn sample(&self, u: Float, v: Float) -> Colour {
+        self.shade(self.noise.get(&[u * self.frequency, v * self.frequency]))
+    }
+}
+
+// An image covering one unit of texture space and repeating, (0, 0) at the
+// bottom left. Pixel values are used as they are, with no gamma decoding.
+pub struct ImageTexture
+{
+    image: image::RgbImage,
+}
+
+impl ImageTexture
+{
+    pub fn new(image: image::RgbImage) -> Result<Self> {
+        if image.width() == 0 || image.height() == 0 {
+            bail!("Texture image is empty ({}x{})", image.width(), image.height());
+        }
+        Ok(ImageTexture { image })
+    }
+
+    pub fn open(path: &str) -> Result<Self> {
+        let image = image::open(path).with_context(|| format!("Can't load texture {}", path))?;
+        ImageTexture::new(image.to_rgb8()).with_context(|| format!("Can't use texture {}", path))
+    }
+
+    fn pixel(&self, x: i64, y: i64) -> Colour {
+        let (w, h) = (self.image.width() as i64, self.image.height() as i64);
+        // Flipped, as images start at the top
+        from_rgb(self.image.get_pixel(x.rem_euclid(w) as u32, (h - 1 - y.rem_euclid(h)) as u32))
+    }
+}
+
+impl Texture2 for ImageTexture
+{
+    // Bilinear, between pixel centres
+    fn sample(&self, u: Float, v: Float) -> Colour {
+        let x = u * self.image.width() as Float - 0.5;
+        let y = v * self.image.height() as Float - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+        let bottom = lerp(&self.pixel(x0, y0), &self.pixel(x0 + 1, y0), fx);
+        let top = lerp(&self.pixel(x0, y0 + 1), &self.pixel(x0 + 1, y0 + 1), fx);
+        lerp(&bottom, &top, fy)
+    }
+}
+
+// How a surface point becomes texture coordinates
+#[derive(Debug, Copy, Clone)]
+pub enum Mapping
+{
+    // (p . u, p . v): projected straight along u x v, the lengths of u and v
+    // setting the scale
+    Planar { u: Vector3, v: Vector3 },
+    // Longitude around y and latitude from the south pole, both in [0, 1]
+    Spherical { centre: Point3 },
+    // Around the vertical axis through `centre` in [0, 1], and up it in units
+    // of `height`
+    Cylindrical { centre: Point3, height: Float },
+    // Planar projections along x, y and z, blended by how much the normal
+    // faces each, so nothing is stretched. `scale` repeats per unit of
+    // space; higher `sharpness` narrows the blends.
+    Triplanar { scale: Float, sharpness: Float },
+}
+
+impl Mapping
+{
+    // Along x and z, one repeat per unit: for floors
+    pub fn ground() -> Self {
+        Mapping::Planar {
+            u: Vector3::new(1.0, 0.0, 0.0),
+            v: Vector3::new(0.0, 0.0, 1.0),
+        }
+    }
+
+    // Texture coordinates, for every mapping but triplanar
+    pub fn uv(&self, p: &Point3) -> Option<(Float, Float)> {
+        let tau = std::f64::consts::TAU as Float;
+        match self {
+            Mapping::Planar { u, v } => Some((p.to_vector().dot_product(u), p.to_vector().dot_product(v))),
+            Mapping::Spherical { centre } => {
+                let d = (*p - *centre).normalized();
+                let v = d.y.clamp(-1.0, 1.0).acos() / (tau / 2.0);
+                Some((d.z.atan2(d.x) / tau + 0.5, 1.0 - v))
+            }
+            Mapping::Cylindrical { centre, height } => {
+                let d = *p - *centre;
+                Some((d.z.atan2(d.x) / tau + 0.5, d.y / height))
+            }
+            Mapping::Triplanar { .. } => None,
+        }
+    }
+
+    pub fn sample<T: Texture2>(&self, texture: &T, p: &Point3, normal: &Vector3) -> Colour {
+        match self {
+            Mapping::Triplanar { scale, sharpness } => {
+                let q = p.to_vector().scale(*scale);
+                let w = normal.abs();
+                let w = Vector3::new(w.x.powf(*sharpness), w.y.powf(*sharpness), w.z.powf(*sharpness));
+                let w = w.scale(1.0 / (w.x + w.y + w.z));
+                texture.sample(q.z, q.y).scale(w.x) + texture.sample(q.x, q.z).scale(w.y) + texture.sample(q.x, q.y).scale(w.z)
+            }
+            _ => {
+                let (u, v) = self.uv(p).unwrap();
+                texture.sample(u, v)
+            }
+        }
+    }
+}
+
+// A 2D texture put on a surface by a mapping
+pub struct Mapped<T>
+{
+    pub texture: T,
+    pub mapping: Mapping,
+}
+
+impl<T: Texture2> Texture for Mapped<T>
+{
+    fn colour(&self, p: &Point3, normal: &Vector3) -> Colour {
+        self.mapping.sample(&self.texture, p, normal)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::noise::Perlin;
+
+    const BLACK: Colour = Vector3 { x: 0.0, y: 0.0, z: 0.0 };
+    const WHITE: Colour = Vector3 { x: 1.0, y: 1.0, z: 1.0 };
+
+    fn near(a: &Colour, b: &Colour) -> bool {
+        (*a - *b).mag() < 1e-5
+    }
+
+    #[test]
+    fn patterns() {
+        let checker = Checker { a: BLACK, b: WHITE };
+        assert_eq!(BLACK, checker.sample(0.25, 0.25));
+        assert_eq!(WHITE, checker.sample(0.75, 0.25));
+        assert_eq!(BLACK, checker.sample(0.75, 0.75));
+        // Carries on through zero
+        assert_eq!(WHITE, checker.sample(-0.25, 0.25));
+        assert_eq!(BLACK, checker.sample(-0.25, -0.25));
+
+        let stripes = Stripes { a: BLACK, b: WHITE, ratio: 0.25 };
+        assert_eq!(BLACK, stripes.sample(0.1, 7.0));
+        assert_eq!(WHITE, stripes.sample(0.3, 7.0));
+        assert_eq!(BLACK, stripes.sample(-0.9, 0.0));
+
+        let grid = Grid { line: BLACK, background: WHITE, width: 0.1 };
+        assert_eq!(BLACK, grid.sample(2.02, 0.5));
+        assert_eq!(BLACK, grid.sample(0.5, -0.97));
+        assert_eq!(WHITE, grid.sample(0.5, 0.5));
+
+        let gradient = Gradient { a: BLACK, b: WHITE };
+        assert!(near(&WHITE.scale(0.25), &gradient.sample(3.25, 0.0)));
+    }
+
+    #[test]
+    fn noise() {
+        let t = NoiseTexture { noise: Perlin::new(1), low: BLACK, high: WHITE, frequency: 4.0 };
+        // Gradient noise is 0 on the lattice, the middle of the range
+        assert!(near(&WHITE.scale(0.5), &t.colour(&Point3::new(0.25, 0.5, -1.0), &WHITE)));
+        assert!(near(&WHITE.scale(0.5), &t.sample(0.25, 0.5)));
+        for i in 0..100 {
+            let c = t.colour(&Point3::new(i as Float * 0.37, 1.1, i as Float * -0.21), &WHITE);
+            assert!(c.x >= 0.0 && c.x <= 1.0);
+        }
+    }
+
+    fn test_image() -> image::RgbImage {
+        // 2x2: red, green along the bottom; blue, white along the top
+        let mut image = image::RgbImage::new(2, 2);
+        image.put_pixel(0, 1, image::Rgb([255, 0, 0]));
+        image.put_pixel(1, 1, image::Rgb([0, 255, 0]));
+        image.put_pixel(0, 0, image::Rgb([0, 0, 255]));
+        image.put_pixel(1, 0, image::Rgb([255, 255, 255]));
+        image
+    }
+
+    #[test]
+    fn images() {
+        let t = ImageTexture::new(test_image()).unwrap();
+        assert!(near(&Vector3::new(1.0, 0.0, 0.0), &t.sample(0.25, 0.25)));
+        assert!(near(&Vector3::new(0.0, 1.0, 0.0), &t.sample(0.75, 0.25)));
+        assert!(near(&Vector3::new(0.0, 0.0, 1.0), &t.sample(0.25, 0.75)));
+        // Halfway between red and green, and wrapping round to red again
+        assert!(near(&Vector3::new(0.5, 0.5, 0.0), &t.sample(0.5, 0.25)));
+        assert!(near(&Vector3::new(0.5, 0.5, 0.0), &t.sample(1.0, 0.25)));
+        assert!(near(&t.sample(0.3, 0.6), &t.sample(-1.7, 3.6)));
+
+        let path = std::env::temp_dir().join(format!("sdf-rs-texture-{}.png", std::process::id()));
+        test_image().save(&path).unwrap();
+        let loaded = ImageTexture::open(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!(near(&t.sample(0.4, 0.1), &loaded.sample(0.4, 0.1)));
+        assert!(ImageTexture::open("no/such/texture.png").is_err());
+        assert!(ImageTexture::new(image::RgbImage::new(0, 0)).is_err());
+        assert!(ImageTexture::new(image::RgbImage::new(4, 0)).is_err());
+    }
+
+    #[test]
+    fn mappings() {
+        let p = Point3::new(1.5, 2.0, -0.25);
+        assert_eq!(Some((1.5, -0.25)), Mapping::ground().uv(&p));
+
+        let sphere = Mapping::Spherical { centre: Point3::new(0.0, 1.0, 0.0) };
+        let (_, v) = sphere.uv(&Point3::new(0.0, 3.0, 0.0)).unwrap();
+        assert_eq!(1.0, v);
+        let (u, v) = sphere.uv(&Point3::new(-1.0, 1.0, 0.0)).unwrap();
+        assert!(Float::abs(v - 0.5) < 1e-6 && Float::abs(u - 1.0) < 1e-6);
+        let (u, _) = sphere.uv(&Point3::new(1.0, 1.0, 0.0)).unwrap();
+        assert!(Float::abs(u - 0.5) < 1e-6);
+
+        let cylinder = Mapping::Cylindrical { centre: Point3::origin(), height: 4.0 };
+        let (u, v) = cylinder.uv(&Point3::new(0.0, 2.0, 3.0)).unwrap();
+        assert!(Float::abs(u - 0.75) < 1e-6 && Float::abs(v - 0.5) < 1e-6);
+        assert_eq!(None, Mapping::Triplanar { scale: 1.0, sharpness: 4.0 }.uv(&p));
+    }
+
+    #[test]
+    fn triplanar() {
+        let gradient = Gradient { a: BLACK, b: WHITE };
+        let m = Mapping::Triplanar { scale: 1.0, sharpness: 4.0 };
+        let p = Point3::new(0.25, 0.5, 0.75);
+        // Facing straight along an axis it's the planar projection along it
+        assert!(near(&gradient.sample(0.75, 0.5), &m.sample(&gradient, &p, &Vector3::new(1.0, 0.0, 0.0))));
+        assert!(near(&gradient.sample(0.25, 0.75), &m.sample(&gradient, &p, &Vector3::new(0.0, -1.0, 0.0))));
+        assert!(near(&gradient.sample(0.25, 0.5), &m.sample(&gradient, &p, &Vector3::new(0.0, 0.0, 1.0))));
+        // At 45 degrees it's an even blend of two
+        let n = Vector3::new(1.0, 1.0, 0.0).normalized();
+        let blend = (gradient.sample(0.75, 0.5) + gradient.sample(0.25, 0.75)).scale(0.5);
+        assert!(near(&blend, &m.sample(&gradient, &p, &n)));
+
+        let mapped = Mapped { texture: gradient, mapping: m };
+        assert!(near(&blend, &mapped.colour(&p, &n)));
+    }
+
+    #[test]
+    fn rgb() {
+        assert_eq!(image::Rgb([255, 0, 127]), to_rgb(&Vector3::new(2.0, -1.0, 0.5)));
+        assert_eq!(WHITE, from_rgb(&image::Rgb([255, 255, 255])));
+        assert_eq!(WHITE, WHITE.colour(&Point3::origin(), &WHITE));
+    }
+}
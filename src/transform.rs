@@ -0,0 +1,188 @@
+use anyhow::{anyhow, Result};
+
+use crate::matrix::Mat4;
+use crate::point::{Point3, Vector3};
+use crate::real::{Float, Real};
+use crate::{transform, DistanceField};
+
+// A node placed in the world by a matrix. Queries are taken back into the
+// node's own space by the inverse, which is worked out once up front.
+//
+// Distances measured in object space are in object units, so they're scaled
+// back up: exactly by the scale factor for rigid motions and uniform scales,
+// and for anything else (non-uniform scale, shear) by a lower bound on how
+// much the transform can shrink a distance, so marching stays safe but takes
+// more steps. `is_exact` says which.
+#[derive(Debug, Copy, Clone)]
+pub struct Transform<N>
+{
+    node: N,
+    matrix: Mat4,
+    inverse: Mat4,
+    scale: Float,
+    exact: bool,
+}
+
+impl<N: DistanceField> Transform<N>
+{
+    pub fn new(node: N, matrix: &Mat4) -> Result<Self> {
+        let inverse = matrix.inverse().ok_or_else(|| anyhow!("Transform can't be inverted:\n{}", matrix))?;
+        let (scale, exact) = distance_scale(matrix, &inverse);
+        Ok(Transform {
+            node,
+            matrix: *matrix,
+            inverse,
+            scale,
+            exact,
+        })
+    }
+
+    pub fn translate(node: N, offset: &Vector3) -> Self {
+        Transform::new(node, &Mat4::translation(&(*offset).into())).unwrap()
+    }
+
+    pub fn matrix(&self) -> &Mat4 {
+        &self.matrix
+    }
+
+    pub fn inverse(&self) -> &Mat4 {
+        &self.inverse
+    }
+
+    // True if distances are exact, false if they're only a lower bound
+    pub fn is_exact(&self) -> bool {
+        self.exact
+    }
+}
+
+impl<N: DistanceField> DistanceField for Transform<N>
+{
+    fn distance<T: Real>(&self, p: &Point3<T>) -> T {
+        self.node.distance(&transform(p, &self.inverse)) * T::from_float(self.scale)
+    }
+
+    // The scale never makes distances change faster than the child's
+    fn lipschitz(&self) -> Float {
+        self.node.lipschitz()
+    }
+}
+
+// How much object space distances are multiplied by in world space, and
+// whether that's exact. With A the linear part of the transform, a uniform
+// scale has AᵀA = s²I and scales every distance by s. Otherwise distances
+// shrink by at most the smallest singular value of A, which is
+// 1 / ‖A⁻¹‖₂ and so at least 1 / ‖A⁻¹‖ in the Frobenius norm.
+fn distance_scale(matrix: &Mat4, inverse: &Mat4) -> (Float, bool) {
+    let column = |m: &Mat4, x: usize| Vector3::new(m[(x, 0)], m[(x, 1)], m[(x, 2)]);
+    let a = [0, 1, 2].map(|x| column(matrix, x));
+
+    let s2 = a[0].dot_product(&a[0]);
+    let tolerance = 1e-5 * s2;
+    let uniform = (0..3).all(|i| {
+        (0..3).all(|j| {
+            let expected = if i == j { s2 } else { 0.0 };
+            Float::abs(a[i].dot_product(&a[j]) - expected) < tolerance
+        })
+    });
+
+    if uniform {
+        (s2.sqrt(), true)
+    } else {
+        let frobenius: Float = (0..3).map(|x| column(inverse, x).dot_product(&column(inverse, x))).sum();
+        (1.0 / frobenius.sqrt(), false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::node::{Cuboid, Sphere};
+    use crate::normal::{calc_normal, NormalMethod};
+    use crate::vector::Vec4;
+
+    const QUARTER: Float = std::f64::consts::FRAC_PI_2 as Float;
+
+    fn near(a: Float, b: Float) -> bool {
+        Float::abs(a - b) < 1e-5
+    }
+
+    #[test]
+    fn translate() {
+        let s = Transform::translate(Sphere { radius: 1.0 }, &Vector3::new(3.0, 0.0, 0.0));
+        assert!(s.is_exact());
+        assert_eq!(0.0, s.distance(&Point3::new(4.0, 0.0, 0.0)));
+        assert_eq!(-1.0, s.distance(&Point3::new(3.0, 0.0, 0.0)));
+    }
+
+    #[test]
+    fn rotate() {
+        // A box long in x, stood on end
+        let b = Cuboid { dimensions: Vector3::new(1.0, 0.25, 0.25) };
+        let r = b.transformed(&Mat4::rotation_z(QUARTER)).unwrap();
+        assert!(r.is_exact());
+        assert!(near(b.distance(&Point3::new(0.9, 0.0, 0.0)), r.distance(&Point3::new(0.0, 0.9, 0.0))));
+        assert!(r.distance(&Point3::new(0.9, 0.0, 0.0)) > 0.0);
+
+        // Normals come out rotated too
+        let n = calc_normal(&r, &Point3::new(0.0, 1.1, 0.0), 1.0, NormalMethod::AutoDiff);
+        assert!((n - Vector3::new(0.0, 1.0, 0.0)).mag() < 1e-5);
+    }
+
+    #[test]
+    fn uniform_scale() {
+        let m: Mat4 = Mat4::scale(&Vec4::direction(2.0, 2.0, 2.0))
+            .then(&Mat4::rotation_y(0.3))
+            .then(&Mat4::translation(&Vec4::direction(1.0, 0.0, 0.0)));
+        let s = Sphere { radius: 1.0 }.transformed(&m).unwrap();
+        assert!(s.is_exact());
+        assert!(near(3.0, s.distance(&Point3::new(6.0, 0.0, 0.0))));
+        assert!(near(-2.0, s.distance(&Point3::new(1.0, 0.0, 0.0))));
+    }
+
+    #[test]
+    fn non_uniform_scale_is_a_bound() {
+        // An ellipsoid with semi-axes 2, 1, 1
+        let m: Mat4 = Mat4::scale(&Vec4::direction(2.0, 1.0, 1.0));
+        let e = Sphere { radius: 1.0 }.transformed(&m).unwrap();
+        assert!(!e.is_exact());
+
+        // Never more than the true distance along the axes...
+        assert!(e.distance(&Point3::new(3.0, 0.0, 0.0)) <= 1.0);
+        assert!(e.distance(&Point3::new(0.0, 3.0, 0.0)) <= 2.0);
+        assert!(e.distance(&Point3::new(3.0, 0.0, 0.0)) > 0.0);
+        assert!(e.distance(&Point3::new(1.5, 0.0, 0.0)) < 0.0);
+
+        // ...and never changes faster than the distance between points
+        let points: Vec<Point3> = (0..200)
+            .map(|i| {
+                let i = i as Float;
+                Point3::new((i * 0.37).sin() * 4.0, (i * 0.91).cos() * 3.0, (i * 0.13).sin() * 2.0)
+            })
+            .collect();
+        for p in &points {
+            for q in &points {
+                let dd = Float::abs(e.distance(p) - e.distance(q));
+                assert!(dd <= (*p - *q).mag() + 1e-9);
+            }
+        }
+
+        // Shear is treated the same way
+        let sheared = Sphere { radius: 1.0 }.transformed(&Mat4::shear(1.0, 0.0, 0.0, 0.0, 0.0, 0.0)).unwrap();
+        assert!(!sheared.is_exact());
+    }
+
+    #[test]
+    fn singular() {
+        let m: Mat4 = Mat4::scale(&Vec4::direction(1.0, 0.0, 1.0));
+        assert!(Sphere { radius: 1.0 }.transformed(&m).is_err());
+    }
+
+    #[test]
+    fn nested() {
+        let inner = Transform::translate(Sphere { radius: 0.5 }, &Vector3::new(1.0, 0.0, 0.0));
+        let outer = inner.transformed(&Mat4::rotation_y(QUARTER)).unwrap();
+        // (1, 0, 0) turned a quarter about y ends up at (0, 0, -1)
+        assert!(near(-0.5, outer.distance(&Point3::new(0.0, 0.0, -1.0))));
+        assert_eq!(outer.matrix().inverse().unwrap(), *outer.inverse());
+    }
+}
@@ -1,81 +1,114 @@
-use std::ops::{Add, Index, IndexMut, Sub};
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Div, DivAssign, Index, IndexMut, Mul, MulAssign, Neg, Sub, SubAssign};
 
-pub type Float = f64;
+use crate::real::{cast, Float, Real};
 
+// Generic over the scalar so distance functions can be evaluated on dual
+// numbers as well as plain floats; `Vec4` on its own means `Vec4<Float>`
 #[derive(Debug, PartialEq, Copy, Clone)]
-pub struct Vec4
+pub struct Vec4<T = Float>
 {
-    pub x: Float,
-    pub y: Float,
-    pub z: Float,
-    pub w: Float,
+    pub x: T,
+    pub y: T,
+    pub z: T,
+    pub w: T,
 }
 
 impl Vec4
 {
-    pub fn position(x: Float, y: Float, z: Float) -> Vec4 {
+    // Convert to another scalar type, as a constant
+    pub fn lift<T: Real>(&self) -> Vec4<T> {
+        self.cast()
+    }
+}
+
+impl<T: Real> Vec4<T>
+{
+    pub fn position(x: T, y: T, z: T) -> Vec4<T> {
         Vec4 {
             x,
             y,
             z,
-            w: 1.0,
+            w: T::from_float(1.0),
         }
     }
 
-    pub fn direction(x: Float, y: Float, z: Float) -> Vec4 {
+    pub fn direction(x: T, y: T, z: T) -> Vec4<T> {
         Vec4 {
             x,
             y,
             z,
-            w: 0.0,
+            w: T::from_float(0.0),
         }
     }
     
-    pub fn as_direction(&self) -> Vec4 {
+    pub fn as_direction(&self) -> Vec4<T> {
         Vec4 {
             x: self.x,
             y: self.y,
             z: self.z,
-            w: 0.0,
+            w: T::from_float(0.0),
         }
     }
 
-    pub fn dot_product(&self, other: &Vec4) -> Float {
-        self.x * other.x + self.y * other.y + self.z * other.z
+    // Drop down to plain floats, discarding any derivatives
+    pub fn value(&self) -> Vec4 {
+        self.cast()
     }
 
-    pub fn cross_product(&self, other: &Vec4) -> Vec4 {
+    pub fn cast<U: Real>(&self) -> Vec4<U> {
         Vec4 {
-            x: (self.y * other.z) - (self.z * other.y),
-            y: (self.z * other.x) - (self.x * other.z),
-            z: (self.x * other.y) - (self.y * other.x),
-            w: 0.0,
+            x: cast(self.x),
+            y: cast(self.y),
+            z: cast(self.z),
+            w: cast(self.w),
         }
     }
 
-    pub fn abs(&self) -> Vec4 {
+    pub fn to_array(&self) -> [T; 4] {
+        [self.x, self.y, self.z, self.w]
+    }
+
+    pub fn from_array(a: [T; 4]) -> Vec4<T> {
+        Vec4 { x: a[0], y: a[1], z: a[2], w: a[3] }
+    }
+
+    fn with_w(self, w: T) -> Vec4<T> {
+        Vec4 { w, ..self }
+    }
+
+    pub fn dot_product(&self, other: &Vec4<T>) -> T {
+        let p = T::mul4(self.to_array(), other.to_array());
+        p[0] + p[1] + p[2]
+    }
+
+    pub fn cross_product(&self, other: &Vec4<T>) -> Vec4<T> {
         Vec4 {
-            x: Float::abs(self.x),
-            y: Float::abs(self.y),
-            z: Float::abs(self.z),
-            w: Float::abs(self.w)
+            x: (self.y * other.z) - (self.z * other.y),
+            y: (self.z * other.x) - (self.x * other.z),
+            z: (self.x * other.y) - (self.y * other.x),
+            w: T::from_float(0.0),
         }
     }
 
-    pub fn scale(&self, other: Float) -> Vec4 {
+    pub fn abs(&self) -> Vec4<T> {
         Vec4 {
-            x: self.x * other,
-            y: self.y * other,
-            z: self.z * other,
+            x: self.x.abs(),
+            y: self.y.abs(),
+            z: self.z.abs(),
             w: self.w,
         }
     }
 
-    pub fn mag(&self) -> Float {
-        Float::sqrt(self.dot_product(self))
+    pub fn scale(&self, other: T) -> Vec4<T> {
+        Vec4::from_array(T::mul4(self.to_array(), [other; 4])).with_w(self.w)
     }
 
-    pub fn normalized(&self) -> Vec4 {
+    pub fn mag(&self) -> T {
+        self.dot_product(self).sqrt()
+    }
+
+    pub fn normalized(&self) -> Vec4<T> {
         let mag = self.mag();
         Vec4 {
             x: self.x / mag,
@@ -85,7 +118,7 @@ impl Vec4
         }
     }
 
-    pub fn reverse(&self) -> Vec4 {
+    pub fn reverse(&self) -> Vec4<T> {
         Vec4 {
             x: -self.x,
             y: -self.y,
@@ -95,39 +128,121 @@ impl Vec4
     }
 }
 
-impl Add for &Vec4
+// Adding and subtracting work on w too, as homogeneous coordinates should:
+// position - position is a direction and position +- direction a position,
+// whichever way round. Everything else is component-wise on x, y and z and
+// leaves w alone.
+impl<T: Real> Add for Vec4<T>
+{
+    type Output = Vec4<T>;
+
+    fn add(self, other: Vec4<T>) -> Vec4<T> {
+        Vec4::from_array(T::add4(self.to_array(), other.to_array()))
+    }
+}
+
+impl<T: Real> Sub for Vec4<T>
+{
+    type Output = Vec4<T>;
+
+    fn sub(self, other: Vec4<T>) -> Vec4<T> {
+        Vec4::from_array(T::sub4(self.to_array(), other.to_array()))
+    }
+}
+
+impl<T: Real> Mul for Vec4<T>
 {
-    type Output = Vec4;
+    type Output = Vec4<T>;
 
-    fn add(self, other: &Vec4) -> Vec4 {
+    fn mul(self, other: Vec4<T>) -> Vec4<T> {
+        Vec4::from_array(T::mul4(self.to_array(), other.to_array())).with_w(self.w)
+    }
+}
+
+impl<T: Real> Div for Vec4<T>
+{
+    type Output = Vec4<T>;
+
+    fn div(self, other: Vec4<T>) -> Vec4<T> {
         Vec4 {
-            x: self.x + other.x,
-            y: self.y + other.y,
-            z: self.z + other.z,
+            x: self.x / other.x,
+            y: self.y / other.y,
+            z: self.z / other.z,
             w: self.w,
         }
     }
 }
 
-impl Sub for &Vec4
+impl<T: Real> Mul<T> for Vec4<T>
 {
-    type Output = Vec4;
+    type Output = Vec4<T>;
 
-    fn sub(self, other: &Vec4) -> Vec4 {
-        Vec4 {
-            x: self.x - other.x,
-            y: self.y - other.y,
-            z: self.z - other.z,
-            w: 0.0,
-        }
+    fn mul(self, other: T) -> Vec4<T> {
+        self.scale(other)
+    }
+}
+
+impl<T: Real> Div<T> for Vec4<T>
+{
+    type Output = Vec4<T>;
+
+    fn div(self, other: T) -> Vec4<T> {
+        self.scale(T::from_float(1.0) / other)
+    }
+}
+
+impl<T: Real> Neg for Vec4<T>
+{
+    type Output = Vec4<T>;
+
+    fn neg(self) -> Vec4<T> {
+        self.reverse()
     }
 }
 
-impl Index<usize> for Vec4
+impl<T: Real> Neg for &Vec4<T>
 {
-    type Output = Float;
+    type Output = Vec4<T>;
+
+    fn neg(self) -> Vec4<T> {
+        self.reverse()
+    }
+}
+
+impl<T: Real> Sum for Vec4<T>
+{
+    fn sum<I: Iterator<Item = Vec4<T>>>(iter: I) -> Vec4<T> {
+        let zero = T::from_float(0.0);
+        iter.fold(Vec4::direction(zero, zero, zero), |a, b| a + b)
+    }
+}
 
-    fn index(&self, index: usize) -> &Float {
+impl<'a, T: Real> Sum<&'a Vec4<T>> for Vec4<T>
+{
+    fn sum<I: Iterator<Item = &'a Vec4<T>>>(iter: I) -> Vec4<T> {
+        iter.copied().sum()
+    }
+}
+
+forward_ref_binop!(Add, add, Vec4<T>, Vec4<T>);
+forward_ref_binop!(Sub, sub, Vec4<T>, Vec4<T>);
+forward_ref_binop!(Mul, mul, Vec4<T>, Vec4<T>);
+forward_ref_binop!(Div, div, Vec4<T>, Vec4<T>);
+forward_ref_binop!(Mul, mul, Vec4<T>, T);
+forward_ref_binop!(Div, div, Vec4<T>, T);
+assign_op!(AddAssign, add_assign, Add, add, Vec4<T>, Vec4<T>);
+assign_op!(SubAssign, sub_assign, Sub, sub, Vec4<T>, Vec4<T>);
+assign_op!(MulAssign, mul_assign, Mul, mul, Vec4<T>, Vec4<T>);
+assign_op!(DivAssign, div_assign, Div, div, Vec4<T>, Vec4<T>);
+assign_op!(MulAssign, mul_assign, Mul, mul, Vec4<T>, T);
+assign_op!(DivAssign, div_assign, Div, div, Vec4<T>, T);
+scalar_mul!(Vec4, f32, f64);
+
+impl<T> Index<usize> for Vec4<T>
+{
+    type Output = T;
+
+    fn index(&self, index: usize) -> &T {
         match index {
             0 => &self.x,
             1 => &self.y,
@@ -138,9 +253,9 @@ impl Index<usize> for Vec4
     }
 }
 
-impl IndexMut<usize> for Vec4
+impl<T> IndexMut<usize> for Vec4<T>
 {
-    fn index_mut(&mut self, index: usize) -> &mut Float {
+    fn index_mut(&mut self, index: usize) -> &mut T {
         match index {
             0 => &mut self.x,
             1 => &mut self.y,
@@ -163,34 +278,56 @@ mod tests {
 
     #[test]
     fn add_position() {
+        // Not a position any more, but twice one
         let u = Vec4::position(1.0, 2.0, 3.0);
         let v = Vec4::position(-1.0, -2.0, -3.0);
-        assert_eq!(Vec4::position(2.0, 4.0, 6.0), &u + &u);
-        assert_eq!(Vec4::position(0.0, 0.0, 0.0), &u + &v);
+        assert_eq!(Vec4 { x: 2.0, y: 4.0, z: 6.0, w: 2.0 }, u + u);
+        assert_eq!(Vec4 { x: 0.0, y: 0.0, z: 0.0, w: 2.0 }, u + v);
     }
 
     #[test]
     fn add_direction() {
         let u = Vec4::direction(1.0, 2.0, 3.0);
         let v = Vec4::direction(-1.0, -2.0, -3.0);
-        assert_eq!(Vec4::direction(2.0, 4.0, 6.0), &u + &u);
-        assert_eq!(Vec4::direction(0.0, 0.0, 0.0), &u + &v);
+        assert_eq!(Vec4::direction(2.0, 4.0, 6.0), u + u);
+        assert_eq!(Vec4::direction(0.0, 0.0, 0.0), u + v);
     }
 
     #[test]
     fn add_direction_to_position() {
         let u = Vec4::position(1.0, 2.0, 3.0);
         let v = Vec4::direction(-1.0, -2.0, -3.0);
-        assert_eq!(Vec4::position(2.0, 4.0, 6.0), &u + &u);
-        assert_eq!(Vec4::position(0.0, 0.0, 0.0), &u + &v);
+        assert_eq!(Vec4::position(0.0, 0.0, 0.0), u + v);
+        assert_eq!(u + v, v + u);
     }
 
     #[test]
     fn sub() {
         let u = Vec4::position(1.0, 2.0, 3.0);
         let v = Vec4::position(-1.0, -2.0, -3.0);
-        assert_eq!(Vec4::direction(2.0, 4.0, 6.0), &u - &v);
-        assert_eq!(Vec4::direction(0.0, 0.0, 0.0), &u - &u);
+        let d = Vec4::direction(1.0, 1.0, 1.0);
+        assert_eq!(Vec4::direction(2.0, 4.0, 6.0), u - v);
+        assert_eq!(Vec4::direction(0.0, 0.0, 0.0), u - u);
+        assert_eq!(Vec4::position(0.0, 1.0, 2.0), u - d);
+        assert_eq!(Vec4::direction(0.0, 0.0, 0.0), d - d);
+        // However it's put together
+        assert_eq!(u - d, u + -d);
+        assert_eq!((u - v).abs(), (v - u).abs());
+    }
+
+    #[test]
+    fn componentwise_ops_keep_w() {
+        let u = Vec4::position(-1.0, 2.0, -3.0);
+        let d = Vec4::direction(-1.0, 2.0, -3.0);
+        for v in [u, d] {
+            assert_eq!(v.w, v.abs().w);
+            assert_eq!(v.w, v.scale(-2.0).w);
+            assert_eq!(v.w, (v * d).w);
+            assert_eq!(v.w, (v / d).w);
+            assert_eq!(v.w, (-v).w);
+            assert_eq!(v.w, v.normalized().w);
+        }
+        assert_eq!(Vec4::position(1.0, 2.0, 3.0), u.abs());
     }
 
     #[test]
@@ -254,6 +391,42 @@ mod tests {
         assert_eq!(3.0, u[2]);
     }
 
+    #[test]
+    #[allow(clippy::op_ref)]
+    fn operators() {
+        let u = Vec4::position(1.0, 2.0, 3.0);
+        let v = Vec4::direction(2.0, 2.0, 2.0);
+
+        assert_eq!(&u + &v, u + v);
+        assert_eq!(Vec4::position(3.0, 4.0, 5.0), u + v);
+        assert_eq!(Vec4::position(-1.0, 0.0, 1.0), u - v);
+        assert_eq!(u.scale(2.0), u * 2.0);
+        assert_eq!(u.scale(2.0), 2.0 * u);
+        assert_eq!(u * 2.0, u * v);
+        assert_eq!(u.scale(0.5), u / 2.0);
+        assert_eq!(u / 2.0, u / v);
+        assert_eq!(u.reverse(), -u);
+
+        let mut w = v;
+        w += v;
+        w *= 0.5;
+        w /= v;
+        w -= Vec4::direction(1.0, 1.0, 1.0);
+        assert_eq!(Vec4::direction(0.0, 0.0, 0.0), w);
+
+        assert_eq!(Vec4::direction(3.0, 4.0, 5.0), [u.as_direction(), v].iter().sum());
+    }
+
+    #[test]
+    fn precision() {
+        let u: Vec4<f32> = Vec4::position(1.0, 2.0, 2.0);
+        let v: Vec4<f64> = u.cast();
+        assert_eq!(3.0f32, u.mag());
+        assert_eq!(3.0f64, v.mag());
+        assert_eq!(1.0f64, v.w);
+        assert_eq!(u, v.cast());
+    }
+
     #[test]
     fn index_rw() {
         let mut u = Vec4::position(1.0, 2.0, 3.0);